@@ -1,4 +1,4 @@
-use color::consts::CIE_COLOR_MATCH;
+use color::gamut::spectrum_locus;
 use color::{Color, XYChroma, XYZ};
 use png::Encoder;
 use std::io::Write;
@@ -83,12 +83,12 @@ fn generate_plot(data_path: &Path) -> std::io::Result<()> {
     let script_path = Path::new("locus.gnuplot");
     let mut scriptw = std::io::LineWriter::new(File::create(script_path)?);
     let mut w = csv::WriterBuilder::default().from_path(locus_path)?;
-    for (xy, lambda) in generate_spectrum_locus().iter().zip((380..780).step_by(5)) {
-        w.write_record(&[lambda.to_string(), xy.x.to_string(), xy.y.to_string()])?;
+    for xy in spectrum_locus() {
+        w.write_record(&[xy.x.to_string(), xy.y.to_string()])?;
     }
     w.flush()?;
 
-    let script = format!("set terminal png size 1000,1000\nset output \"locus.png\"\nset grid\nset xrange [0:1]\nset yrange [0:1]\nset xlabel \"x\"\nset ylabel \"y\"\nset title \"Generated plankian locus\"\nset datafile separator \",\"\nplot \"{}\" using 5:6 with lines title \"Plankian locus\", \"{}\" using 2:3 with lines title \"Spectral locus\"\n", data_path.to_string_lossy(), locus_path.to_string_lossy());
+    let script = format!("set terminal png size 1000,1000\nset output \"locus.png\"\nset grid\nset xrange [0:1]\nset yrange [0:1]\nset xlabel \"x\"\nset ylabel \"y\"\nset title \"Generated plankian locus\"\nset datafile separator \",\"\nplot \"{}\" using 5:6 with lines title \"Plankian locus\", \"{}\" using 1:2 with lines title \"Spectral locus\"\n", data_path.to_string_lossy(), locus_path.to_string_lossy());
 
     scriptw.write(script.as_ref())?;
     drop(scriptw);
@@ -99,13 +99,6 @@ fn generate_plot(data_path: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
-fn generate_spectrum_locus() -> Vec<XYChroma> {
-    CIE_COLOR_MATCH
-        .iter()
-        .map(|a| XYZ::from(*a).to_chromaticity().0)
-        .collect()
-}
-
 fn lin_space(start: f64, end: f64, length: usize) -> Vec<f64> {
     let range = end - start;
     let step = range / (length as f64);
@@ -1,9 +1,20 @@
 use crate::gamut::ColorSystem;
-use crate::gamut::SYSTEM_SRGB;
+use crate::gamut::{ILLUMINANT_D65, SYSTEM_SRGB};
+use crate::lab::Lab;
 use crate::xyz::XYZ;
-use nalgebra::{Vector3, Vector4};
+use image::{DynamicImage, Rgb, Rgba};
+use nalgebra::{Point3, Vector3, Vector4};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::f64;
 use std::fmt;
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// Wavelengths, in nm, at which the red, green and blue channels sample the spectrum in
+/// `redshift`
+const CHANNEL_WAVELENGTHS: [f64; 3] = [610.0, 540.0, 450.0];
+/// Bounds of the visible spectrum, in nm
+const VISIBLE_RANGE: (f64, f64) = (380.0, 700.0);
 
 /** Linear RGB Color structure */
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -93,6 +104,152 @@ impl Color {
 			.fold(0.0f64, |p, c| if p.abs() < c.abs() { *c } else { p });
 		return self.replace_rgb(self.red / max, self.green / max, self.blue / max);
 	}
+	/// Clamps all four channels to `[min, max]`, keeping the color system.
+	pub fn clamp_channels(&self, min: f64, max: f64) -> Self {
+		Color {
+			red: clamp(self.red, min, max),
+			green: clamp(self.green, min, max),
+			blue: clamp(self.blue, min, max),
+			alpha: clamp(self.alpha, min, max),
+			system: self.system,
+		}
+	}
+	/// Clamps all four channels to `[0, 1]`.
+	pub fn to_unit_range(&self) -> Self {
+		self.clamp_channels(0.0, 1.0)
+	}
+	/// Complementary color (`1 - channel` on the stored values), keeping alpha and the system.
+	pub fn invert(&self) -> Self {
+		self.replace_rgb(1.0 - self.red, 1.0 - self.green, 1.0 - self.blue)
+	}
+	/// Compares colors by their relative luminance, computed in linear light with the sRGB weights.
+	pub fn luminance_cmp(&self, other: &Color) -> Ordering {
+		self.to_grayscale()
+			.partial_cmp(&other.to_grayscale())
+			.unwrap_or(Ordering::Equal)
+	}
+	/// Relative luminance (CIE Y) of the color, decoded to linear light and weighted with the
+	/// sRGB coefficients. Colors without a system are taken as linear already.
+	pub fn to_grayscale(&self) -> f64 {
+		let lin: Vector3<f64> = self.clone().into();
+		0.2126 * lin.x + 0.7152 * lin.y + 0.0722 * lin.z
+	}
+	/// CIE76 color difference, the euclidean distance between both colors in L*a*b*. The colors
+	/// are decoded with the given system.
+	pub fn delta_e_76(&self, other: &Color, system: &ColorSystem) -> f64 {
+		self.to_lab(system).delta_e_76(&other.to_lab(system))
+	}
+	/// CIEDE2000 perceptual color difference between both colors, decoded with the given system.
+	/// A difference of 1 is about the smallest one can notice.
+	pub fn delta_e_2000(&self, other: &Color, system: &ColorSystem) -> f64 {
+		self.to_lab(system).delta_e_2000(&other.to_lab(system))
+	}
+	/// L*a*b* coordinates of the color, adapted from the white point of the system to D65.
+	fn to_lab(&self, system: &ColorSystem) -> Lab {
+		system
+			.to_xyz(self)
+			.expect("Couldn't convert to XYZ")
+			.adapt(system.white, ILLUMINANT_D65)
+			.to_lab()
+	}
+	/// Hue (in degrees, 0..360), saturation and value (both 0..1) of the color, computed on the
+	/// linear channels. Achromatic colors have a hue and saturation of 0.
+	pub fn to_hsv(&self) -> (f64, f64, f64) {
+		let lin = self.linear();
+		let (hue, min, max) = hue_min_max(lin.red, lin.green, lin.blue);
+		let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+		(hue, saturation, max)
+	}
+	/// Linear color from its hue (in degrees, wrapped into 0..360), saturation and value. This is
+	/// the inverse of `to_hsv`.
+	pub fn from_hsv(h: f64, s: f64, v: f64) -> Self {
+		let chroma = v * s;
+		Color::from_hue_chroma(h, chroma, v - chroma)
+	}
+	/// Like `from_hsv`, encoding the color into the given system.
+	pub fn from_hsv_with_system(h: f64, s: f64, v: f64, system: ColorSystem) -> Self {
+		system
+			.gamma(&Color::from_hsv(h, s, v))
+			.into_with_system(system)
+	}
+	/// Hue (in degrees, 0..360), saturation and lightness (both 0..1) of the color, computed on
+	/// the linear channels. Achromatic colors have a hue and saturation of 0.
+	pub fn to_hsl(&self) -> (f64, f64, f64) {
+		let lin = self.linear();
+		let (hue, min, max) = hue_min_max(lin.red, lin.green, lin.blue);
+		let lightness = (max + min) / 2.0;
+		let saturation = if max == min {
+			0.0
+		} else {
+			(max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+		};
+
+		(hue, saturation, lightness)
+	}
+	/// Linear color from its hue (in degrees, wrapped into 0..360), saturation and lightness.
+	/// This is the inverse of `to_hsl`.
+	pub fn from_hsl(h: f64, s: f64, l: f64) -> Self {
+		let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+		Color::from_hue_chroma(h, chroma, l - chroma / 2.0)
+	}
+	/// Like `from_hsl`, encoding the color into the given system.
+	pub fn from_hsl_with_system(h: f64, s: f64, l: f64, system: ColorSystem) -> Self {
+		system
+			.gamma(&Color::from_hsl(h, s, l))
+			.into_with_system(system)
+	}
+	/// Rotates the hue of the color by the given angle in degrees, keeping its saturation,
+	/// lightness, alpha and system.
+	pub fn hue_rotate(&self, degrees: f64) -> Self {
+		let (h, s, l) = self.to_hsl();
+		let col = Color::from_hsl(h + degrees, s, l);
+		let col = match self.system {
+			Some(sys) => sys.gamma(&col),
+			None => col,
+		};
+
+		self.replace_rgb(col.red, col.green, col.blue)
+	}
+	/// Color with the given hue (in degrees), chroma and amount of grey added to every channel,
+	/// following the hexagonal projection shared by HSV and HSL.
+	fn from_hue_chroma(h: f64, chroma: f64, grey: f64) -> Self {
+		let sector = h.rem_euclid(360.0) / 60.0;
+		let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+		let (r, g, b) = match sector as u32 % 6 {
+			0 => (chroma, x, 0.0),
+			1 => (x, chroma, 0.0),
+			2 => (0.0, chroma, x),
+			3 => (0.0, x, chroma),
+			4 => (x, 0.0, chroma),
+			_ => (chroma, 0.0, x),
+		};
+
+		Color::new(r + grey, g + grey, b + grey)
+	}
+	/// Multiplies the color channels by alpha, in linear light. Premultiplied colors composite
+	/// with plain additions, see `alpha_composite`.
+	pub fn premultiply_alpha(&self) -> Self {
+		self.scale_linear_rgb(self.alpha)
+	}
+	/// Divides the color channels by alpha, undoing `premultiply_alpha`. Fully transparent
+	/// colors become transparent black.
+	pub fn unpremultiply_alpha(&self) -> Self {
+		if self.alpha < 1e-10 {
+			let mut col = self.replace_rgb(0.0, 0.0, 0.0);
+			col.alpha = 0.0;
+			return col;
+		}
+		self.scale_linear_rgb(1.0 / self.alpha)
+	}
+	fn scale_linear_rgb(&self, factor: f64) -> Self {
+		let lin = self.linear();
+		let col = lin.replace_rgb(lin.red * factor, lin.green * factor, lin.blue * factor);
+		match self.system {
+			Some(s) => s.gamma(&col),
+			None => col,
+		}
+	}
 	pub fn into_with_system(self, sys: ColorSystem) -> Self {
 		let mut col = self.clone();
 		col.system = Some(sys);
@@ -102,6 +259,38 @@ impl Color {
 }
 
 impl Color {
+	/// Adds colors in linear light, or fails if they are from different systems.
+	pub fn checked_add(self, rhs: Self) -> Result<Self, ColorError> {
+		self.zip_linear(&rhs, |a, b| a + b)
+	}
+	/// Subtracts colors in linear light, or fails if they are from different systems.
+	pub fn checked_sub(self, rhs: Self) -> Result<Self, ColorError> {
+		self.zip_linear(&rhs, |a, b| a - b)
+	}
+	/// Combines each channel of both colors, decoded to linear light, and encodes the result back
+	/// into their common system.
+	fn zip_linear<F: Fn(f64, f64) -> f64>(&self, rhs: &Self, f: F) -> Result<Self, ColorError> {
+		let system = require_compatible_systems(self.system, rhs.system)?;
+		let (a, b) = (self.linear(), rhs.linear());
+		let col = Color {
+			red: f(a.red, b.red),
+			green: f(a.green, b.green),
+			blue: f(a.blue, b.blue),
+			alpha: f(a.alpha, b.alpha),
+			system,
+		};
+
+		Ok(match system {
+			Some(s) => s.gamma(&col),
+			None => col,
+		})
+	}
+	fn linear(&self) -> Self {
+		match self.system {
+			Some(s) => s.gamma_inv(self),
+			None => self.clone(),
+		}
+	}
 	pub fn mix(x: f64, a: Self, b: Self) -> Result<Self, &'static str> {
 		if a.system != b.system {
 			return Err("Cannot mix colors from different systems");
@@ -115,35 +304,167 @@ impl Color {
 			system: a.system,
 		})
 	}
+	/// Color of a monochromatic spectral line, modelled as a narrow Gaussian spectrum (5 nm
+	/// FWHM) centered on the wavelength. The color is constrained into the system's gamut and
+	/// normalized, as pure spectral colors lie outside of any RGB gamut.
+	pub fn from_wavelength_nm(lambda: f64, system: ColorSystem) -> Self {
+		const FWHM: f64 = 5.0;
+		let sigma = FWHM / (2.0 * (2.0 * f64::consts::LN_2).sqrt());
+		XYZ::from_spectral_data(&|l| (-0.5 * ((l - lambda) / sigma).powi(2)).exp())
+			.to_color(system)
+			.expect("Couldn't convert to color")
+			.constrain()
+			.normalize()
+	}
+	/// Image of the given size filled with this color, for quick previews.
+	pub fn swatch(&self, width: u32, height: u32) -> DynamicImage {
+		let mut img = DynamicImage::new_rgba8(width, height);
+		let px: Rgba<u8> = self.clone().into();
+		for p in img.as_mut_rgba8().unwrap().pixels_mut() {
+			*p = px;
+		}
+
+		img
+	}
+	/// Image of the given size filled with a horizontal gradient going from `from` on the left
+	/// edge to `to` on the right edge.
+	///
+	/// Panics if the colors are from different systems.
+	pub fn gradient_swatch(from: Color, to: Color, width: u32, height: u32) -> DynamicImage {
+		let mut img = DynamicImage::new_rgba8(width, height);
+		let last = width.saturating_sub(1).max(1) as f64;
+		let columns: Vec<Rgba<u8>> = (0..width)
+			.map(|x| {
+				Color::mix(x as f64 / last, from.clone(), to.clone())
+					.expect("Cannot make a gradient between colors from different systems")
+					.into()
+			})
+			.collect();
+		for (x, _, p) in img.as_mut_rgba8().unwrap().enumerate_pixels_mut() {
+			*p = columns[x as usize];
+		}
+
+		img
+	}
 }
 
-impl Add<Color> for Color {
-	type Output = Self;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorError {
+	IncompatibleSystems,
+}
 
-	fn add(self, rhs: Self) -> Self {
-		if self.system != rhs.system {
-			panic!("Cannot add colors from different systems");
+impl fmt::Display for ColorError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ColorError::IncompatibleSystems => write!(f, "Colors are from different systems"),
 		}
+	}
+}
 
-		if let Some(s) = self.system {
-			let col = s.gamma_inv(&self);
+impl Error for ColorError {}
 
-			s.gamma(&Self {
-				red: col.red + rhs.red,
-				green: col.green + rhs.green,
-				blue: col.blue + rhs.blue,
-				alpha: col.alpha + rhs.alpha,
-				system: self.system,
-			})
-		} else {
-			Self {
-				red: self.red + rhs.red,
-				green: self.green + rhs.green,
-				blue: self.blue + rhs.blue,
-				alpha: self.alpha + rhs.alpha,
-				system: self.system,
-			}
-		}
+/// Returns the system to use when combining colors from the given systems. An untagged color is
+/// compatible with any system.
+pub fn require_compatible_systems(
+	a: Option<ColorSystem>,
+	b: Option<ColorSystem>,
+) -> Result<Option<ColorSystem>, ColorError> {
+	match (a, b) {
+		(Some(a), Some(b)) if a != b => Err(ColorError::IncompatibleSystems),
+		(a, b) => Ok(a.or(b)),
+	}
+}
+
+/// Porter-Duff "over" operator: composites `over` on top of `under`, both with straight alpha.
+/// The colors are blended premultiplied, in linear light.
+///
+/// Panics if the colors are from different systems.
+pub fn alpha_composite(over: &Color, under: &Color) -> Color {
+	let system = require_compatible_systems(over.system, under.system)
+		.expect("Cannot composite colors from different systems");
+	// Spares opaque and fully transparent pixels the rounding errors of the round trip
+	if over.alpha >= 1.0 {
+		return over.clone();
+	} else if over.alpha <= 0.0 {
+		return under.clone();
+	}
+
+	let a = over.premultiply_alpha().linear();
+	let b = under.premultiply_alpha().linear();
+	let rest = 1.0 - a.alpha;
+	let col = Color {
+		red: a.red + b.red * rest,
+		green: a.green + b.green * rest,
+		blue: a.blue + b.blue * rest,
+		alpha: a.alpha + b.alpha * rest,
+		system: None,
+	}
+	.unpremultiply_alpha();
+
+	match system {
+		Some(s) => s.gamma(&col).into_with_system(s),
+		None => col,
+	}
+}
+
+/// Shifts the color by the redshift `z` (negative for a blueshift, greater than -1), stretching
+/// its wavelengths by `1 + z`. The channels are taken as samples of a piecewise linear spectrum
+/// falling off to zero at the edges of the visible range, which is sampled back at the shifted
+/// wavelengths. The intensity also drops by `(1 + z)^4`, as photons lose energy and arrive
+/// further apart: an infinite redshift gives black.
+pub fn redshift(col: Color, z: f64) -> Color {
+	if z == 0.0 {
+		return col;
+	}
+
+	let lin = col.linear();
+	let [red_nm, green_nm, blue_nm] = CHANNEL_WAVELENGTHS;
+	let spectrum = [
+		(VISIBLE_RANGE.0, 0.0),
+		(blue_nm, lin.blue),
+		(green_nm, lin.green),
+		(red_nm, lin.red),
+		(VISIBLE_RANGE.1, 0.0),
+	];
+	let sample = |lambda: f64| {
+		spectrum
+			.windows(2)
+			.find(|w| lambda >= w[0].0 && lambda <= w[1].0)
+			.map(|w| lerp((lambda - w[0].0) / (w[1].0 - w[0].0), w[0].1, w[1].1))
+			.unwrap_or(0.0)
+	};
+
+	let stretch = 1.0 + z;
+	let dimming = stretch.powi(-4);
+	let shifted = lin.replace_rgb(
+		sample(red_nm / stretch) * dimming,
+		sample(green_nm / stretch) * dimming,
+		sample(blue_nm / stretch) * dimming,
+	);
+	match col.system {
+		Some(s) => s.gamma(&shifted),
+		None => shifted,
+	}
+}
+
+/// Relativistic Doppler shift of light from a source moving at `beta` times the speed of light
+/// along the line of sight, positive towards the observer (blueshift) and negative away from it
+/// (redshift). `beta` must be within (-1, 1). See `redshift`.
+pub fn doppler_shift(col: Color, beta: f64) -> Color {
+	redshift(col, ((1.0 - beta) / (1.0 + beta)).sqrt() - 1.0)
+}
+
+/// Sorts colors in increasing order of relative luminance.
+pub fn sort_by_luminance(colors: &mut [Color]) {
+	colors.sort_by(|a, b| a.luminance_cmp(b));
+}
+
+impl Add<Color> for Color {
+	type Output = Self;
+
+	fn add(self, rhs: Self) -> Self {
+		self.checked_add(rhs)
+			.expect("Cannot add colors from different systems")
 	}
 }
 
@@ -151,27 +472,31 @@ impl Sub<Color> for Color {
 	type Output = Self;
 
 	fn sub(self, rhs: Self) -> Self {
-		if self.system != rhs.system {
-			panic!("Cannot subtract colors from different systems");
-		}
+		self.checked_sub(rhs)
+			.expect("Cannot subtract colors from different systems")
+	}
+}
 
+impl Neg for Color {
+	type Output = Self;
+
+	fn neg(self) -> Self {
 		if let Some(s) = self.system {
 			let col = s.gamma_inv(&self);
-			let rhs = s.gamma_inv(&rhs);
 
 			s.gamma(&Self {
-				red: col.red - rhs.red,
-				green: col.green - rhs.green,
-				blue: col.blue - rhs.blue,
-				alpha: col.alpha - rhs.alpha,
+				red: -col.red,
+				green: -col.green,
+				blue: -col.blue,
+				alpha: -col.alpha,
 				system: self.system,
 			})
 		} else {
 			Self {
-				red: self.red - rhs.red,
-				green: self.green - rhs.green,
-				blue: self.blue - rhs.blue,
-				alpha: self.alpha - rhs.alpha,
+				red: -self.red,
+				green: -self.green,
+				blue: -self.blue,
+				alpha: -self.alpha,
 				system: self.system,
 			}
 		}
@@ -223,6 +548,16 @@ impl Mul<f64> for Color {
 	}
 }
 
+impl Mul<Color> for Color {
+	type Output = Self;
+
+	/// Component-wise modulation ("Multiply" blend mode), computed in linear light
+	fn mul(self, rhs: Self) -> Self {
+		self.zip_linear(&rhs, |a, b| a * b)
+			.expect("Cannot multiply colors from different systems")
+	}
+}
+
 impl Div<f64> for Color {
 	type Output = Self;
 	fn div(self, rhs: f64) -> Self {
@@ -320,6 +655,12 @@ impl From<Vector4<f64>> for Color {
 	}
 }
 
+impl From<Point3<f64>> for Color {
+	fn from(val: Point3<f64>) -> Color {
+		Color::from(val.coords)
+	}
+}
+
 impl Into<u32> for Color {
 	fn into(self) -> u32 {
 		self.to_u32()
@@ -383,6 +724,33 @@ impl Into<Vector4<f64>> for Color {
 	}
 }
 
+impl From<Color> for Point3<f64> {
+	fn from(val: Color) -> Self {
+		let v: Vector3<f64> = val.into();
+		Point3::from(v)
+	}
+}
+
+/// Quantises the color into 8-bit channels. Colors tagged with a `ColorSystem` are stored gamma-encoded
+/// already, so channels are only clamped to [0, 1] before quantisation, consistent with `to_u32`.
+impl From<Color> for Rgba<u8> {
+	fn from(val: Color) -> Self {
+		Rgba([
+			quantize(val.red),
+			quantize(val.green),
+			quantize(val.blue),
+			quantize(val.alpha),
+		])
+	}
+}
+
+/// Quantises the color into 8-bit channels, dropping alpha. See the `Rgba<u8>` conversion.
+impl From<Color> for Rgb<u8> {
+	fn from(val: Color) -> Self {
+		Rgb([quantize(val.red), quantize(val.green), quantize(val.blue)])
+	}
+}
+
 impl fmt::Display for Color {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(
@@ -396,8 +764,17 @@ impl fmt::Display for Color {
 
 #[cfg(test)]
 mod tests {
-	use crate::color::Color;
+	use crate::color::{
+		alpha_composite, doppler_shift, redshift, require_compatible_systems, sort_by_luminance,
+		Color, ColorError,
+	};
+	use crate::gamut::SYSTEM_CIERGB;
 	use crate::gamut::SYSTEM_SRGB;
+	use crate::lab::tests::SHARMA_2005_PAIRS;
+	use crate::lab::Lab;
+	use approx::assert_relative_eq;
+	use image::{Rgb, Rgba};
+	use nalgebra::Point3;
 	use std::u32;
 
 	#[test]
@@ -407,6 +784,32 @@ mod tests {
 		assert_eq!(col, col2);
 	}
 
+	#[test]
+	fn computes_color_differences() {
+		let gray = Color::with_system(0.5, 0.5, 0.5, SYSTEM_SRGB);
+		assert_eq!(gray.delta_e_2000(&gray, &SYSTEM_SRGB), 0.0);
+		let white = Color::with_system(1.0, 1.0, 1.0, SYSTEM_SRGB);
+		let black = Color::with_system(0.0, 0.0, 0.0, SYSTEM_SRGB);
+		assert_relative_eq!(
+			white.delta_e_76(&black, &SYSTEM_SRGB),
+			100.0,
+			epsilon = 1e-6
+		);
+
+		// Pairs from Sharma, Wu and Dalal's CIEDE2000 test data, as sRGB colors. The math itself
+		// is tested on `Lab`.
+		let color = |(l, a, b)| {
+			SYSTEM_SRGB
+				.to_rgb(&Lab::new(l, a, b).to_xyz())
+				.expect("Couldn't convert to RGB")
+		};
+		for &(x, y, expected) in &SHARMA_2005_PAIRS {
+			let (x, y) = (color(x), color(y));
+			assert_relative_eq!(x.delta_e_2000(&y, &SYSTEM_SRGB), expected, epsilon = 1e-4);
+			assert_relative_eq!(y.delta_e_2000(&x, &SYSTEM_SRGB), expected, epsilon = 1e-4);
+		}
+	}
+
 	#[test]
 	fn shows_hex() {
 		let col = Color::new(1.0, 0.0, 1.0);
@@ -427,6 +830,326 @@ mod tests {
 		assert_eq!(output, input);
 	}
 
+	#[test]
+	fn converts_into_image_pixels() {
+		let col = Color::with_system(1.5, 0.5, -0.2, SYSTEM_SRGB);
+		let rgba: Rgba<u8> = col.clone().into();
+		let rgb: Rgb<u8> = col.into();
+		assert_eq!(rgba, Rgba([255, 127, 0, 255]));
+		assert_eq!(rgb, Rgb([255, 127, 0]));
+	}
+
+	#[test]
+	fn converts_to_and_from_points() {
+		let col = Color::new(0.1, 0.2, 0.3);
+		let pt: Point3<f64> = col.clone().into();
+		assert_eq!(pt, Point3::new(0.1, 0.2, 0.3));
+		assert_eq!(Color::from(pt), col);
+	}
+
+	#[test]
+	fn sorts_by_luminance() {
+		let mut colors = vec![
+			Color::new(1.0, 1.0, 1.0),
+			Color::new(0.0, 0.0, 1.0),
+			Color::new(0.0, 1.0, 0.0),
+			Color::new(1.0, 0.0, 0.0),
+		];
+		sort_by_luminance(&mut colors);
+		assert_eq!(
+			colors,
+			vec![
+				Color::new(0.0, 0.0, 1.0),
+				Color::new(1.0, 0.0, 0.0),
+				Color::new(0.0, 1.0, 0.0),
+				Color::new(1.0, 1.0, 1.0),
+			]
+		);
+	}
+
+	#[test]
+	fn converts_to_grayscale() {
+		let red = Color::from_u32(0xFFFF0000);
+		assert_relative_eq!(red.to_grayscale(), 0.2126, epsilon = 1e-12);
+		assert_relative_eq!(
+			Color::from_u32(0xFFFFFFFF).to_grayscale(),
+			1.0,
+			epsilon = 1e-12
+		);
+		// Mid gray is encoded, its luminance is much lower than half
+		let gray = Color::from_u32(0xFF808080).to_grayscale();
+		assert!(gray > 0.2 && gray < 0.25, "{}", gray);
+		assert_eq!(Color::new(0.5, 0.5, 0.5).to_grayscale(), 0.5);
+	}
+
+	#[test]
+	fn negates_and_inverts() {
+		let col = Color::new(0.25, 0.5, 1.0);
+		let mut expected = Color::new(-0.25, -0.5, -1.0);
+		expected.alpha = -1.0;
+		assert_eq!(-col.clone(), expected);
+		assert_eq!(-(-col.clone()), col);
+		assert_eq!(col.invert(), Color::new(0.75, 0.5, 0.0));
+
+		let srgb = Color::with_system(0.02, 0.5, 1.0, SYSTEM_SRGB);
+		let negated = -srgb.clone();
+		assert_eq!(negated.red, -0.02);
+		assert_eq!(
+			srgb.invert(),
+			Color::with_system(0.98, 0.5, 0.0, SYSTEM_SRGB)
+		);
+	}
+
+	#[test]
+	fn modulates_colors() {
+		let col = Color::with_system(0.2, 0.6, 0.9, SYSTEM_SRGB);
+		let white = Color::with_system(1.0, 1.0, 1.0, SYSTEM_SRGB);
+		let black = Color::with_system(0.0, 0.0, 0.0, SYSTEM_SRGB);
+		let modulated = col.clone() * white;
+		assert_relative_eq!(modulated.red, col.red, epsilon = 1e-9);
+		assert_relative_eq!(modulated.green, col.green, epsilon = 1e-9);
+		assert_relative_eq!(modulated.blue, col.blue, epsilon = 1e-9);
+		assert_eq!(col * black, Color::with_system(0.0, 0.0, 0.0, SYSTEM_SRGB));
+
+		let lin = Color::new(0.5, 0.25, 1.0) * Color::new(0.5, 1.0, 0.0);
+		assert_eq!(lin, Color::new(0.25, 0.25, 0.0));
+	}
+
+	#[test]
+	fn clamps_channels() {
+		let col = Color::with_system(0.2, 0.6, 0.9, SYSTEM_SRGB);
+		assert_eq!(col.to_unit_range(), col);
+		assert_eq!(col.clamp_channels(0.0, 2.0), col);
+
+		let mut out = Color::with_system(1.5, -0.5, 0.5, SYSTEM_SRGB);
+		out.alpha = 3.0;
+		assert_eq!(
+			out.to_unit_range(),
+			Color::with_system(1.0, 0.0, 0.5, SYSTEM_SRGB)
+		);
+		let clamped = out.clamp_channels(0.25, 0.75);
+		assert_eq!((clamped.red, clamped.green), (0.75, 0.25));
+		assert_eq!((clamped.blue, clamped.alpha), (0.5, 0.75));
+	}
+
+	#[test]
+	fn creates_swatches() {
+		let red = Color::from_u32(0xFFFF0000);
+		let blue = Color::from_u32(0xFF0000FF);
+		let img = red.swatch(4, 2);
+		let buf = img.as_rgba8().unwrap();
+		assert_eq!(buf.dimensions(), (4, 2));
+		assert!(buf.pixels().all(|p| *p == Rgba([255, 0, 0, 255])));
+
+		let img = Color::gradient_swatch(red, blue, 5, 2);
+		let buf = img.as_rgba8().unwrap();
+		assert_eq!(*buf.get_pixel(0, 1), Rgba([255, 0, 0, 255]));
+		assert_eq!(*buf.get_pixel(2, 0), Rgba([127, 0, 127, 255]));
+		assert_eq!(*buf.get_pixel(4, 1), Rgba([0, 0, 255, 255]));
+	}
+
+	#[test]
+	fn checks_system_compatibility() {
+		assert_eq!(require_compatible_systems(None, None), Ok(None));
+		assert_eq!(
+			require_compatible_systems(Some(SYSTEM_SRGB), None),
+			Ok(Some(SYSTEM_SRGB))
+		);
+		assert_eq!(
+			require_compatible_systems(None, Some(SYSTEM_SRGB)),
+			Ok(Some(SYSTEM_SRGB))
+		);
+		assert_eq!(
+			require_compatible_systems(Some(SYSTEM_SRGB), Some(SYSTEM_SRGB)),
+			Ok(Some(SYSTEM_SRGB))
+		);
+		assert_eq!(
+			require_compatible_systems(Some(SYSTEM_SRGB), Some(SYSTEM_CIERGB)),
+			Err(ColorError::IncompatibleSystems)
+		);
+
+		let srgb = Color::with_system(0.5, 0.5, 0.5, SYSTEM_SRGB);
+		let cie = Color::with_system(0.5, 0.5, 0.5, SYSTEM_CIERGB);
+		assert_eq!(
+			srgb.clone().checked_sub(cie),
+			Err(ColorError::IncompatibleSystems)
+		);
+		let mut black = Color::with_system(0.0, 0.0, 0.0, SYSTEM_SRGB);
+		black.alpha = 0.0;
+		assert_eq!(black.checked_add(srgb.clone()), Ok(srgb.clone()));
+		let mut doubled = srgb.clone() + Color::from((0.0, 0.0, 0.0, 0.0));
+		assert_eq!(doubled, srgb);
+		doubled += srgb.clone();
+		assert!(doubled.red > srgb.red);
+	}
+
+	#[test]
+	fn colors_spectral_lines() {
+		let red = Color::from_wavelength_nm(650.0, SYSTEM_SRGB);
+		let green = Color::from_wavelength_nm(530.0, SYSTEM_SRGB);
+		let blue = Color::from_wavelength_nm(450.0, SYSTEM_SRGB);
+		for col in &[&red, &green, &blue] {
+			assert!(col.in_gamut());
+			assert_relative_eq!(col.red.max(col.green).max(col.blue), 1.0);
+		}
+		assert!(red.red > red.green && red.red > red.blue);
+		assert!(green.green > green.red && green.green > green.blue);
+		assert!(blue.blue > blue.red && blue.blue > blue.green);
+	}
+
+	#[test]
+	fn converts_to_and_from_hsv() {
+		assert_eq!(Color::new(1.0, 0.0, 0.0).to_hsv(), (0.0, 1.0, 1.0));
+		assert_eq!(Color::new(0.0, 0.5, 0.0).to_hsv(), (120.0, 1.0, 0.5));
+		assert_eq!(Color::new(0.0, 0.0, 1.0).to_hsv(), (240.0, 1.0, 1.0));
+		assert_eq!(Color::from_hsv(300.0, 1.0, 1.0), Color::new(1.0, 0.0, 1.0));
+		assert_eq!(Color::from_hsv(-60.0, 1.0, 1.0), Color::new(1.0, 0.0, 1.0));
+
+		// Achromatic colors don't have a hue
+		let (h, s, v) = Color::new(0.3, 0.3, 0.3).to_hsv();
+		assert_eq!((h, s), (0.0, 0.0));
+		assert_relative_eq!(v, 0.3);
+		assert_eq!(Color::new(0.0, 0.0, 0.0).to_hsv(), (0.0, 0.0, 0.0));
+		assert_eq!(Color::from_hsv(0.0, 0.0, 0.3), Color::new(0.3, 0.3, 0.3));
+
+		for &(r, g, b) in &[(0.2, 0.6, 0.9), (0.9, 0.1, 0.4), (0.5, 0.45, 0.0)] {
+			let col = Color::new(r, g, b);
+			let (h, s, v) = col.to_hsv();
+			let back = Color::from_hsv(h, s, v);
+			assert_relative_eq!(back.red, r, epsilon = 1e-12);
+			assert_relative_eq!(back.green, g, epsilon = 1e-12);
+			assert_relative_eq!(back.blue, b, epsilon = 1e-12);
+
+			// Tagged colors are converted in linear light
+			let srgb = Color::with_system(r, g, b, SYSTEM_SRGB);
+			let (h, s, v) = srgb.to_hsv();
+			let back = Color::from_hsv_with_system(h, s, v, SYSTEM_SRGB);
+			assert_relative_eq!(back.red, r, epsilon = 1e-9);
+			assert_relative_eq!(back.green, g, epsilon = 1e-9);
+			assert_relative_eq!(back.blue, b, epsilon = 1e-9);
+			assert_eq!(back.alpha, 1.0);
+		}
+	}
+
+	#[test]
+	fn converts_to_and_from_hsl() {
+		assert_eq!(Color::new(1.0, 0.0, 0.0).to_hsl(), (0.0, 1.0, 0.5));
+		assert_eq!(Color::new(0.0, 0.0, 0.0).to_hsl(), (0.0, 0.0, 0.0));
+		assert_eq!(Color::new(1.0, 1.0, 1.0).to_hsl(), (0.0, 0.0, 1.0));
+		assert_eq!(Color::from_hsl(120.0, 1.0, 0.0), Color::new(0.0, 0.0, 0.0));
+		assert_eq!(Color::from_hsl(120.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0));
+		assert_eq!(
+			Color::from_hsl(120.0, 0.0, 0.25),
+			Color::new(0.25, 0.25, 0.25)
+		);
+
+		// Pure hues are as light as they can be while staying saturated
+		for &h in &[0.0, 60.0, 150.0, 270.0] {
+			assert_eq!(Color::from_hsl(h, 1.0, 0.5), Color::from_hsv(h, 1.0, 1.0));
+		}
+
+		for &(r, g, b) in &[(0.2, 0.6, 0.9), (0.9, 0.1, 0.4), (0.5, 0.45, 0.0)] {
+			let (h, s, l) = Color::new(r, g, b).to_hsl();
+			let back = Color::from_hsl(h, s, l);
+			assert_relative_eq!(back.red, r, epsilon = 1e-12);
+			assert_relative_eq!(back.green, g, epsilon = 1e-12);
+			assert_relative_eq!(back.blue, b, epsilon = 1e-12);
+		}
+	}
+
+	#[test]
+	fn rotates_hue() {
+		assert_eq!(
+			Color::new(1.0, 0.0, 0.0).hue_rotate(120.0),
+			Color::new(0.0, 1.0, 0.0)
+		);
+
+		let mut col = Color::with_system(0.2, 0.6, 0.9, SYSTEM_SRGB);
+		col.alpha = 0.5;
+		let rotated = col.hue_rotate(360.0);
+		assert_relative_eq!(rotated.red, col.red, epsilon = 1e-9);
+		assert_relative_eq!(rotated.green, col.green, epsilon = 1e-9);
+		assert_relative_eq!(rotated.blue, col.blue, epsilon = 1e-9);
+		assert_eq!(rotated.alpha, 0.5);
+		assert_relative_eq!(
+			col.hue_rotate(90.0).to_hsl().0,
+			col.to_hsl().0 + 90.0,
+			epsilon = 1e-9
+		);
+	}
+
+	#[test]
+	fn premultiplies_and_composites_alpha() {
+		let mut col = Color::new(0.5, 1.0, 0.25);
+		col.alpha = 0.5;
+		let premultiplied = col.premultiply_alpha();
+		assert_eq!(
+			(premultiplied.red, premultiplied.green, premultiplied.blue),
+			(0.25, 0.5, 0.125)
+		);
+		assert_eq!(premultiplied.alpha, 0.5);
+		assert_eq!(premultiplied.unpremultiply_alpha(), col);
+
+		let mut clear = Color::with_system(0.3, 0.6, 0.9, SYSTEM_SRGB);
+		clear.alpha = 0.0;
+		let mut transparent_black = Color::with_system(0.0, 0.0, 0.0, SYSTEM_SRGB);
+		transparent_black.alpha = 0.0;
+		assert_eq!(clear.unpremultiply_alpha(), transparent_black);
+
+		let red = Color::from_u32(0xFFFF0000);
+		assert_eq!(alpha_composite(&clear, &red), red);
+		assert_eq!(alpha_composite(&red, &clear), red);
+		let blue = Color::from_u32(0xFF0000FF);
+		assert_eq!(alpha_composite(&blue, &red), blue);
+
+		// Half-transparent white over black gives half the light
+		let mut white = Color::new(1.0, 1.0, 1.0);
+		white.alpha = 0.5;
+		let grey = alpha_composite(&white, &Color::new(0.0, 0.0, 0.0));
+		assert_eq!(grey, Color::new(0.5, 0.5, 0.5));
+	}
+
+	#[test]
+	fn shifts_colors_along_the_spectrum() {
+		let white = Color::new(1.0, 1.0, 1.0);
+		assert_eq!(redshift(white.clone(), 0.0), white);
+
+		// Red and green stay within the flat part of the spectrum, blue moves towards its edge
+		let warm = redshift(white.clone(), 0.1);
+		let dimming = 1.1f64.powi(-4);
+		assert_relative_eq!(warm.red, dimming, epsilon = 1e-12);
+		assert_relative_eq!(warm.green, dimming, epsilon = 1e-12);
+		assert_relative_eq!(
+			warm.blue,
+			dimming * (450.0 / 1.1 - 380.0) / 70.0,
+			epsilon = 1e-12
+		);
+
+		// Green light is redshifted towards red, and blueshifted towards blue
+		let green = Color::new(0.0, 1.0, 0.0);
+		let redder = redshift(green.clone(), 0.1);
+		let bluer = redshift(green, -0.1);
+		assert!(redder.red > redder.blue);
+		assert!(bluer.blue > bluer.red);
+		let total = |c: &Color| c.red + c.green + c.blue;
+		assert!(total(&bluer) > total(&redder));
+
+		let mut col = Color::with_system(0.2, 0.5, 0.8, SYSTEM_SRGB);
+		col.alpha = 0.5;
+		let mut black = Color::with_system(0.0, 0.0, 0.0, SYSTEM_SRGB);
+		black.alpha = 0.5;
+		assert_eq!(redshift(col, f64::INFINITY), black);
+	}
+
+	#[test]
+	fn doppler_shifts_moving_sources() {
+		let col = Color::with_system(0.2, 0.5, 0.8, SYSTEM_SRGB);
+		assert_eq!(doppler_shift(col.clone(), 0.0), col);
+		// 1 + z = sqrt((1 - beta) / (1 + beta))
+		assert_eq!(doppler_shift(col.clone(), 0.6), redshift(col.clone(), -0.5));
+		assert_eq!(doppler_shift(col.clone(), -0.6), redshift(col, 1.0));
+	}
+
 	#[test]
 	fn can_constrain_into_gamut() {
 		let col = Color::new(0.0, 0.5, -2.5);
@@ -448,3 +1171,25 @@ fn clamp(x: f64, min: f64, max: f64) -> f64 {
 		x
 	}
 }
+
+/// Hue (in degrees, 0..360, 0 for greys), minimum and maximum of the channels
+fn hue_min_max(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+	let max = r.max(g).max(b);
+	let min = r.min(g).min(b);
+	let delta = max - min;
+	let hue = if delta == 0.0 {
+		0.0
+	} else if max == r {
+		60.0 * ((g - b) / delta)
+	} else if max == g {
+		60.0 * ((b - r) / delta + 2.0)
+	} else {
+		60.0 * ((r - g) / delta + 4.0)
+	};
+
+	(hue.rem_euclid(360.0), min, max)
+}
+
+fn quantize(x: f64) -> u8 {
+	(clamp(x, 0.0, 1.0) * 255f64) as u8
+}
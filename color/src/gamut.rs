@@ -1,5 +1,6 @@
 // Port of much of http://www.fourmilab.ch/documents/specrend/ - more precisely their C tool
 
+use crate::blackbody::CIE_COLOR_MATCH;
 use crate::color::Color;
 use crate::xyz::XYZ;
 
@@ -15,6 +16,11 @@ pub const ILLUMINANT_D65: XYChroma = XYChroma {
 	y: 0.3291,
 };
 #[allow(dead_code)]
+pub const ILLUMINANT_D50: XYChroma = XYChroma {
+	x: 0.3457,
+	y: 0.3585,
+};
+#[allow(dead_code)]
 pub const ILLUMINANT_C: XYChroma = XYChroma {
 	x: 0.3101,
 	y: 0.3162,
@@ -31,7 +37,7 @@ pub const SYSTEM_SRGB: ColorSystem = ColorSystem {
 	green: XYChroma { x: 0.3, y: 0.6 },
 	blue: XYChroma { x: 0.15, y: 0.06 },
 	white: ILLUMINANT_D65,
-	gamma: 2.4,
+	transfer: TransferFunction::SRGB,
 };
 
 #[allow(dead_code)]
@@ -40,19 +46,56 @@ pub const SYSTEM_CIERGB: ColorSystem = ColorSystem {
 	green: XYChroma { x: 0.3, y: 0.6 },
 	blue: XYChroma { x: 0.15, y: 0.06 },
 	white: ILLUMINANT_E,
-	gamma: 2.4,
+	transfer: TransferFunction::SRGB,
+};
+
+/// DCI-P3 primaries with the D65 white point, as used by wide-gamut displays and cinema
+#[allow(dead_code)]
+pub const SYSTEM_DCI_P3: ColorSystem = ColorSystem {
+	red: XYChroma { x: 0.680, y: 0.320 },
+	green: XYChroma { x: 0.265, y: 0.690 },
+	blue: XYChroma { x: 0.150, y: 0.060 },
+	white: ILLUMINANT_D65,
+	transfer: TransferFunction::PowerLaw(2.6),
+};
+
+/// ITU-R BT.2020 (Rec. 2020) primaries, used by UHD and HDR video
+#[allow(dead_code)]
+pub const SYSTEM_REC2020: ColorSystem = ColorSystem {
+	red: XYChroma { x: 0.708, y: 0.292 },
+	green: XYChroma { x: 0.170, y: 0.797 },
+	blue: XYChroma { x: 0.131, y: 0.046 },
+	white: ILLUMINANT_D65,
+	transfer: TransferFunction::PowerLaw(2.4),
 };
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColorSystem {
 	pub red: XYChroma,
 	pub green: XYChroma,
 	pub blue: XYChroma,
 	pub white: XYChroma,
-	pub gamma: f64,
+	pub transfer: TransferFunction,
+}
+
+/// Transfer function encoding linear light into the values stored by a color system. Negative
+/// values are encoded symmetrically, so out-of-gamut colors survive round trips.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferFunction {
+	/// Pure power law with the given exponent, linear light being `value^exponent`
+	PowerLaw(f64),
+	/// Piecewise sRGB curve: a linear segment near black, then a 2.4 power law
+	SRGB,
+	/// SMPTE ST 2084 Perceptual Quantizer used by HDR10, linear light 1.0 being 10000 nits
+	PQ,
+	/// ARIB STD-B67 Hybrid Log-Gamma used by HDR broadcasts, linear light 1.0 being the peak
+	HLG,
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XYChroma {
 	pub x: f64,
 	pub y: f64,
@@ -61,6 +104,10 @@ pub struct XYChroma {
 impl ColorSystem {
 	pub fn to_rgb(&self, xyz: &XYZ) -> Result<Color, &'static str> {
 		self.get_matrix_to_rgb()
+			.and_then(|m| {
+				m.try_inverse()
+					.ok_or("Cannot invert RGB to XYZ component matrix")
+			})
 			.map(|m| {
 				let v: Vector3<f64> = xyz.clone().into();
 				m * v
@@ -71,13 +118,25 @@ impl ColorSystem {
 	pub fn to_xyz(&self, col: &Color) -> Result<XYZ, &'static str> {
 		let lin_col = self.gamma_inv(col);
 		self.get_matrix_to_rgb().map(|m| {
-			let colv: Vector3<f64> = lin_col.into();
-			let _xyzv: Vector3<f64> = m * colv;
+			// Not through `Into`, which would decode the tagged color a second time
+			let colv = Vector3::new(lin_col.red, lin_col.green, lin_col.blue);
 
 			XYZ::from(m * colv)
 		})
 	}
 
+	/// Converts a color of this system into the target system, adapting it to the target's
+	/// white point with the Bradford transform.
+	pub fn convert_to(&self, col: &Color, target: &ColorSystem) -> Result<Color, &'static str> {
+		self.to_xyz(col)
+			.map(|xyz| xyz.adapt(self.white, target.white))
+			.and_then(|xyz| target.to_rgb(&xyz))
+			.map(|mut c| {
+				c.alpha = col.alpha;
+				c
+			})
+	}
+
 	pub fn desaturate(&self, col: &Color, percent: f64) -> Result<Color, &'static str> {
 		self.to_xyz(col)
 			.map(|xyz| xyz.to_chromaticity())
@@ -92,20 +151,51 @@ impl ColorSystem {
 			})
 	}
 
+	/// Whether all three primaries are visible colors, ie. lie within the spectrum locus.
+	pub fn is_within_visible_gamut(&self) -> bool {
+		let locus = spectrum_locus();
+		[self.red, self.green, self.blue]
+			.iter()
+			.all(|p| polygon_contains(&locus, p))
+	}
+
+	/// Area of the gamut triangle in the xy chromaticity diagram, as a fraction of the area of the
+	/// visible gamut. Systems with imaginary primaries can go over 1.
+	pub fn gamut_volume(&self) -> f64 {
+		polygon_area(&[self.red, self.green, self.blue]) / polygon_area(&spectrum_locus())
+	}
+
+	/// Whether the chromaticity of the color lies within the gamut triangle, ie. whether the color
+	/// can be represented without negative components. Black is in every gamut.
+	pub fn contains_xyz(&self, xyz: &XYZ) -> bool {
+		if xyz.X + xyz.Y + xyz.Z == 0.0 {
+			return true;
+		}
+		let (p, _) = xyz.to_chromaticity();
+		let (a, b, c) = (self.red, self.green, self.blue);
+		let det = (b.y - c.y) * (a.x - c.x) + (c.x - b.x) * (a.y - c.y);
+		let l1 = ((b.y - c.y) * (p.x - c.x) + (c.x - b.x) * (p.y - c.y)) / det;
+		let l2 = ((c.y - a.y) * (p.x - c.x) + (a.x - c.x) * (p.y - c.y)) / det;
+		let l3 = 1.0 - l1 - l2;
+
+		// Leave some room for rounding errors, so the primaries themselves are included
+		[l1, l2, l3].iter().all(|&l| l >= -1e-9)
+	}
+
 	pub fn gamma(&self, col: &Color) -> Color {
 		let mut new_col = col.clone();
-		new_col.red = gamma(self.gamma, col.red);
-		new_col.green = gamma(self.gamma, col.green);
-		new_col.blue = gamma(self.gamma, col.blue);
+		new_col.red = self.transfer.encode(col.red);
+		new_col.green = self.transfer.encode(col.green);
+		new_col.blue = self.transfer.encode(col.blue);
 
 		return new_col;
 	}
 
 	pub fn gamma_inv(&self, col: &Color) -> Color {
 		let mut new_col = col.clone();
-		new_col.red = gamma_inv(self.gamma, col.red);
-		new_col.green = gamma_inv(self.gamma, col.green);
-		new_col.blue = gamma_inv(self.gamma, col.blue);
+		new_col.red = self.transfer.decode(col.red);
+		new_col.green = self.transfer.decode(col.green);
+		new_col.blue = self.transfer.decode(col.blue);
 
 		return new_col;
 	}
@@ -143,8 +233,8 @@ impl fmt::Display for ColorSystem {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(
 			f,
-			"ColorSystem(red={}, green={}, blue={}, white={}, gamma={})",
-			self.red, self.green, self.blue, self.white, self.gamma
+			"ColorSystem(red={}, green={}, blue={}, white={}, transfer={})",
+			self.red, self.green, self.blue, self.white, self.transfer
 		)?;
 		Ok(())
 	}
@@ -157,7 +247,7 @@ impl Default for ColorSystem {
 			green: XYChroma { x: 1.0, y: 0.0 },
 			blue: XYChroma { x: 1.0, y: 0.0 },
 			white: ILLUMINANT_D65,
-			gamma: 1.0,
+			transfer: TransferFunction::PowerLaw(1.0),
 		}
 	}
 }
@@ -264,6 +354,41 @@ impl Into<Complex<f64>> for XYChroma {
 	}
 }
 
+impl TransferFunction {
+	/// Encodes a linear light value
+	pub fn encode(&self, value: f64) -> f64 {
+		let encoded = match *self {
+			TransferFunction::PowerLaw(exp) => value.abs().powf(1.0 / exp),
+			TransferFunction::SRGB => srgb_gamma(value.abs()),
+			TransferFunction::PQ => pq_encode(value.abs()),
+			TransferFunction::HLG => hlg_encode(value.abs()),
+		};
+		encoded.copysign(value)
+	}
+
+	/// Decodes an encoded value back into linear light
+	pub fn decode(&self, value: f64) -> f64 {
+		let decoded = match *self {
+			TransferFunction::PowerLaw(exp) => value.abs().powf(exp),
+			TransferFunction::SRGB => srgb_gamma_inv(value.abs()),
+			TransferFunction::PQ => pq_decode(value.abs()),
+			TransferFunction::HLG => hlg_decode(value.abs()),
+		};
+		decoded.copysign(value)
+	}
+}
+
+impl fmt::Display for TransferFunction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TransferFunction::PowerLaw(exp) => write!(f, "PowerLaw({})", exp),
+			TransferFunction::SRGB => write!(f, "sRGB"),
+			TransferFunction::PQ => write!(f, "PQ"),
+			TransferFunction::HLG => write!(f, "HLG"),
+		}
+	}
+}
+
 impl fmt::Display for XYChroma {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "XYChroma(x={}, y={})", self.x, self.y)?;
@@ -271,32 +396,111 @@ impl fmt::Display for XYChroma {
 	}
 }
 
+/// Horse-shoe boundary of the visible gamut, traced by the chromaticities of monochromatic light
+/// from 380 to 780 nm. The polygon is implicitly closed by the line of purples.
+pub fn spectrum_locus() -> Vec<XYChroma> {
+	CIE_COLOR_MATCH
+		.iter()
+		// The far ends of the table are too faint for their chromaticity to be precise
+		.filter(|a| a.iter().sum::<f64>() >= 1e-3)
+		.map(|a| XYZ::from(*a).to_chromaticity().0)
+		.collect()
+}
+
+/// Even-odd rule point in polygon test
+fn polygon_contains(polygon: &[XYChroma], p: &XYChroma) -> bool {
+	let mut inside = false;
+	let mut j = polygon.len() - 1;
+	for i in 0..polygon.len() {
+		let (a, b) = (polygon[i], polygon[j]);
+		if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+			inside = !inside;
+		}
+		j = i;
+	}
+
+	inside
+}
+
+/// Shoelace formula for the area of a simple polygon
+fn polygon_area(polygon: &[XYChroma]) -> f64 {
+	let mut area = 0.0;
+	let mut j = polygon.len() - 1;
+	for i in 0..polygon.len() {
+		area += polygon[j].x * polygon[i].y - polygon[i].x * polygon[j].y;
+		j = i;
+	}
+
+	area.abs() / 2.0
+}
+
 fn lerp(x: f64, a: f64, b: f64) -> f64 {
 	(1.0 - x) * a + x * b
 }
 
-fn gamma(exp: f64, value: f64) -> f64 {
+fn srgb_gamma(value: f64) -> f64 {
 	let alpha = 0.055;
 	if value > 0.0031308 {
-		return (1.0 + alpha) * value.powf(1.0 / exp) - alpha;
+		return (1.0 + alpha) * value.powf(1.0 / 2.4) - alpha;
 	} else {
 		return 12.92 * value;
 	}
 }
 
-fn gamma_inv(exp: f64, value: f64) -> f64 {
+fn srgb_gamma_inv(value: f64) -> f64 {
 	let alpha = 0.055;
 	if value > 0.04045 {
-		return ((value + alpha) / (1.0 + alpha)).powf(exp);
+		return ((value + alpha) / (1.0 + alpha)).powf(2.4);
 	} else {
 		return value / 12.92;
 	}
 }
 
+// SMPTE ST 2084 constants
+const PQ_M1: f64 = 2610.0 / 16384.0;
+const PQ_M2: f64 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f64 = 3424.0 / 4096.0;
+const PQ_C2: f64 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+fn pq_encode(value: f64) -> f64 {
+	let y = value.powf(PQ_M1);
+	((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+fn pq_decode(value: f64) -> f64 {
+	let e = value.powf(1.0 / PQ_M2);
+	((e - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * e)).powf(1.0 / PQ_M1)
+}
+
+// ARIB STD-B67 constants
+const HLG_A: f64 = 0.17883277;
+const HLG_B: f64 = 1.0 - 4.0 * HLG_A;
+const HLG_C: f64 = 0.55991073;
+
+fn hlg_encode(value: f64) -> f64 {
+	if value <= 1.0 / 12.0 {
+		(3.0 * value).sqrt()
+	} else {
+		HLG_A * (12.0 * value - HLG_B).ln() + HLG_C
+	}
+}
+
+fn hlg_decode(value: f64) -> f64 {
+	if value <= 0.5 {
+		value * value / 3.0
+	} else {
+		(((value - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use crate::color::Color;
-	use crate::gamut::{ColorSystem, XYChroma, ILLUMINANT_D65};
+	use crate::gamut::{
+		spectrum_locus, ColorSystem, TransferFunction, XYChroma, ILLUMINANT_D65, SYSTEM_DCI_P3,
+		SYSTEM_REC2020, SYSTEM_SRGB,
+	};
 	use crate::xyz::XYZ;
 	use approx::assert_abs_diff_eq;
 	use nalgebra::Vector2;
@@ -309,6 +513,85 @@ mod tests {
 		assert_eq!(chroma2.get_z(), 0.0);
 	}
 
+	#[test]
+	fn checks_primaries_against_spectrum_locus() {
+		let locus = spectrum_locus();
+		assert!(locus.iter().all(|xy| xy.x.is_finite() && xy.y.is_finite()));
+
+		assert!(SYSTEM_SRGB.is_within_visible_gamut());
+		let imaginary = ColorSystem {
+			green: XYChroma { x: 0.0, y: 1.0 },
+			..SYSTEM_SRGB
+		};
+		assert!(!imaginary.is_within_visible_gamut());
+	}
+
+	#[test]
+	fn compares_gamuts() {
+		let srgb = SYSTEM_SRGB.gamut_volume();
+		let rec2020 = SYSTEM_REC2020.gamut_volume();
+		// sRGB famously covers about a third of the visible colors, Rec.2020 about two thirds
+		assert_abs_diff_eq!(srgb, 0.35, epsilon = 0.03);
+		assert_abs_diff_eq!(rec2020, 0.64, epsilon = 0.03);
+
+		let primary = |xy| XYZ::chromaticity(xy, 1.0);
+		let rec2020_red = primary(SYSTEM_REC2020.red);
+		assert!(SYSTEM_REC2020.contains_xyz(&rec2020_red));
+		assert!(!SYSTEM_SRGB.contains_xyz(&rec2020_red));
+		for xy in &[SYSTEM_SRGB.red, SYSTEM_SRGB.green, SYSTEM_SRGB.blue] {
+			assert!(SYSTEM_REC2020.contains_xyz(&primary(*xy)));
+		}
+		assert!(SYSTEM_SRGB.contains_xyz(&primary(ILLUMINANT_D65)));
+	}
+
+	#[test]
+	fn transfer_functions_round_trip() {
+		let functions = [
+			TransferFunction::PowerLaw(2.2),
+			TransferFunction::SRGB,
+			TransferFunction::PQ,
+			TransferFunction::HLG,
+		];
+		for tf in &functions {
+			assert_abs_diff_eq!(tf.encode(0.0), 0.0, epsilon = 1e-6);
+			for &v in &[0.001, 0.05, 0.2, 0.5, 1.0, -0.3] {
+				assert_abs_diff_eq!(tf.decode(tf.encode(v)), v, epsilon = 1e-9);
+			}
+		}
+
+		assert_abs_diff_eq!(TransferFunction::PowerLaw(2.0).encode(0.25), 0.5);
+		assert_abs_diff_eq!(TransferFunction::SRGB.encode(0.001), 0.01292);
+		// 100 nits, the usual SDR reference white, sits about halfway through the PQ range
+		assert_abs_diff_eq!(TransferFunction::PQ.encode(0.01), 0.5081, epsilon = 1e-4);
+		assert_abs_diff_eq!(TransferFunction::PQ.encode(1.0), 1.0, epsilon = 1e-9);
+		assert_abs_diff_eq!(
+			TransferFunction::HLG.encode(1.0 / 12.0),
+			0.5,
+			epsilon = 1e-9
+		);
+		assert_abs_diff_eq!(TransferFunction::HLG.encode(1.0), 1.0, epsilon = 1e-6);
+
+		let srgb = Color::with_system(0.5, 0.25, 0.75, SYSTEM_SRGB);
+		let pq = ColorSystem {
+			transfer: TransferFunction::PQ,
+			..SYSTEM_SRGB
+		};
+		let decoded = pq.gamma_inv(&srgb);
+		assert_abs_diff_eq!(pq.gamma(&decoded).green, 0.25, epsilon = 1e-9);
+	}
+
+	#[test]
+	fn wide_gamut_systems_recover_primaries() {
+		for system in &[SYSTEM_DCI_P3, SYSTEM_REC2020] {
+			let (red, _) = Color::with_system(1.0, 0.0, 0.0, *system)
+				.to_xyz(None)
+				.expect("Couldn't convert to XYZ")
+				.to_chromaticity();
+			assert_abs_diff_eq!(red.x, system.red.x, epsilon = 1e-9);
+			assert_abs_diff_eq!(red.y, system.red.y, epsilon = 1e-9);
+		}
+	}
+
 	#[test]
 	fn colorsystem_works() {
 		let red = XYChroma { x: 0.65, y: 0.35 };
@@ -319,7 +602,7 @@ mod tests {
 			green,
 			blue,
 			white: ILLUMINANT_D65,
-			gamma: 0.0,
+			transfer: TransferFunction::PowerLaw(1.0),
 		};
 		let _white_point_xyz = XYZ::chromaticity(ILLUMINANT_D65, 1.0);
 		let xyz_red: Vector2<f64> = Color::with_system(1.0, 0.0, 0.0, system)
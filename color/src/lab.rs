@@ -0,0 +1,261 @@
+use crate::gamut::ILLUMINANT_D65;
+use crate::xyz::XYZ;
+
+use std::f64;
+use std::fmt;
+
+/// Threshold of the linear segment of the L*a*b* transfer function
+const EPSILON: f64 = 6.0 / 29.0;
+
+/** CIE 1976 L*a*b* color, relative to the D65 white point */
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lab {
+	/// Lightness, from 0 (black) to 100 (reference white)
+	pub L: f64,
+	/// Green (negative) to red (positive) axis
+	pub a: f64,
+	/// Blue (negative) to yellow (positive) axis
+	pub b: f64,
+}
+
+impl Lab {
+	pub fn new(l: f64, a: f64, b: f64) -> Self {
+		Lab { L: l, a, b }
+	}
+	pub fn to_xyz(&self) -> XYZ {
+		XYZ::from(*self)
+	}
+	/// Chroma, the distance from the neutral axis
+	pub fn chroma(&self) -> f64 {
+		self.a.hypot(self.b)
+	}
+	/// CIE76 color difference: the euclidean distance in L*a*b* space. A difference of about 2.3
+	/// is just noticeable.
+	pub fn delta_e_76(&self, other: &Lab) -> f64 {
+		((self.L - other.L).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2))
+			.sqrt()
+	}
+	/// CIEDE2000 color difference, correcting CIE76 for the perceptual non-uniformities of
+	/// L*a*b* in lightness, chroma and hue, and for the rotation of blue hues. Uses unit
+	/// weighting factors.
+	pub fn delta_e_2000(&self, other: &Lab) -> f64 {
+		let c_mean = (self.chroma() + other.chroma()) / 2.0;
+		let g = 0.5 * (1.0 - chroma_weight(c_mean));
+		let (c1, h1) = primed_chroma_hue(self, g);
+		let (c2, h2) = primed_chroma_hue(other, g);
+
+		let delta_l = other.L - self.L;
+		let delta_c = c2 - c1;
+		let delta_h = if c1 * c2 == 0.0 {
+			0.0
+		} else if (h2 - h1).abs() <= 180.0 {
+			h2 - h1
+		} else if h2 > h1 {
+			h2 - h1 - 360.0
+		} else {
+			h2 - h1 + 360.0
+		};
+		let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+		let l_mean = (self.L + other.L) / 2.0;
+		let c_mean = (c1 + c2) / 2.0;
+		let h_mean = if c1 * c2 == 0.0 {
+			h1 + h2
+		} else if (h1 - h2).abs() <= 180.0 {
+			(h1 + h2) / 2.0
+		} else if h1 + h2 < 360.0 {
+			(h1 + h2 + 360.0) / 2.0
+		} else {
+			(h1 + h2 - 360.0) / 2.0
+		};
+
+		let cos_deg = |x: f64| x.to_radians().cos();
+		let t = 1.0 - 0.17 * cos_deg(h_mean - 30.0)
+			+ 0.24 * cos_deg(2.0 * h_mean)
+			+ 0.32 * cos_deg(3.0 * h_mean + 6.0)
+			- 0.20 * cos_deg(4.0 * h_mean - 63.0);
+		let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+		let r_c = 2.0 * chroma_weight(c_mean);
+		let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+		let s_c = 1.0 + 0.045 * c_mean;
+		let s_h = 1.0 + 0.015 * c_mean * t;
+		let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+		let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+		(l * l + c * c + h * h + r_t * c * h).sqrt()
+	}
+}
+
+impl From<XYZ> for Lab {
+	fn from(val: XYZ) -> Self {
+		let white = XYZ::chromaticity(ILLUMINANT_D65, 1.0);
+		let fx = transfer(val.X / white.X);
+		let fy = transfer(val.Y / white.Y);
+		let fz = transfer(val.Z / white.Z);
+
+		Lab {
+			L: 116.0 * fy - 16.0,
+			a: 500.0 * (fx - fy),
+			b: 200.0 * (fy - fz),
+		}
+	}
+}
+
+impl From<Lab> for XYZ {
+	fn from(val: Lab) -> Self {
+		let white = XYZ::chromaticity(ILLUMINANT_D65, 1.0);
+		let fy = (val.L + 16.0) / 116.0;
+
+		XYZ {
+			X: white.X * transfer_inv(fy + val.a / 500.0),
+			Y: white.Y * transfer_inv(fy),
+			Z: white.Z * transfer_inv(fy - val.b / 200.0),
+		}
+	}
+}
+
+impl fmt::Display for Lab {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Lab(L={}, a={}, b={})", self.L, self.a, self.b)
+	}
+}
+
+/// Lightness transfer function of CIE 1976 spaces, linear near black and a cube root above
+pub(crate) fn transfer(t: f64) -> f64 {
+	if t > EPSILON.powi(3) {
+		t.cbrt()
+	} else {
+		t / (3.0 * EPSILON * EPSILON) + 4.0 / 29.0
+	}
+}
+
+/// Inverse of `transfer`
+pub(crate) fn transfer_inv(t: f64) -> f64 {
+	if t > EPSILON {
+		t.powi(3)
+	} else {
+		3.0 * EPSILON * EPSILON * (t - 4.0 / 29.0)
+	}
+}
+
+/// `sqrt(C^7 / (C^7 + 25^7))`, used by CIEDE2000 to weigh corrections by chroma
+fn chroma_weight(c: f64) -> f64 {
+	let c7 = c.powi(7);
+	(c7 / (c7 + 25f64.powi(7))).sqrt()
+}
+
+/// Chroma and hue angle (in degrees, 0..360) after stretching the a* axis by `1 + g`
+fn primed_chroma_hue(lab: &Lab, g: f64) -> (f64, f64) {
+	let a = (1.0 + g) * lab.a;
+	let hue = if a == 0.0 && lab.b == 0.0 {
+		0.0
+	} else {
+		lab.b.atan2(a).to_degrees().rem_euclid(360.0)
+	};
+
+	(a.hypot(lab.b), hue)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use crate::gamut::ILLUMINANT_D65;
+	use crate::lab::Lab;
+	use crate::xyz::XYZ;
+	use approx::assert_relative_eq;
+
+	/// L*, a* and b* components of a color
+	type LabTriple = (f64, f64, f64);
+
+	/// The 34 pairs of CIELAB colors and their CIEDE2000 difference from Sharma, Wu and Dalal's
+	/// test data, "The CIEDE2000 Color-Difference Formula: Implementation Notes, Supplementary
+	/// Test Data, and Mathematical Observations" (2005). They cover the hue wrap-around and mean
+	/// hue edge cases.
+	#[rustfmt::skip]
+	pub(crate) const SHARMA_2005_PAIRS: [(LabTriple, LabTriple, f64); 34] = [
+		((50.0000, 2.6772, -79.7751), (50.0000, 0.0000, -82.7485), 2.0425),
+		((50.0000, 3.1571, -77.2803), (50.0000, 0.0000, -82.7485), 2.8615),
+		((50.0000, 2.8361, -74.0200), (50.0000, 0.0000, -82.7485), 3.4412),
+		((50.0000, -1.3802, -84.2814), (50.0000, 0.0000, -82.7485), 1.0000),
+		((50.0000, -1.1848, -84.8006), (50.0000, 0.0000, -82.7485), 1.0000),
+		((50.0000, -0.9009, -85.5211), (50.0000, 0.0000, -82.7485), 1.0000),
+		((50.0000, 0.0000, 0.0000), (50.0000, -1.0000, 2.0000), 2.3669),
+		((50.0000, -1.0000, 2.0000), (50.0000, 0.0000, 0.0000), 2.3669),
+		((50.0000, 2.4900, -0.0010), (50.0000, -2.4900, 0.0009), 7.1792),
+		((50.0000, 2.4900, -0.0010), (50.0000, -2.4900, 0.0010), 7.1792),
+		((50.0000, 2.4900, -0.0010), (50.0000, -2.4900, 0.0011), 7.2195),
+		((50.0000, 2.4900, -0.0010), (50.0000, -2.4900, 0.0012), 7.2195),
+		((50.0000, -0.0010, 2.4900), (50.0000, 0.0009, -2.4900), 4.8045),
+		((50.0000, -0.0010, 2.4900), (50.0000, 0.0010, -2.4900), 4.8045),
+		((50.0000, -0.0010, 2.4900), (50.0000, 0.0011, -2.4900), 4.7461),
+		((50.0000, 2.5000, 0.0000), (50.0000, 0.0000, -2.5000), 4.3065),
+		((50.0000, 2.5000, 0.0000), (73.0000, 25.0000, -18.0000), 27.1492),
+		((50.0000, 2.5000, 0.0000), (61.0000, -5.0000, 29.0000), 22.8977),
+		((50.0000, 2.5000, 0.0000), (56.0000, -27.0000, -3.0000), 31.9030),
+		((50.0000, 2.5000, 0.0000), (58.0000, 24.0000, 15.0000), 19.4535),
+		((50.0000, 2.5000, 0.0000), (50.0000, 3.1736, 0.5854), 1.0000),
+		((50.0000, 2.5000, 0.0000), (50.0000, 3.2972, 0.0000), 1.0000),
+		((50.0000, 2.5000, 0.0000), (50.0000, 1.8634, 0.5757), 1.0000),
+		((50.0000, 2.5000, 0.0000), (50.0000, 3.2592, 0.3350), 1.0000),
+		((60.2574, -34.0099, 36.2677), (60.4626, -34.1751, 39.4387), 1.2644),
+		((63.0109, -31.0961, -5.8663), (62.8187, -29.7946, -4.0864), 1.2630),
+		((61.2901, 3.7196, -5.3901), (61.4292, 2.2480, -4.9620), 1.8731),
+		((35.0831, -44.1164, 3.7933), (35.0232, -40.0716, 1.5901), 1.8645),
+		((22.7233, 20.0904, -46.6940), (23.0331, 14.9730, -42.5619), 2.0373),
+		((36.4612, 47.8580, 18.3852), (36.2715, 50.5065, 21.2231), 1.4146),
+		((90.8027, -2.0831, 1.4410), (91.1528, -1.6435, 0.0447), 1.4441),
+		((90.9257, -0.5406, -0.9208), (88.6381, -0.8985, -0.7239), 1.5381),
+		((6.7747, -0.2908, -2.4247), (5.8714, -0.0985, -2.2286), 0.6377),
+		((2.0776, 0.0795, -1.1350), (0.9033, -0.0636, -0.5514), 0.9082),
+	];
+
+	#[test]
+	fn converts_to_and_from_xyz() {
+		let white = Lab::from(XYZ::chromaticity(ILLUMINANT_D65, 1.0));
+		assert_relative_eq!(white.L, 100.0, epsilon = 1e-9);
+		assert_relative_eq!(white.a, 0.0, epsilon = 1e-9);
+		assert_relative_eq!(white.b, 0.0, epsilon = 1e-9);
+		assert_eq!(
+			Lab::from(XYZ {
+				X: 0.0,
+				Y: 0.0,
+				Z: 0.0
+			}),
+			Lab::default()
+		);
+
+		// Both sides of the linear segment near black
+		for xyz in &[
+			XYZ {
+				X: 0.2,
+				Y: 0.3,
+				Z: 0.4,
+			},
+			XYZ {
+				X: 0.001,
+				Y: 0.002,
+				Z: 0.0005,
+			},
+		] {
+			let back = Lab::from(xyz.clone()).to_xyz();
+			assert_relative_eq!(back.X, xyz.X, epsilon = 1e-12);
+			assert_relative_eq!(back.Y, xyz.Y, epsilon = 1e-12);
+			assert_relative_eq!(back.Z, xyz.Z, epsilon = 1e-12);
+		}
+	}
+
+	#[test]
+	fn computes_color_differences() {
+		let a = Lab::new(50.0, 0.0, 0.0);
+		let b = Lab::new(53.0, 4.0, 0.0);
+		assert_eq!(a.delta_e_76(&b), 5.0);
+		assert_eq!(a.delta_e_76(&a), 0.0);
+		assert_eq!(a.delta_e_2000(&a), 0.0);
+
+		for &((l1, a1, b1), (l2, a2, b2), expected) in &SHARMA_2005_PAIRS {
+			let (x, y) = (Lab::new(l1, a1, b1), Lab::new(l2, a2, b2));
+			assert_relative_eq!(x.delta_e_2000(&y), expected, epsilon = 1e-4);
+			assert_relative_eq!(y.delta_e_2000(&x), expected, epsilon = 1e-4);
+		}
+	}
+}
@@ -1,16 +1,25 @@
 mod blackbody;
 mod color;
-mod gamut;
+pub mod gamut;
+pub mod lab;
+pub mod luv;
+pub mod spectrum;
 pub mod xyz;
 
-pub use self::color::Color;
+pub use self::color::{
+	alpha_composite, doppler_shift, redshift, require_compatible_systems, sort_by_luminance, Color,
+	ColorError,
+};
 pub use blackbody::{blackbody_spectrum, spectrum_to_xyz};
-pub use gamut::{ColorSystem, XYChroma};
+pub use gamut::{ColorSystem, TransferFunction, XYChroma};
+pub use lab::Lab;
+pub use luv::Luv;
+pub use spectrum::Spectrum;
 pub use xyz::XYZ;
 
 pub mod consts {
 	pub use crate::blackbody::CIE_COLOR_MATCH;
-	pub use crate::gamut::{ILLUMINANT_C, ILLUMINANT_D65, ILLUMINANT_E};
-	pub use crate::gamut::{SYSTEM_CIERGB, SYSTEM_SRGB};
+	pub use crate::gamut::{ILLUMINANT_C, ILLUMINANT_D50, ILLUMINANT_D65, ILLUMINANT_E};
+	pub use crate::gamut::{SYSTEM_CIERGB, SYSTEM_DCI_P3, SYSTEM_REC2020, SYSTEM_SRGB};
 
 }
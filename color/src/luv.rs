@@ -0,0 +1,133 @@
+use crate::gamut::ILLUMINANT_D65;
+use crate::lab::{transfer, transfer_inv};
+use crate::xyz::XYZ;
+
+use std::f64;
+use std::fmt;
+
+/** CIE 1976 L*u*v* color, relative to the D65 white point */
+#[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Luv {
+	/// Lightness, from 0 (black) to 100 (reference white)
+	pub L: f64,
+	/// Green (negative) to red (positive) axis
+	pub u: f64,
+	/// Blue (negative) to yellow (positive) axis
+	pub v: f64,
+}
+
+impl Luv {
+	pub fn new(l: f64, u: f64, v: f64) -> Self {
+		Luv { L: l, u, v }
+	}
+	pub fn to_xyz(&self) -> XYZ {
+		XYZ::from(*self)
+	}
+	/// Chroma, the distance from the neutral axis
+	pub fn chroma(&self) -> f64 {
+		self.u.hypot(self.v)
+	}
+	/// Hue angle, in radians from the u* axis
+	pub fn hue_angle(&self) -> f64 {
+		self.v.atan2(self.u)
+	}
+}
+
+impl From<XYZ> for Luv {
+	fn from(val: XYZ) -> Self {
+		let white = XYZ::chromaticity(ILLUMINANT_D65, 1.0);
+		let (un, vn) = uv_chromaticity(&white);
+		let l = 116.0 * transfer(val.Y / white.Y) - 16.0;
+		if l == 0.0 {
+			return Luv::default();
+		}
+		let (u, v) = uv_chromaticity(&val);
+
+		Luv {
+			L: l,
+			u: 13.0 * l * (u - un),
+			v: 13.0 * l * (v - vn),
+		}
+	}
+}
+
+impl From<Luv> for XYZ {
+	fn from(val: Luv) -> Self {
+		let white = XYZ::chromaticity(ILLUMINANT_D65, 1.0);
+		if val.L <= 0.0 {
+			return XYZ {
+				X: 0.0,
+				Y: 0.0,
+				Z: 0.0,
+			};
+		}
+		let (un, vn) = uv_chromaticity(&white);
+		let u = val.u / (13.0 * val.L) + un;
+		let v = val.v / (13.0 * val.L) + vn;
+		let y = white.Y * transfer_inv((val.L + 16.0) / 116.0);
+
+		XYZ {
+			X: y * 9.0 * u / (4.0 * v),
+			Y: y,
+			Z: y * (12.0 - 3.0 * u - 20.0 * v) / (4.0 * v),
+		}
+	}
+}
+
+impl fmt::Display for Luv {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Luv(L={}, u={}, v={})", self.L, self.u, self.v)
+	}
+}
+
+/// CIE 1976 u'v' chromaticity coordinates
+fn uv_chromaticity(xyz: &XYZ) -> (f64, f64) {
+	let denom = xyz.X + 15.0 * xyz.Y + 3.0 * xyz.Z;
+	(4.0 * xyz.X / denom, 9.0 * xyz.Y / denom)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::gamut::{ILLUMINANT_D65, SYSTEM_CIERGB};
+	use crate::luv::Luv;
+	use crate::xyz::XYZ;
+	use approx::assert_relative_eq;
+
+	#[test]
+	fn converts_to_and_from_xyz() {
+		let white = XYZ::chromaticity(ILLUMINANT_D65, 1.0).to_luv();
+		assert_relative_eq!(white.L, 100.0, epsilon = 1e-9);
+		assert_relative_eq!(white.chroma(), 0.0, epsilon = 1e-9);
+		assert_eq!(
+			Luv::from(XYZ {
+				X: 0.0,
+				Y: 0.0,
+				Z: 0.0
+			}),
+			Luv::default()
+		);
+
+		let red = XYZ::chromaticity(SYSTEM_CIERGB.red, 0.2126).to_luv();
+		// Red sits just above the u* axis
+		assert!(red.u > 0.0);
+		assert_relative_eq!(red.hue_angle(), 0.2, epsilon = 0.05);
+
+		for xyz in &[
+			XYZ::chromaticity(SYSTEM_CIERGB.green, 0.7152),
+			XYZ::chromaticity(SYSTEM_CIERGB.red, 0.2126),
+			XYZ::chromaticity(ILLUMINANT_D65, 1.0),
+			// Linear segment near black
+			XYZ {
+				X: 0.001,
+				Y: 0.002,
+				Z: 0.0005,
+			},
+		] {
+			let back = Luv::from(xyz.clone()).to_xyz();
+			assert_relative_eq!(back.X, xyz.X, epsilon = 1e-10);
+			assert_relative_eq!(back.Y, xyz.Y, epsilon = 1e-10);
+			assert_relative_eq!(back.Z, xyz.Z, epsilon = 1e-10);
+		}
+	}
+}
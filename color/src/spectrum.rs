@@ -0,0 +1,188 @@
+use crate::blackbody::{blackbody_spectrum, spectrum_to_xyz};
+use crate::xyz::XYZ;
+
+use std::fmt;
+
+/// Range of wavelengths, in nanometers, covered by the CIE color matching functions
+pub const CIE_RANGE: (f64, f64) = (380.0, 780.0);
+
+/** Spectral power distribution, sampled at evenly spaced wavelengths */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+	/// Power at each wavelength, the first sample being at `lambda_start` and the last at
+	/// `lambda_end`
+	pub samples: Vec<f64>,
+	/// Wavelength of the first sample, in nanometers
+	pub lambda_start: f64,
+	/// Wavelength of the last sample, in nanometers
+	pub lambda_end: f64,
+}
+
+impl Spectrum {
+	pub fn new(samples: Vec<f64>, lambda_start: f64, lambda_end: f64) -> Self {
+		Spectrum {
+			samples,
+			lambda_start,
+			lambda_end,
+		}
+	}
+	/// Samples `f` at `resolution` wavelengths evenly spread over `lambda_start..=lambda_end`
+	pub fn from_fn<F: Fn(f64) -> f64>(
+		f: F,
+		resolution: usize,
+		lambda_start: f64,
+		lambda_end: f64,
+	) -> Self {
+		let step = if resolution > 1 {
+			(lambda_end - lambda_start) / (resolution - 1) as f64
+		} else {
+			0.0
+		};
+		let samples = (0..resolution)
+			.map(|i| f(lambda_start + step * i as f64))
+			.collect();
+
+		Spectrum::new(samples, lambda_start, lambda_end)
+	}
+	/// Emission of a black body at the given temperature (in Kelvin), sampled over the visible
+	/// range covered by the CIE color matching functions.
+	pub fn blackbody(temperature: f64, resolution: usize) -> Self {
+		Self::from_fn(
+			|lambda| blackbody_spectrum(temperature, lambda),
+			resolution,
+			CIE_RANGE.0,
+			CIE_RANGE.1,
+		)
+	}
+	/// Distance between two samples, in nanometers
+	pub fn step(&self) -> f64 {
+		if self.samples.len() > 1 {
+			(self.lambda_end - self.lambda_start) / (self.samples.len() - 1) as f64
+		} else {
+			0.0
+		}
+	}
+	/// Power at the given wavelength, linearly interpolated between samples. The spectrum is zero
+	/// outside of its range.
+	pub fn at(&self, lambda: f64) -> f64 {
+		if self.samples.is_empty() || lambda < self.lambda_start || lambda > self.lambda_end {
+			return 0.0;
+		}
+		let step = self.step();
+		if step == 0.0 {
+			return self.samples[0];
+		}
+
+		let t = (lambda - self.lambda_start) / step;
+		let i = (t.floor() as usize).min(self.samples.len() - 1);
+		let j = (i + 1).min(self.samples.len() - 1);
+		let frac = t - i as f64;
+		self.samples[i] * (1.0 - frac) + self.samples[j] * frac
+	}
+	/// Tristimulus values of the spectrum, integrated against the CIE 1931 color matching
+	/// functions
+	pub fn to_xyz(&self) -> XYZ {
+		let (x, y, z) = spectrum_to_xyz(&|lambda| self.at(lambda));
+
+		XYZ { X: x, Y: y, Z: z }
+	}
+	/// Sum of both distributions, as emitted by both sources at once. The result covers both
+	/// ranges, at the finer of both resolutions.
+	pub fn add(&self, other: &Spectrum) -> Spectrum {
+		let start = self.lambda_start.min(other.lambda_start);
+		let end = self.lambda_end.max(other.lambda_end);
+		let step = match (self.step(), other.step()) {
+			(a, b) if a > 0.0 && b > 0.0 => a.min(b),
+			(a, b) => a.max(b),
+		};
+		let resolution = if step > 0.0 {
+			((end - start) / step).round() as usize + 1
+		} else {
+			self.samples.len().max(other.samples.len())
+		};
+
+		Self::from_fn(
+			|lambda| self.at(lambda) + other.at(lambda),
+			resolution,
+			start,
+			end,
+		)
+	}
+	/// Scales the power at every wavelength by `factor`
+	pub fn scale(&self, factor: f64) -> Spectrum {
+		Spectrum::new(
+			self.samples.iter().map(|s| s * factor).collect(),
+			self.lambda_start,
+			self.lambda_end,
+		)
+	}
+	/// Wavelength at which the most power is emitted, in nanometers
+	pub fn dominant_wavelength(&self) -> f64 {
+		let peak = self
+			.samples
+			.iter()
+			.enumerate()
+			.fold(
+				(0, f64::NEG_INFINITY),
+				|acc, (i, &s)| {
+					if s > acc.1 {
+						(i, s)
+					} else {
+						acc
+					}
+				},
+			)
+			.0;
+		self.lambda_start + self.step() * peak as f64
+	}
+}
+
+impl fmt::Display for Spectrum {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Spectrum({} samples, {}..{} nm)",
+			self.samples.len(),
+			self.lambda_start,
+			self.lambda_end
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::spectrum::Spectrum;
+	use crate::xyz::XYZ;
+	use approx::assert_relative_eq;
+
+	#[test]
+	fn matches_closure_based_blackbody() {
+		let spectrum = Spectrum::blackbody(6500.0, 81).to_xyz();
+		let expected = XYZ::blackbody(6500.0);
+		assert_relative_eq!(spectrum.X, expected.X, max_relative = 1e-2);
+		assert_relative_eq!(spectrum.Y, expected.Y, max_relative = 1e-2);
+		assert_relative_eq!(spectrum.Z, expected.Z, max_relative = 1e-2);
+
+		// Wien's displacement law puts the peak of a 5000 K black body at ~580 nm
+		assert_relative_eq!(
+			Spectrum::blackbody(5000.0, 401).dominant_wavelength(),
+			580.0,
+			epsilon = 1.0
+		);
+	}
+
+	#[test]
+	fn combines_spectra() {
+		let a = Spectrum::new(vec![1.0, 2.0, 3.0], 400.0, 500.0);
+		let b = Spectrum::new(vec![1.0, 1.0], 450.0, 550.0);
+		assert_eq!(a.at(425.0), 1.5);
+		assert_eq!(a.at(600.0), 0.0);
+
+		let sum = a.add(&b);
+		assert_eq!(sum.lambda_start, 400.0);
+		assert_eq!(sum.lambda_end, 550.0);
+		assert_eq!(sum.samples, vec![1.0, 3.0, 4.0, 1.0]);
+		assert_eq!(sum.dominant_wavelength(), 500.0);
+		assert_eq!(a.scale(2.0).samples, vec![2.0, 4.0, 6.0]);
+	}
+}
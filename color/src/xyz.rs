@@ -1,10 +1,34 @@
 use crate::blackbody::{blackbody_spectrum, spectrum_to_xyz};
 use crate::color::Color;
 use crate::gamut::{ColorSystem, XYChroma, SYSTEM_SRGB};
+use crate::lab::Lab;
+use crate::luv::Luv;
+use std::cmp::Ordering;
 use std::fmt;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
-use nalgebra::{Point3, Vector3};
+use nalgebra::{Matrix3, Point3, Vector3};
+
+/// Bradford matrix from XYZ to cone responses, in column-major order
+const BRADFORD: [[f64; 3]; 3] = [
+	[0.8951, -0.7502, 0.0389],
+	[0.2664, 1.7135, -0.0685],
+	[-0.1614, 0.0367, 1.0296],
+];
+/// Inverse of `BRADFORD`, in column-major order
+const BRADFORD_INV: [[f64; 3]; 3] = [
+	[
+		0.9869929054667123,
+		0.43230526972339456,
+		-0.008528664575177328,
+	],
+	[
+		-0.14705425642099013,
+		0.5183602715367776,
+		0.04004282165408487,
+	],
+	[0.15996265166373122, 0.0492912282128556, 0.9684866957875501],
+];
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct XYZ {
@@ -15,11 +39,14 @@ pub struct XYZ {
 
 impl XYZ {
 	pub fn chromaticity(xy: XYChroma, Y: f64) -> Self {
-		let y_ratio = Y / xy.y;
+		Self::from_xyy(xy.x, xy.y, Y)
+	}
+	pub fn from_xyy(x: f64, y: f64, Y: f64) -> Self {
+		let y_ratio = Y / y;
 		XYZ {
-			X: xy.x * y_ratio,
-			Y: Y,
-			Z: xy.get_z() * y_ratio,
+			X: x * y_ratio,
+			Y,
+			Z: (1.0 - x - y) * y_ratio,
 		}
 	}
 	pub fn from_spectral_data(f: &Fn(f64) -> f64) -> Self {
@@ -30,6 +57,27 @@ impl XYZ {
 	pub fn blackbody(temperature: f64) -> Self {
 		Self::from_spectral_data(&|y| blackbody_spectrum(temperature, y))
 	}
+	/// Chromatic adaptation with the Bradford transform: the color as seen under the `to_white`
+	/// illuminant, given it was seen under `from_white`. The cone responses are scaled by the
+	/// ratio of the responses to both white points.
+	pub fn adapt(&self, from_white: XYChroma, to_white: XYChroma) -> Self {
+		let (bradford, bradford_inv) = (Matrix3::from(BRADFORD), Matrix3::from(BRADFORD_INV));
+		let lms = |xyz: XYZ| -> Vector3<f64> { bradford * Into::<Vector3<f64>>::into(xyz) };
+		let from = lms(XYZ::chromaticity(from_white, 1.0));
+		let to = lms(XYZ::chromaticity(to_white, 1.0));
+		let scaled = lms(self.clone()).component_mul(&to.component_div(&from));
+
+		XYZ::from(bradford_inv * scaled)
+	}
+	/// Linearly interpolates between two tristimulus values. XYZ is linear in luminous power, so
+	/// this is the physically correct way of mixing light sources.
+	pub fn mix(t: f64, a: &XYZ, b: &XYZ) -> Self {
+		XYZ {
+			X: lerp(t, a.X, b.X),
+			Y: lerp(t, a.Y, b.Y),
+			Z: lerp(t, a.Z, b.Z),
+		}
+	}
 }
 
 impl XYZ {
@@ -39,6 +87,12 @@ impl XYZ {
 	pub fn to_srgb(&self) -> Result<Color, &'static str> {
 		SYSTEM_SRGB.to_rgb(self)
 	}
+	pub fn to_lab(&self) -> Lab {
+		Lab::from(self.clone())
+	}
+	pub fn to_luv(&self) -> Luv {
+		Luv::from(self.clone())
+	}
 	pub fn to_chromaticity(&self) -> (XYChroma, f64) {
 		let sum = self.X + self.Y + self.Z;
 		let chroma = XYChroma {
@@ -56,6 +110,14 @@ impl fmt::Display for XYZ {
 	}
 }
 
+/// Orders tristimulus values by luminance (`Y`) only, so colors with equal luminance but different
+/// chromaticities compare as equal.
+impl PartialOrd for XYZ {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		self.Y.partial_cmp(&other.Y)
+	}
+}
+
 impl Add<XYZ> for XYZ {
 	type Output = Self;
 	fn add(self, rhs: XYZ) -> Self {
@@ -154,7 +216,11 @@ impl From<Vector3<f64>> for XYZ {
 
 impl From<Point3<f64>> for XYZ {
 	fn from(val: Point3<f64>) -> Self {
-		XYZ::from(val.coords)
+		XYZ {
+			X: val.x,
+			Y: val.y,
+			Z: val.z,
+		}
 	}
 }
 
@@ -170,16 +236,110 @@ impl Into<Vector3<f64>> for XYZ {
 	}
 }
 
-impl Into<Point3<f64>> for XYZ {
-	fn into(self) -> Point3<f64> {
-		Point3::new(self.X, self.Y, self.Z)
+impl From<XYZ> for Point3<f64> {
+	fn from(val: XYZ) -> Self {
+		Point3::new(val.X, val.Y, val.Z)
 	}
 }
 
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+	(1.0 - t) * a + t * b
+}
+
 #[cfg(test)]
 mod tests {
 	use crate::color::Color;
-	use crate::gamut::SYSTEM_SRGB;
+	use crate::gamut::{ColorSystem, ILLUMINANT_D50, ILLUMINANT_D65, ILLUMINANT_E, SYSTEM_SRGB};
+	use crate::xyz::XYZ;
+	use approx::assert_relative_eq;
+	use nalgebra::Point3;
+
+	#[test]
+	fn converts_to_and_from_points() {
+		let xyz = XYZ {
+			X: 0.25,
+			Y: 0.5,
+			Z: 0.75,
+		};
+		let pt: Point3<f64> = xyz.clone().into();
+		assert_eq!(pt, Point3::new(0.25, 0.5, 0.75));
+		assert_eq!(XYZ::from(pt), xyz);
+	}
+
+	#[test]
+	fn sorts_blackbodies_by_luminance() {
+		let mut colors = vec![
+			XYZ::blackbody(10000.0),
+			XYZ::blackbody(1000.0),
+			XYZ::blackbody(5000.0),
+		];
+		colors.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		assert_eq!(
+			colors,
+			vec![
+				XYZ::blackbody(1000.0),
+				XYZ::blackbody(5000.0),
+				XYZ::blackbody(10000.0)
+			]
+		);
+		assert!(colors[0] < colors[1] && colors[1] < colors[2]);
+	}
+
+	#[test]
+	fn chromaticity_matches_xyy() {
+		assert_eq!(
+			XYZ::chromaticity(ILLUMINANT_D65, 0.8),
+			XYZ::from_xyy(ILLUMINANT_D65.x, ILLUMINANT_D65.y, 0.8)
+		);
+	}
+
+	#[test]
+	fn mixes_tristimulus_values() {
+		let white = XYZ::chromaticity(ILLUMINANT_E, 1.0);
+		for &t in &[0.0, 0.3, 0.5, 1.0] {
+			let mixed = XYZ::mix(t, &white, &white);
+			assert_relative_eq!(mixed.X, white.X, epsilon = 1e-12);
+			assert_relative_eq!(mixed.Y, white.Y, epsilon = 1e-12);
+			assert_relative_eq!(mixed.Z, white.Z, epsilon = 1e-12);
+		}
+
+		let black = XYZ::from_xyy(ILLUMINANT_E.x, ILLUMINANT_E.y, 0.0);
+		assert_relative_eq!(XYZ::mix(0.25, &black, &white).Y, 0.25, epsilon = 1e-12);
+	}
+
+	#[test]
+	fn adapts_between_white_points() {
+		let d65 = XYZ::chromaticity(ILLUMINANT_D65, 1.0);
+		let d50 = XYZ::chromaticity(ILLUMINANT_D50, 1.0);
+		let adapted = d65.adapt(ILLUMINANT_D65, ILLUMINANT_D50);
+		assert_relative_eq!(adapted.X, d50.X, epsilon = 1e-9);
+		assert_relative_eq!(adapted.Y, d50.Y, epsilon = 1e-9);
+		assert_relative_eq!(adapted.Z, d50.Z, epsilon = 1e-9);
+
+		let d50_srgb = ColorSystem {
+			white: ILLUMINANT_D50,
+			..SYSTEM_SRGB
+		};
+		let white = Color::with_system(1.0, 1.0, 1.0, SYSTEM_SRGB);
+		let converted = SYSTEM_SRGB.convert_to(&white, &d50_srgb).unwrap();
+		// White stays white in a system with the target white point
+		assert_relative_eq!(converted.red, 1.0, epsilon = 1e-6);
+		assert_relative_eq!(converted.green, 1.0, epsilon = 1e-6);
+		assert_relative_eq!(converted.blue, 1.0, epsilon = 1e-6);
+		let back = d50_srgb.convert_to(&converted, &SYSTEM_SRGB).unwrap();
+		assert_relative_eq!(back.red, 1.0, epsilon = 1e-6);
+		assert_relative_eq!(back.green, 1.0, epsilon = 1e-6);
+		assert_relative_eq!(back.blue, 1.0, epsilon = 1e-6);
+
+		let col = Color::with_system(0.2, 0.6, 0.9, SYSTEM_SRGB);
+		let back = SYSTEM_SRGB
+			.convert_to(&col, &d50_srgb)
+			.and_then(|c| d50_srgb.convert_to(&c, &SYSTEM_SRGB))
+			.unwrap();
+		assert_relative_eq!(back.red, col.red, epsilon = 1e-6);
+		assert_relative_eq!(back.green, col.green, epsilon = 1e-6);
+		assert_relative_eq!(back.blue, col.blue, epsilon = 1e-6);
+	}
 
 	#[test]
 	fn conversion_to_xyz_and_back() {
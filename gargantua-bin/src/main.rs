@@ -1,12 +1,17 @@
 use image::{DynamicImage, Pixel, Rgb};
-use nalgebra::{Translation3, UnitQuaternion, Vector3};
-use rand::Rng;
+use nalgebra::{Quaternion, Translation3, UnitQuaternion, Vector3};
 use regex::Regex;
+use serde::Deserialize;
 
-use gargantua::raytrace::render::render;
-use gargantua::raytrace::Point;
-use gargantua::{Camera, GRScene, Ring, Scene, Sphere, Texture, TextureFiltering, TextureMode};
+use color::Color;
+use gargantua::raytrace::render::{render_hdr, render_with_config, render_with_pool, Reporter};
+use gargantua::{
+	Camera, GRScene, RenderConfig, RenderError, Ring, Scene, Sphere, Texture, TextureFiltering,
+	TextureMode,
+};
 
+use std::fs;
+use std::path::Path;
 use std::{f64, u32};
 
 enum SpaceTime {
@@ -14,19 +19,42 @@ enum SpaceTime {
 	Schwardzchild,
 }
 
-fn create_bg_texture() -> Texture {
-	let mut img = DynamicImage::new_rgb8(512, 256);
-	let mut rng = rand::thread_rng();
-	for (_x, _y, p) in img.as_mut_rgb8().unwrap().enumerate_pixels_mut() {
-		let mut val = rng.gen_range(0.0, 1.0);
-		if val > 0.9 {
-			val = (val - 0.9) / 0.1;
-		}
-		let col = (val * 255.0) as u8;
-		*p = Rgb::from_channels(col, col, col, 255);
+/// Pose of the camera at a frame of an animation. The rotation is a quaternion, as
+/// `[x, y, z, w]`, and the field of view is vertical, in degrees.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct Keyframe {
+	frame: u32,
+	position: [f64; 3],
+	rotation: [f64; 4],
+	fov: f64,
+}
+
+impl Keyframe {
+	fn position(&self) -> Vector3<f64> {
+		Vector3::from(self.position)
 	}
 
-	return Texture(img, TextureFiltering::Nearest, TextureMode::Repeat);
+	/// `base` moved to the pose of the keyframe
+	fn camera(&self, base: &Camera) -> Camera {
+		let [x, y, z, w] = self.rotation;
+		let mut camera = base.clone();
+		camera.set_position(Translation3::from(self.position()));
+		camera.set_rotation(UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)));
+		camera.perspective.set_fovy(self.fov.to_radians());
+		camera
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+	/// 8-bit image, in the format given by the file extension
+	Png,
+	/// 32-bit float OpenEXR image, keeping the full range of the render
+	Exr,
+}
+
+fn create_bg_texture() -> Texture {
+	return Texture::generate_star_field(512, 256, 0.002, 0);
 }
 
 fn create_sphere_texture() -> Texture {
@@ -39,23 +67,15 @@ fn create_sphere_texture() -> Texture {
 		};
 	}
 
-	return Texture(img, TextureFiltering::Nearest, TextureMode::Repeat);
+	return Texture::new(img, TextureFiltering::Nearest, TextureMode::Repeat);
 }
 
 fn setup_scene_flat(w: u32, h: u32) -> Scene {
 	let mut scn = Scene {
 		camera: Camera::new(w, h, 30.0),
-		sphere: Sphere {
-			pos: Point::new(0.0, 0.0, 0.0),
-			radius: 1.0,
-			texture: create_sphere_texture(),
-		},
-		ring: Ring {
-			pos: Point::new(0.0, 0.0, 0.0),
-			radius: (2.0, 3.0),
-			texture_top: create_sphere_texture(),
-			texture_bottom: create_sphere_texture(),
-		},
+		sphere: Sphere::new(1.0, create_sphere_texture()),
+		objects: vec![],
+		ring: Ring::new(2.0, 3.0, create_sphere_texture()),
 		bgtex: Some(create_bg_texture()),
 	};
 	scn.set_camera(
@@ -71,7 +91,12 @@ fn setup_scene_flat(w: u32, h: u32) -> Scene {
 }
 
 fn setup_scene_gr(w: u32, h: u32) -> GRScene {
-	GRScene(setup_scene_flat(w, h), 0.16, 500)
+	// The default tolerance is too strict this far from the black hole for geodesics to reach it
+	// within the budget
+	GRScene {
+		tolerance: 0.1,
+		..GRScene::new(setup_scene_flat(w, h), 0.16, 2000)
+	}
 }
 
 fn main() {
@@ -93,6 +118,62 @@ fn main() {
 				.short("q")
 				.help("Quiet output (no progress readout)"),
 		)
+		.arg(
+			clap::Arg::with_name("samples")
+				.long("samples")
+				.value_name("N")
+				.help("Number of rays averaged per pixel, for antialiasing")
+				.takes_value(true),
+		)
+		.arg(
+			clap::Arg::with_name("threads")
+				.short("j")
+				.long("threads")
+				.value_name("N")
+				.help("Number of render threads (defaults to one per CPU)")
+				.takes_value(true),
+		)
+		.arg(
+			clap::Arg::with_name("chunk-size")
+				.long("chunk-size")
+				.value_name("PIXELS")
+				.help("Side length of the square tiles rendered by each thread")
+				.takes_value(true),
+		)
+		.arg(
+			clap::Arg::with_name("format")
+				.long("format")
+				.value_name("FORMAT")
+				.help("Output format, exr keeps full precision colors")
+				.possible_values(&["png", "exr"])
+				.default_value("png"),
+		)
+		.arg(
+			clap::Arg::with_name("scene")
+				.long("scene")
+				.value_name("FILE")
+				.help("Renders the GRScene of a JSON or TOML file instead of the built-in one")
+				.takes_value(true),
+		)
+		.arg(
+			clap::Arg::with_name("normals")
+				.long("normals")
+				.help("Renders surface normals (in flat spacetime) instead of colors"),
+		)
+		.arg(
+			clap::Arg::with_name("animation")
+				.long("animation")
+				.value_name("FILE")
+				.help("Renders the camera moving through the keyframes of a JSON file, one PNG per frame")
+				.takes_value(true),
+		)
+		.arg(
+			clap::Arg::with_name("out-dir")
+				.long("out-dir")
+				.value_name("DIR")
+				.help("Directory the frames of an animation are saved to")
+				.default_value("frames"),
+		)
 		.subcommand(
 			clap::SubCommand::with_name("flat").about("Renders a black hole in flat spacetime"),
 		)
@@ -109,7 +190,7 @@ fn main() {
 	} else {
 		SpaceTime::Schwardzchild
 	};
-	let (width, height) = matches
+	let size = matches
 		.value_of("size")
 		.and_then(|s| size_re.captures(s))
 		.map(|c| {
@@ -117,19 +198,98 @@ fn main() {
 				u32::from_str_radix(&c[1], 10).expect("Couldn't parse width"),
 				u32::from_str_radix(&c[2], 10).expect("Couldn't parse height"),
 			)
-		})
-		.unwrap_or((640u32, 360u32));
+		});
+	let scene = match matches.value_of("scene") {
+		Some(path) => {
+			let mut scene = load_scene(path);
+			if let Some((width, height)) = size {
+				scene.scene.set_size(width, height);
+			}
+			scene
+		}
+		None => {
+			let (width, height) = size.unwrap_or((640u32, 360u32));
+			setup_scene_gr(width, height)
+		}
+	};
+	let defaults = RenderConfig::default();
+	let config = RenderConfig {
+		chunk_size: matches
+			.value_of("chunk-size")
+			.map(|s| s.parse().expect("Couldn't parse chunk size"))
+			.unwrap_or(defaults.chunk_size),
+		num_threads: matches
+			.value_of("threads")
+			.map(|s| s.parse().expect("Couldn't parse number of threads")),
+		samples: matches
+			.value_of("samples")
+			.map(|s| s.parse().expect("Couldn't parse number of samples"))
+			.unwrap_or(defaults.samples),
+		..defaults
+	};
+
+	let format = match matches.value_of("format") {
+		Some("exr") => OutputFormat::Exr,
+		_ => OutputFormat::Png,
+	};
+	let default_output = match format {
+		OutputFormat::Png => "output.png",
+		OutputFormat::Exr => "output.exr",
+	};
+
+	if let Some(path) = matches.value_of("animation") {
+		if format == OutputFormat::Exr {
+			eprintln!("Animations can only be saved as PNG frames");
+			std::process::exit(1);
+		}
+		run_animation(
+			&st_type,
+			scene,
+			&load_keyframes(path),
+			matches.value_of("out-dir").unwrap_or("frames"),
+			matches.is_present("quiet"),
+			matches.is_present("normals"),
+			config,
+		);
+		return;
+	}
 
 	run(
 		st_type,
-		matches.value_of("output").unwrap_or("output.png"),
-		width,
-		height,
+		scene,
+		matches.value_of("OUT").unwrap_or(default_output),
+		format,
 		matches.is_present("quiet"),
+		matches.is_present("normals"),
+		config,
 	);
 }
 
-fn run(st_type: SpaceTime, output: &str, width: u32, height: u32, quiet: bool) {
+/// Renders an 8-bit image of the scene, in the given spacetime
+fn render_image(
+	st_type: &SpaceTime,
+	scene: GRScene,
+	normals: bool,
+	config: RenderConfig,
+	reporter: Option<Reporter>,
+) -> Result<DynamicImage, RenderError> {
+	match st_type {
+		_ if normals => scene.scene.render_normal_map(config),
+		SpaceTime::Flat => render_with_config(scene.scene, config, reporter),
+		SpaceTime::Schwardzchild => scene.render(config, reporter),
+	}
+}
+
+fn run(
+	st_type: SpaceTime,
+	scene: GRScene,
+	output: &str,
+	format: OutputFormat,
+	quiet: bool,
+	normals: bool,
+	config: RenderConfig,
+) {
+	let (width, height) = (scene.scene.camera.width, scene.scene.camera.height);
 	if !quiet {
 		println!("Rendering a {:?} image to {}", (width, height), output);
 	}
@@ -137,18 +297,24 @@ fn run(st_type: SpaceTime, output: &str, width: u32, height: u32, quiet: bool) {
 	let report =
 		|p: f64, msg: String| print!("{}               \r", progressbar(30, Some(start), p, msg));
 
-	match st_type {
-		SpaceTime::Flat => {
-			let scene = setup_scene_flat(width, height);
-			render(scene, if quiet { None } else { Some(&report) })
-				.map(|i| i.save(output).expect("Error saving image"))
-				.expect("Error rendering image");
+	let reporter: Option<Reporter> = if quiet { None } else { Some(&report) };
+	if format == OutputFormat::Exr {
+		if normals {
+			eprintln!("Normal maps can only be saved as 8-bit images");
+			std::process::exit(1);
 		}
-		SpaceTime::Schwardzchild => {
-			let scene = setup_scene_gr(width, height);
-			render(scene, if quiet { None } else { Some(&report) })
-				.map(|i| i.save(output).expect("Error saving image"))
-				.expect("Error rendering image");
+		let result = match st_type {
+			SpaceTime::Flat => render_hdr(scene.scene, config, reporter),
+			SpaceTime::Schwardzchild => render_hdr(scene, config, reporter),
+		};
+		save_exr(output, width, height, result);
+	} else {
+		match render_image(&st_type, scene, normals, config, reporter) {
+			Ok(img) => img.save(output).expect("Error saving image"),
+			Err(err) => {
+				eprintln!("Error rendering image: {}", err);
+				std::process::exit(1);
+			}
 		}
 	}
 
@@ -169,6 +335,190 @@ fn run(st_type: SpaceTime, output: &str, width: u32, height: u32, quiet: bool) {
 	print!("\n");
 }
 
+/// Renders every frame from the first keyframe to the last into `out_dir`, as
+/// `frame_0000.png` and so on, numbered like the keyframes
+fn run_animation(
+	st_type: &SpaceTime,
+	scene: GRScene,
+	keyframes: &[Keyframe],
+	out_dir: &str,
+	quiet: bool,
+	normals: bool,
+	config: RenderConfig,
+) {
+	if let Err(err) = fs::create_dir_all(out_dir) {
+		eprintln!("Error creating {}: {}", out_dir, err);
+		std::process::exit(1);
+	}
+	let (first, last) = (keyframes[0].frame, keyframes[keyframes.len() - 1].frame);
+	let count = last - first + 1;
+	if !quiet {
+		println!("Rendering {} frames to {}", count, out_dir);
+	}
+	let pool = match config.thread_pool() {
+		Ok(pool) => pool,
+		Err(err) => {
+			eprintln!("Error rendering animation: {}", err);
+			std::process::exit(1);
+		}
+	};
+	let start = std::time::Instant::now();
+	let report =
+		|p: f64, msg: String| print!("{}               \r", progressbar(30, Some(start), p, msg));
+
+	for frame in first..=last {
+		let done = (frame - first) as f64;
+		let frame_report = |p: f64, msg: String| {
+			report(
+				(done + p) / count as f64,
+				format!("Frame {}/{}: {}", done + 1.0, count, msg),
+			)
+		};
+		let reporter: Option<Reporter> = if quiet { None } else { Some(&frame_report) };
+
+		let mut frame_scene = scene.clone();
+		frame_scene.scene.camera = camera_at(&scene.scene.camera, keyframes, frame);
+		let output = Path::new(out_dir).join(format!("frame_{:04}.png", frame));
+		// The pool is built once for the whole animation
+		let rendered = match st_type {
+			_ if normals => frame_scene
+				.scene
+				.render_normal_map_with_pool(&pool, config.clone()),
+			SpaceTime::Flat => render_with_pool(frame_scene.scene, &pool, config.clone(), reporter),
+			SpaceTime::Schwardzchild => {
+				frame_scene.render_with_pool(&pool, config.clone(), reporter)
+			}
+		};
+		match rendered {
+			Ok(img) => img.save(&output).expect("Error saving image"),
+			Err(err) => {
+				eprintln!("Error rendering frame {}: {}", frame, err);
+				std::process::exit(1);
+			}
+		}
+	}
+
+	let done = format!(
+		"Done in {:.2} s.",
+		start.elapsed().as_millis() as f64 / 1000.0
+	);
+	if quiet {
+		println!("{}", done);
+	} else {
+		report(1.0, done);
+		println!();
+	}
+}
+
+/// Camera of the animation at the given frame. Positions follow a cubic Hermite spline through
+/// the keyframes, with Catmull-Rom tangents so the camera keeps moving through them, while the
+/// rest of the pose is interpolated by `Camera::interpolate`. Frames before the first keyframe
+/// or after the last one hold it.
+fn camera_at(base: &Camera, keyframes: &[Keyframe], frame: u32) -> Camera {
+	let i = keyframes
+		.iter()
+		.rposition(|k| k.frame <= frame)
+		.unwrap_or(0);
+	if i + 1 >= keyframes.len() || frame < keyframes[i].frame {
+		return keyframes[i].camera(base);
+	}
+	let (k0, k1) = (&keyframes[i], &keyframes[i + 1]);
+	let span = (k1.frame - k0.frame) as f64;
+	let t = (frame - k0.frame) as f64 / span;
+
+	// Tangents along the curve, scaled from per-frame velocities to the segment
+	let tangent = |j: usize| {
+		let (prev, next) = (
+			&keyframes[j.saturating_sub(1)],
+			&keyframes[(j + 1).min(keyframes.len() - 1)],
+		);
+		(next.position() - prev.position()) / (next.frame - prev.frame) as f64 * span
+	};
+	let (t2, t3) = (t * t, t * t * t);
+	let position = k0.position() * (2.0 * t3 - 3.0 * t2 + 1.0)
+		+ tangent(i) * (t3 - 2.0 * t2 + t)
+		+ k1.position() * (-2.0 * t3 + 3.0 * t2)
+		+ tangent(i + 1) * (t3 - t2);
+
+	let mut camera = Camera::interpolate(&k0.camera(base), &k1.camera(base), t);
+	camera.set_position(Translation3::from(position));
+	camera
+}
+
+/// Reads the keyframes of an animation from a JSON file, exiting on failure
+fn load_keyframes(path: &str) -> Vec<Keyframe> {
+	let result = fs::read_to_string(path)
+		.map_err(|err| err.to_string())
+		.and_then(|contents| parse_keyframes(&contents));
+	match result {
+		Ok(keyframes) => keyframes,
+		Err(err) => {
+			eprintln!("Error loading animation {}: {}", path, err);
+			std::process::exit(1);
+		}
+	}
+}
+
+/// Parses a JSON array of keyframes, sorting them by frame
+fn parse_keyframes(contents: &str) -> Result<Vec<Keyframe>, String> {
+	let mut keyframes: Vec<Keyframe> =
+		serde_json::from_str(contents).map_err(|err| err.to_string())?;
+	keyframes.sort_by_key(|k| k.frame);
+	if keyframes.is_empty() {
+		return Err("No keyframes".to_string());
+	}
+	if let Some(k) = keyframes.windows(2).find(|k| k[0].frame == k[1].frame) {
+		return Err(format!("Several keyframes for frame {}", k[0].frame));
+	}
+
+	Ok(keyframes)
+}
+
+/// Reads a scene from a JSON or TOML file, exiting on failure. The file describes a `GRScene`,
+/// whose geodesic settings are ignored when rendering in flat spacetime.
+fn load_scene(path: &str) -> GRScene {
+	let result = fs::read_to_string(path)
+		.map_err(|err| err.to_string())
+		.and_then(|contents| parse_scene(path, &contents));
+	match result {
+		Ok(scene) => scene,
+		Err(err) => {
+			eprintln!("Error loading scene {}: {}", path, err);
+			std::process::exit(1);
+		}
+	}
+}
+
+/// Parses a scene in the format given by the extension of its file name
+fn parse_scene(path: &str, contents: &str) -> Result<GRScene, String> {
+	match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+		Some("json") => serde_json::from_str(contents).map_err(|err| err.to_string()),
+		Some("toml") => toml::from_str(contents).map_err(|err| err.to_string()),
+		_ => Err("Unknown scene format, expected a .json or .toml file".to_string()),
+	}
+}
+
+/// Saves the rendered pixels as RGBA 32-bit float channels, in linear light
+fn save_exr(
+	output: &str,
+	width: u32,
+	height: u32,
+	result: Result<Vec<(u32, u32, Color)>, RenderError>,
+) {
+	let pixels: Vec<[f64; 4]> = match result {
+		Ok(pixels) => pixels.into_iter().map(|(_, _, col)| col.into()).collect(),
+		Err(err) => {
+			eprintln!("Error rendering image: {}", err);
+			std::process::exit(1);
+		}
+	};
+	exr::prelude::write_rgba_file(output, width as usize, height as usize, |x, y| {
+		let px = pixels[y * width as usize + x];
+		(px[0] as f32, px[1] as f32, px[2] as f32, px[3] as f32)
+	})
+	.expect("Error saving image");
+}
+
 fn progressbar(width: u8, start: Option<std::time::Instant>, p: f64, msg: String) -> String {
 	let filled = (width as f64 * p).round() as u8;
 	(0..filled)
@@ -191,3 +541,86 @@ fn progressbar(width: u8, start: Option<std::time::Instant>, p: f64, msg: String
 		})
 		.collect()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{camera_at, parse_keyframes, parse_scene};
+
+	use gargantua::{Camera, GRScene};
+	use nalgebra::Vector3;
+
+	const EXAMPLE_SCENE: &str = include_str!("../examples/scene.toml");
+	const EXAMPLE_ANIMATION: &str = include_str!("../examples/orbit.json");
+
+	fn to_json(scene: &GRScene) -> serde_json::Value {
+		serde_json::to_value(scene).unwrap()
+	}
+
+	#[test]
+	fn parses_example_scene() {
+		let scene = parse_scene("scene.toml", EXAMPLE_SCENE).unwrap();
+		assert_eq!(
+			(scene.scene.camera.width, scene.scene.camera.height),
+			(640, 360)
+		);
+		assert_eq!(scene.scene.ring.radius, (2.0, 3.0));
+		assert_eq!(scene.dt, 0.16);
+		assert_eq!(scene.max_evals, 2000);
+		assert!(parse_scene("scene.yaml", EXAMPLE_SCENE).is_err());
+	}
+
+	#[test]
+	fn converts_between_json_and_toml() {
+		let scene = parse_scene("scene.toml", EXAMPLE_SCENE).unwrap();
+		let json = serde_json::to_string(&scene).unwrap();
+		let from_json = parse_scene("scene.json", &json).unwrap();
+		assert_eq!(to_json(&from_json), to_json(&scene));
+
+		// Going through a `toml::Value` emits the plain values of tables before their subtables
+		let toml = toml::to_string(&toml::Value::try_from(&from_json).unwrap()).unwrap();
+		let from_toml = parse_scene("scene.toml", &toml).unwrap();
+		assert_eq!(to_json(&from_toml), to_json(&scene));
+	}
+
+	#[test]
+	fn interpolates_keyframes() {
+		let keyframes = parse_keyframes(EXAMPLE_ANIMATION).unwrap();
+		assert_eq!(keyframes.first().unwrap().frame, 0);
+		let base = Camera::new(64, 36, 30.0);
+		for k in &keyframes {
+			let camera = camera_at(&base, &keyframes, k.frame);
+			assert_eq!(camera.isometry, k.camera(&base).isometry);
+		}
+		// Held before the first keyframe and after the last one
+		let last = keyframes.last().unwrap();
+		let after = camera_at(&base, &keyframes, last.frame + 10);
+		assert_eq!(after.isometry, last.camera(&base).isometry);
+
+		// Evenly spaced keyframes on a line are followed at constant speed
+		let line = parse_keyframes(
+			r#"[
+				{"frame": 10, "position": [0, 0, 0], "rotation": [0, 0, 0, 1], "fov": 30},
+				{"frame": 0, "position": [-2, 0, 0], "rotation": [0, 0, 0, 1], "fov": 30},
+				{"frame": 20, "position": [2, 0, 0], "rotation": [0, 0, 0, 1], "fov": 60}
+			]"#,
+		)
+		.unwrap();
+		assert_eq!(line[0].frame, 0);
+		for frame in 0..=20 {
+			let camera = camera_at(&base, &line, frame);
+			let expected = Vector3::new(-2.0 + 0.2 * frame as f64, 0.0, 0.0);
+			assert!((camera.isometry.translation.vector - expected).norm() < 1e-9);
+		}
+		let fov = camera_at(&base, &line, 15).perspective.fovy().to_degrees();
+		assert!((fov - 45.0).abs() < 1e-9);
+
+		assert!(parse_keyframes("[]").is_err());
+		assert!(parse_keyframes(
+			r#"[
+				{"frame": 1, "position": [0, 0, 0], "rotation": [0, 0, 0, 1], "fov": 30},
+				{"frame": 1, "position": [1, 0, 0], "rotation": [0, 0, 0, 1], "fov": 30}
+			]"#
+		)
+		.is_err());
+	}
+}
@@ -5,7 +5,9 @@ use criterion::{Criterion, ParameterizedBenchmark};
 
 use image::{DynamicImage, Pixel, Rgb};
 use nalgebra::{Translation3};
-use gargantua::{render, Camera, GRScene, Scene, Sphere, Texture, TextureFiltering, TextureMode};
+use gargantua::{
+	render, Camera, GRScene, Ring, Scene, Sphere, Texture, TextureFiltering, TextureMode,
+};
 use gargantua::raytrace::{Point};
 
 use rand::Rng;
@@ -21,7 +23,7 @@ fn create_bg_texture() -> Texture {
 		};
 	}
 
-	return Texture(tex, TextureFiltering::Bilinear, TextureMode::Repeat);
+	return Texture::new(tex, TextureFiltering::Bilinear, TextureMode::Repeat);
 }
 
 fn create_sphere_texture() -> Texture {
@@ -34,7 +36,7 @@ fn create_sphere_texture() -> Texture {
 		};
 	}
 
-	return Texture(tex, TextureFiltering::Nearest, TextureMode::Repeat);
+	return Texture::new(tex, TextureFiltering::Nearest, TextureMode::Repeat);
 }
 
 fn setup_scene_flat(w: u32, h: u32) -> Scene {
@@ -46,11 +48,8 @@ fn setup_scene_flat(w: u32, h: u32) -> Scene {
 			radius: 1.0,
 			texture: create_sphere_texture(),
 		},
-		ring: Ring {
-			pos: Point::new(0.0, 0.0, 0.0),
-			radius: (2.0, 3.0),
-			texture: create_sphere_texture()
-		}
+		objects: vec![],
+		ring: Ring::new(2.0, 3.0, create_sphere_texture()),
 	};
 
 	scn.set_camera(Some(Translation3::new(0.0, 0.0, 20.0)), None, None);
@@ -59,7 +58,7 @@ fn setup_scene_flat(w: u32, h: u32) -> Scene {
 }
 
 fn setup_scene_gr(max_iter: u32) -> GRScene {
-	GRScene(setup_scene_flat(100, 100), 0.16, max_iter)
+	GRScene::new(setup_scene_flat(100, 100), 0.16, max_iter)
 }
 
 fn crit_bench_flat(c: &mut Criterion) {
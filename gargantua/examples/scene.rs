@@ -22,18 +22,18 @@ fn main() {
             }
         }
     }
+    let texture = Texture::new(texture, TextureFiltering::Nearest, TextureMode::Repeat);
     let mut scn = Scene {
         camera: Camera::new(500, 500, 45.0),
-        sphere: Sphere {
-            pos: Point::new(0.0, 0.0, 0.0),
-            radius: 1.0,
-            texture: Texture(texture, TextureFiltering::Nearest, TextureMode::Repeat),
-        },
+        sphere: Sphere::new(1.0, texture.clone()).with_position(Point::new(0.0, 0.0, 0.0)),
+        objects: vec![],
         ring: Ring {
             pos: Point::new(0.0, 0.0, 0.0),
             radius: (2.0, 3.0),
-            texture_top: Texture(texture, TextureFiltering::Nearest, TextureMode::Repeat),
-            texture_bottom: Texture(texture, TextureFiltering::Nearest, TextureMode::Repeat)
+            texture_top: texture.clone(),
+            texture_bottom: texture,
+            angular_velocity: 0.0,
+            mipmaps: None,
         },
         bgtex: None,
     };
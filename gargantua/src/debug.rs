@@ -0,0 +1,152 @@
+use crate::raytrace::{Point, Ray, Vector};
+use crate::schwardzchild::{GRParticle, GRScene};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use nalgebra::Unit;
+
+use std::f64::consts::PI;
+use std::ops::Range;
+
+const PATH_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const HORIZON_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const PHOTON_SPHERE_COLOR: Rgba<u8> = Rgba([255, 200, 0, 255]);
+
+/// Renders photon paths around the black hole, seen from above (looking down the z axis), to help
+/// tune the timestep, evaluation budget and tolerance settings of the scene.
+///
+/// `num_rays` rays are fired from the left towards +x, evenly spaced over the impact parameters.
+/// The sphere of the scene is drawn as the event horizon, along with the photon sphere at 1.5
+/// times its radius.
+pub fn render_geodesic_cross_section(
+	gr_scene: &GRScene,
+	num_rays: u32,
+	impact_params: Range<f64>,
+	image_size: u32,
+) -> DynamicImage {
+	let sphere = &gr_scene.scene.sphere;
+	let extent = 2.0
+		* impact_params
+			.start
+			.abs()
+			.max(impact_params.end.abs())
+			.max(2.0 * sphere.radius);
+	let to_pixel = |p: &Point| {
+		let scale = image_size as f64 / (2.0 * extent);
+		(
+			(p.x - sphere.pos.x + extent) * scale,
+			(extent - (p.y - sphere.pos.y)) * scale,
+		)
+	};
+
+	let mut buf = RgbaImage::from_pixel(image_size, image_size, Rgba([0, 0, 0, 255]));
+	draw_circle(
+		&mut buf,
+		to_pixel,
+		&sphere.pos,
+		sphere.radius,
+		HORIZON_COLOR,
+	);
+	draw_circle(
+		&mut buf,
+		to_pixel,
+		&sphere.pos,
+		1.5 * sphere.radius,
+		PHOTON_SPHERE_COLOR,
+	);
+
+	let last = num_rays.saturating_sub(1).max(1) as f64;
+	for i in 0..num_rays {
+		let b = impact_params.start + (impact_params.end - impact_params.start) * i as f64 / last;
+		let ray = Ray {
+			origin: sphere.pos + Vector::new(-extent, b, 0.0),
+			direction: Vector::x_axis(),
+		};
+		let path = GRParticle::from_ray(&ray, gr_scene.dt)
+			.with_tolerance(gr_scene.tolerance)
+			.with_bodies(&gr_scene.bodies)
+			.with_integrator(gr_scene.integrator)
+			.trace_path(sphere, gr_scene.max_evals);
+		for seg in path.windows(2) {
+			draw_line(&mut buf, to_pixel(&seg[0]), to_pixel(&seg[1]), PATH_COLOR);
+		}
+	}
+
+	DynamicImage::ImageRgba8(buf)
+}
+
+fn draw_circle<F: Fn(&Point) -> (f64, f64)>(
+	buf: &mut RgbaImage,
+	to_pixel: F,
+	center: &Point,
+	radius: f64,
+	col: Rgba<u8>,
+) {
+	const SEGMENTS: u32 = 128;
+	let point = |i: u32| {
+		let angle = 2.0 * PI * i as f64 / SEGMENTS as f64;
+		let dir = Unit::new_normalize(Vector::new(angle.cos(), angle.sin(), 0.0));
+		to_pixel(&(center + dir.as_ref() * radius))
+	};
+	for i in 0..SEGMENTS {
+		draw_line(buf, point(i), point(i + 1), col);
+	}
+}
+
+pub(crate) fn draw_line(buf: &mut RgbaImage, from: (f64, f64), to: (f64, f64), col: Rgba<u8>) {
+	let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+	// Paths leaving the view can end up very far away, only draw what could be visible
+	let steps = dx.abs().max(dy.abs()).ceil().min(4.0 * buf.width() as f64) as u32;
+	for i in 0..=steps {
+		let t = if steps == 0 {
+			0.0
+		} else {
+			i as f64 / steps as f64
+		};
+		let (x, y) = (from.0 + t * dx, from.1 + t * dy);
+		if x >= 0.0 && y >= 0.0 && (x as u32) < buf.width() && (y as u32) < buf.height() {
+			buf.put_pixel(x as u32, y as u32, col);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{render_geodesic_cross_section, HORIZON_COLOR, PATH_COLOR};
+
+	use crate::{Camera, GRScene, Ring, Scene, Sphere, Texture, TextureFiltering, TextureMode};
+	use image::{DynamicImage, Rgba};
+
+	#[test]
+	fn draws_horizon_and_paths() {
+		let tex = Texture::new(
+			DynamicImage::new_rgb8(1, 1),
+			TextureFiltering::Nearest,
+			TextureMode::Clamp,
+		);
+		let scene = GRScene {
+			tolerance: 0.1,
+			..GRScene::new(
+				Scene {
+					camera: Camera::new(1, 1, 30.0),
+					sphere: Sphere::new(1.0, tex.clone()),
+					objects: vec![],
+					ring: Ring::new(2.0, 3.0, tex),
+					bgtex: None,
+				},
+				0.1,
+				80,
+			)
+		};
+		let img = render_geodesic_cross_section(&scene, 3, -4.0..4.0, 64);
+		let buf = img.as_rgba8().unwrap();
+
+		assert_eq!(buf.dimensions(), (64, 64));
+		// The view spans [-8, 8] on both axes: the horizon crosses the x axis at x = 1
+		assert_eq!(*buf.get_pixel(36, 32), HORIZON_COLOR);
+		assert_eq!(*buf.get_pixel(32, 32), Rgba([0, 0, 0, 255]));
+		// Every ray starts at the left edge, at its impact parameter
+		assert_eq!(*buf.get_pixel(0, 48), PATH_COLOR);
+		assert_eq!(*buf.get_pixel(0, 32), PATH_COLOR);
+		assert_eq!(*buf.get_pixel(0, 16), PATH_COLOR);
+	}
+}
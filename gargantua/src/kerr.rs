@@ -0,0 +1,205 @@
+use crate::raytrace::{PixelAovs, Point, Ray, RayHit, Renderable, Scene, Sphere, Vector};
+use crate::schwardzchild::{GRParticle, GRScene};
+
+use color::Color;
+use nalgebra::Vector2;
+
+/// Particle following a geodesic around a rotating black hole, spinning about the z axis
+pub struct KerrParticle {
+	particle: GRParticle,
+	/// Dimensionless angular momentum `a/M` of the black hole, in 0..1
+	pub spin: f64,
+}
+
+/// Scene around a rotating black hole: the Schwarzschild scene and the spin of the black hole
+/// (see `KerrParticle::spin`). The black hole at the center of the sphere spins about the z axis,
+/// the normal of the ring, while the other bodies of the scene bend geodesics like in `GRScene`.
+#[derive(Clone)]
+pub struct KerrScene(pub GRScene, pub f64);
+
+impl KerrParticle {
+	pub fn new(particle: GRParticle, spin: f64) -> Self {
+		KerrParticle { particle, spin }
+	}
+
+	pub fn from_ray(ray: &Ray, dt: f64, spin: f64) -> Self {
+		KerrParticle::new(GRParticle::from_ray(ray, dt), spin)
+	}
+
+	pub fn particle(&self) -> &GRParticle {
+		&self.particle
+	}
+
+	/// Integrates the geodesic until the particle enters the sphere, returning where it did, or
+	/// until `max_evals` force evaluations are spent.
+	pub fn intersect(&mut self, sphere: &Sphere, max_evals: u32) -> Option<Point> {
+		let spin = self.spin;
+		self.particle
+			.intersect_with(sphere, max_evals, &|pos, vel| {
+				spin_correction_term(pos, vel, spin)
+			})
+	}
+
+	/// Like `intersect`, checking each sphere of the scene in turn, see
+	/// `GRParticle::intersect_spheres`.
+	pub fn intersect_spheres<'s>(
+		&mut self,
+		scene: &'s Scene,
+		max_evals: u32,
+	) -> Option<(Point, &'s Sphere)> {
+		let spin = self.spin;
+		self.particle
+			.intersect_spheres_with(scene, max_evals, &|pos, vel| {
+				spin_correction_term(pos, vel, spin)
+			})
+	}
+}
+
+impl KerrScene {
+	/// Shades the pixel like `GRScene`, with the frame-dragging of the spinning black hole added
+	/// to the geodesics
+	fn shade(&self, x: f64, y: f64, lens: Vector2<f64>) -> (Color, Option<RayHit>, PixelAovs) {
+		let spin = self.1;
+		self.0
+			.shade(x, y, lens, &|pos, vel| spin_correction_term(pos, vel, spin))
+	}
+}
+
+impl Renderable for KerrScene {
+	fn render_px(&self, x: u32, y: u32) -> Color {
+		self.trace_px(x, y).0
+	}
+
+	fn trace_px(&self, x: u32, y: u32) -> (Color, Option<RayHit>) {
+		self.trace_subpx(x as f64, y as f64, Vector2::zeros())
+	}
+
+	fn trace_subpx(&self, x: f64, y: f64, lens: Vector2<f64>) -> (Color, Option<RayHit>) {
+		let (col, hit, _) = self.shade(x, y, lens);
+		(col, hit)
+	}
+
+	fn render_px_aovs(&self, x: u32, y: u32) -> (Color, PixelAovs) {
+		let (col, _, aovs) = self.shade(x as f64, y as f64, Vector2::zeros());
+		(col, aovs)
+	}
+
+	fn get_dimensions(&self) -> (u32, u32) {
+		self.0.get_dimensions()
+	}
+}
+
+/// Frame-dragging acceleration of a particle at `pos` (relative to the singularity) moving at
+/// `vel`, around a black hole of the given spin about the z axis. This is the gravitomagnetic
+/// term of the weak-field limit of the Kerr metric in Boyer-Lindquist coordinates, in units
+/// where the Schwarzschild radius is 1: prograde particles are pushed outwards, retrograde ones
+/// pulled in.
+pub fn spin_correction_term(pos: Vector, vel: Vector, spin: f64) -> Vector {
+	// J = a M, with a = spin M and M = 1/2
+	let j = Vector::z() * spin / 4.0;
+	let r2 = pos.dot(&pos);
+	let r_hat = pos / r2.sqrt();
+	let precession = (3.0 * j.dot(&r_hat) * r_hat - j) / r2.powf(1.5);
+
+	2.0 * precession.cross(&vel)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{spin_correction_term, KerrScene};
+
+	use crate::raytrace::{RayHit, Renderable, Vector};
+	use crate::{Camera, GRScene, Ring, Scene, Sphere, Texture, TextureFiltering, TextureMode};
+	use image::{DynamicImage, Rgba};
+	use nalgebra::{Translation3, UnitQuaternion, Vector3};
+
+	use std::f64::consts::FRAC_PI_2;
+
+	fn kerr_scene(spin: f64) -> KerrScene {
+		let texture = |img| Texture::new(img, TextureFiltering::Nearest, TextureMode::Clamp);
+		let white = DynamicImage::ImageRgba8(image::ImageBuffer::from_pixel(
+			1,
+			1,
+			Rgba([255, 255, 255, 255]),
+		));
+		let mut scene = Scene {
+			camera: Camera::new(32, 32, 60.0),
+			sphere: Sphere::new(1.0, texture(white)),
+			objects: vec![],
+			// Out of the way, only the shadow matters
+			ring: Ring::new(100.0, 101.0, texture(DynamicImage::new_rgba8(1, 1))),
+			bgtex: Some(texture(DynamicImage::new_rgba8(1, 1))),
+		};
+		// In the equatorial plane, with the spin axis pointing up
+		scene.set_camera(
+			Some(Translation3::new(0.0, -6.0, 0.0)),
+			Some(UnitQuaternion::from_axis_angle(
+				&Vector3::x_axis(),
+				FRAC_PI_2,
+			)),
+			None,
+		);
+
+		KerrScene(
+			GRScene {
+				tolerance: 0.1,
+				..GRScene::new(scene, 0.1, 800)
+			},
+			spin,
+		)
+	}
+
+	/// Number of pixels showing the sphere, in the left and right halves of the image
+	fn shadow_halves(scene: KerrScene) -> (usize, usize) {
+		// The sphere sits at the horizon, so it is told apart by what the rays hit, not by color
+		let (w, h) = scene.get_dimensions();
+		let count = |xs: std::ops::Range<u32>| {
+			xs.flat_map(|x| (0..h).map(move |y| (x, y)))
+				.filter(|&(x, y)| scene.trace_px(x, y).1 == Some(RayHit::Sphere))
+				.count()
+		};
+		// Pixel `x` mirrors pixel `w - x`, as rays go through pixel corners
+		(count(1..w / 2), count(w / 2 + 1..w))
+	}
+
+	#[test]
+	fn frame_dragging_depends_on_direction() {
+		let pos = Vector::new(2.0, 0.0, 0.0);
+		let prograde = spin_correction_term(pos, Vector::y(), 0.9);
+		let retrograde = spin_correction_term(pos, -Vector::y(), 0.9);
+		assert!(prograde.x > 0.0);
+		assert_eq!(retrograde, -prograde);
+		assert_eq!(spin_correction_term(pos, Vector::y(), 0.0), Vector::zeros());
+	}
+
+	#[test]
+	fn spin_makes_lensing_asymmetric() {
+		let (left, right) = shadow_halves(kerr_scene(0.0));
+		assert!(left > 0);
+		assert_eq!(left, right);
+
+		let (left, right) = shadow_halves(kerr_scene(0.99));
+		assert_ne!(left, right);
+	}
+
+	#[test]
+	fn shades_like_schwarzschild_without_spin() {
+		let mut scene = kerr_scene(0.0);
+		let white = scene.0.scene.sphere.texture.clone();
+		scene.0.scene.ring = Ring::new(2.0, 3.0, white).with_angular_velocity(0.1);
+		// Above the plane of the ring
+		scene
+			.0
+			.set_camera(Some(Translation3::new(0.0, -6.0, 3.0)), None, None);
+
+		let mut ring_hits = 0;
+		let every_other = |n: u32| (0..n).step_by(2);
+		for (x, y) in every_other(32).flat_map(|x| every_other(32).map(move |y| (x, y))) {
+			let (col, hit) = scene.trace_px(x, y);
+			assert_eq!((col, hit), scene.0.trace_px(x, y));
+			assert_eq!(scene.render_px_aovs(x, y), scene.0.render_px_aovs(x, y));
+			ring_hits += (hit == Some(RayHit::Ring)) as usize;
+		}
+		assert!(ring_hits > 0);
+	}
+}
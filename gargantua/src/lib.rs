@@ -1,11 +1,29 @@
+pub mod debug;
+pub mod kerr;
 pub mod physics;
+pub mod postprocess;
 pub mod raytrace;
 pub mod schwardzchild;
 pub mod texture;
 mod utils;
 
+pub use kerr::{KerrParticle, KerrScene};
 pub use physics::Particle;
-pub use raytrace::render::render;
-pub use raytrace::{Camera, Intersectable, Ray, Ring, Scene, Sphere};
-pub use schwardzchild::{GRParticle, GRScene};
-pub use texture::{Texture, TextureFiltering, TextureMode};
+pub use postprocess::ToneMapping;
+pub use raytrace::render::{
+	render, render_hdr, render_region, render_streaming, render_with_aovs, render_with_checkpoint,
+	render_with_config, render_with_pool, render_with_stats, OutputFormat, RenderConfig,
+	RenderError, RenderOutput, RenderResult, SceneStats, TileOrder,
+};
+pub use raytrace::{
+	Camera, Intersectable, MultiRing, PixelAovs, ProjectionMode, Ray, RayHit, Ring, RingBand,
+	Scene, Sphere, AABB,
+};
+pub use schwardzchild::{
+	isco_radius, photon_sphere_radius, GRParticle, GRScene, GeodesicType, Integrator,
+};
+pub use texture::{
+	composite_textures, AtlasRegion, MipmappedTexture, Texture, TextureAtlas, TextureError,
+	TextureFiltering, TextureLoadError, TextureMode,
+};
+pub use utils::export_trajectory_csv;
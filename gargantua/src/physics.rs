@@ -34,6 +34,48 @@ impl Particle {
 		self.acc = Vector::zeros();
 	}
 
+	/// Adds the acceleration given by `force_fn` at the current state, then advances the particle
+	/// by `dt` with `update`, which is symplectic (semi-implicit) Euler.
+	pub fn integrate_symplectic(&mut self, dt: f64, force_fn: &dyn Fn(&Particle) -> Vector) {
+		let force = force_fn(self);
+		self.add_force(force);
+		self.update(dt);
+	}
+
+	/// Advances the particle by `dt` with the classic 4th order Runge-Kutta scheme, where
+	/// `force_fn` gives the acceleration of the particle in a given state. Forces accumulated with
+	/// `add_force` are not taken into account, and are cleared.
+	pub fn update_rk4(&mut self, dt: f64, force_fn: &dyn Fn(&Particle) -> Vector) {
+		let at = |k_pos: Vector, k_vel: Vector, h: f64| Particle {
+			pos: self.pos + k_pos * h,
+			vel: self.vel + k_vel * h,
+			acc: Vector::zeros(),
+		};
+
+		let (k1_pos, k1_vel) = (self.vel, force_fn(self));
+		let p2 = at(k1_pos, k1_vel, dt / 2.0);
+		let (k2_pos, k2_vel) = (p2.vel, force_fn(&p2));
+		let p3 = at(k2_pos, k2_vel, dt / 2.0);
+		let (k3_pos, k3_vel) = (p3.vel, force_fn(&p3));
+		let p4 = at(k3_pos, k3_vel, dt);
+		let (k4_pos, k4_vel) = (p4.vel, force_fn(&p4));
+
+		self.pos += (k1_pos + 2.0 * k2_pos + 2.0 * k3_pos + k4_pos) * dt / 6.0;
+		self.vel += (k1_vel + 2.0 * k2_vel + 2.0 * k3_vel + k4_vel) * dt / 6.0;
+		self.acc = Vector::zeros();
+	}
+
+	/// Specific angular momentum `pos × vel` of the particle, around the origin
+	pub fn angular_momentum(&self) -> Vector {
+		self.pos.coords.cross(&self.vel)
+	}
+
+	/// Specific energy of the particle: its kinetic energy plus the potential energy given by
+	/// `potential_fn` for its state
+	pub fn total_energy(&self, potential_fn: &dyn Fn(&Particle) -> f64) -> f64 {
+		0.5 * self.vel.norm_squared() + potential_fn(self)
+	}
+
 	pub fn pos(&self) -> Point {
 		self.pos
 	}
@@ -72,3 +114,47 @@ impl Default for Particle {
 fn pt_zero() -> Point {
 	Point::new(0.0, 0.0, 0.0)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::Particle;
+
+	use crate::raytrace::{Point, Vector};
+
+	#[test]
+	fn rk4_follows_harmonic_oscillator() {
+		// x'' = -x, starting at rest at x = 1: x(t) = cos(t)
+		let spring = |p: &Particle| -p.pos().coords;
+		let mut rk4 = Particle::new(Point::new(1.0, 0.0, 0.0));
+		let mut euler = rk4.clone();
+		let dt = 0.1;
+		for _ in 0..100 {
+			rk4.update_rk4(dt, &spring);
+			euler.add_force(spring(&euler));
+			euler.update(dt);
+		}
+
+		let exact = Vector::new(10f64.cos(), 0.0, 0.0);
+		assert!((rk4.pos().coords - exact).norm() < 1e-4);
+		let potential = |p: &Particle| 0.5 * p.pos().coords.norm_squared();
+		assert!((rk4.total_energy(&potential) - 0.5).abs() < 1e-4);
+		assert!((euler.pos().coords - exact).norm() > 1e-2);
+		assert_eq!(rk4.acc(), Vector::zeros());
+	}
+
+	#[test]
+	fn symplectic_euler_keeps_circular_orbits() {
+		// Unit circular orbit around a central mass with GM = 1
+		let gravity = |p: &Particle| -p.pos().coords / p.pos().coords.norm().powi(3);
+		let mut part = Particle::new(Point::new(1.0, 0.0, 0.0));
+		part.set_vel(Vector::new(0.0, 1.0, 0.0));
+		for _ in 0..10_000 {
+			part.integrate_symplectic(1e-3, &gravity);
+			assert!((part.pos().coords.norm() - 1.0).abs() < 1e-3);
+		}
+		// Over one and a half orbits
+		assert!(part.pos().y < 0.0);
+		assert!((part.angular_momentum() - Vector::z()).norm() < 1e-9);
+		assert_eq!(part.acc(), Vector::zeros());
+	}
+}
@@ -0,0 +1,452 @@
+use color::TransferFunction;
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage, Rgba, RgbaImage};
+
+/// Image decoded into linear light, one `[r, g, b, a]` value per pixel in row-major order
+struct LinearImage {
+	width: u32,
+	height: u32,
+	pixels: Vec<[f32; 4]>,
+}
+
+impl LinearImage {
+	/// Decodes an 8-bit sRGB image. Alpha is kept as is.
+	fn from_image(img: &DynamicImage) -> Self {
+		let (width, height) = img.dimensions();
+		let pixels = img
+			.to_rgba()
+			.pixels()
+			.map(|p| {
+				let decode = |v: u8| TransferFunction::SRGB.decode(v as f64 / 255.0) as f32;
+				[
+					decode(p[0]),
+					decode(p[1]),
+					decode(p[2]),
+					p[3] as f32 / 255.0,
+				]
+			})
+			.collect();
+
+		LinearImage {
+			width,
+			height,
+			pixels,
+		}
+	}
+
+	/// Encodes back into an 8-bit sRGB image, clipping values outside of the display range
+	fn to_image(&self) -> DynamicImage {
+		let encode = |v: f32| {
+			let v = TransferFunction::SRGB.encode(clamp(v, 0.0, 1.0) as f64);
+			(v * 255.0).round() as u8
+		};
+		let mut buf = RgbaImage::new(self.width, self.height);
+		for (p, px) in buf.pixels_mut().zip(&self.pixels) {
+			*p = Rgba([
+				encode(px[0]),
+				encode(px[1]),
+				encode(px[2]),
+				(clamp(px[3], 0.0, 1.0) * 255.0).round() as u8,
+			]);
+		}
+
+		DynamicImage::ImageRgba8(buf)
+	}
+
+	fn get(&self, x: i64, y: i64) -> [f32; 4] {
+		let x = clamp(x, 0, self.width as i64 - 1) as u32;
+		let y = clamp(y, 0, self.height as i64 - 1) as u32;
+		self.pixels[(y * self.width + x) as usize]
+	}
+
+	/// Bilinearly interpolated value at a fractional position, pixel centers being at integer
+	/// coordinates. Positions outside the image are clamped to the edges.
+	fn sample(&self, x: f32, y: f32) -> [f32; 4] {
+		let (x0, y0) = (x.floor(), y.floor());
+		let (fx, fy) = (x - x0, y - y0);
+		let (x0, y0) = (x0 as i64, y0 as i64);
+		let lerp = |a: [f32; 4], b: [f32; 4], t: f32| {
+			let mut out = [0.0; 4];
+			for (o, (a, b)) in out.iter_mut().zip(a.iter().zip(&b)) {
+				*o = a + (b - a) * t;
+			}
+			out
+		};
+		let top = lerp(self.get(x0, y0), self.get(x0 + 1, y0), fx);
+		let bottom = lerp(self.get(x0, y0 + 1), self.get(x0 + 1, y0 + 1), fx);
+
+		lerp(top, bottom, fy)
+	}
+
+	/// Convolves the color channels with the 1D kernel (centered, of odd length) along x then
+	/// along y, clamping to the edges
+	fn convolve_separable(&self, kernel: &[f32]) -> Self {
+		let half = (kernel.len() / 2) as i64;
+		let pass = |img: &LinearImage, (dx, dy): (i64, i64)| {
+			let mut pixels = Vec::with_capacity(img.pixels.len());
+			for y in 0..img.height as i64 {
+				for x in 0..img.width as i64 {
+					let mut sum = [0.0, 0.0, 0.0, img.get(x, y)[3]];
+					for (i, w) in kernel.iter().enumerate() {
+						let offset = i as i64 - half;
+						let px = img.get(x + offset * dx, y + offset * dy);
+						for c in 0..3 {
+							sum[c] += w * px[c];
+						}
+					}
+					pixels.push(sum);
+				}
+			}
+			LinearImage {
+				width: img.width,
+				height: img.height,
+				pixels,
+			}
+		};
+
+		pass(&pass(self, (1, 0)), (0, 1))
+	}
+
+	/// Applies the function to the color channels of every pixel
+	fn map_rgb<F: Fn(f32) -> f32>(mut self, f: F) -> Self {
+		for px in &mut self.pixels {
+			px[0] = f(px[0]);
+			px[1] = f(px[1]);
+			px[2] = f(px[2]);
+		}
+		self
+	}
+}
+
+/// Tone mapping operator applied to renders, see `RenderConfig::tone_mapping`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping {
+	/// Leaves the image untouched
+	None,
+	/// Exponential exposure curve `1 - exp(-exposure * x)`, see `tone_map_exposure`
+	Exposure(f64),
+	/// Extended Reinhard operator, see `tone_map_reinhard`
+	Reinhard { white_point: f64 },
+	/// ACES filmic curve, see `tone_map_aces`
+	Aces,
+}
+
+impl ToneMapping {
+	pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+		match *self {
+			ToneMapping::None => img,
+			ToneMapping::Exposure(exposure) => tone_map_exposure(&img, exposure),
+			ToneMapping::Reinhard { white_point } => tone_map_reinhard(&img, white_point),
+			ToneMapping::Aces => tone_map_aces(&img),
+		}
+	}
+
+	/// Maps a value in linear light to the display range, as `apply` does to every channel
+	pub fn map(&self, x: f32) -> f32 {
+		match *self {
+			ToneMapping::None => x,
+			ToneMapping::Exposure(exposure) => 1.0 - (-exposure as f32 * x.max(0.0)).exp(),
+			ToneMapping::Reinhard { white_point } => reinhard(x, white_point as f32),
+			ToneMapping::Aces => aces(x),
+		}
+	}
+}
+
+/// Tone maps pixels in linear light, given as `[r, g, b, a]` in row-major order, and encodes
+/// them into an 8-bit sRGB image
+pub(crate) fn encode_linear(
+	width: u32,
+	height: u32,
+	pixels: Vec<[f32; 4]>,
+	tone_mapping: ToneMapping,
+) -> DynamicImage {
+	LinearImage {
+		width,
+		height,
+		pixels,
+	}
+	.map_rgb(|x| tone_mapping.map(x))
+	.to_image()
+}
+
+/// Filmic tone curve fitted to the ACES reference rendering and output transforms, mapping
+/// linear light to the display range `[0, 1]`.
+pub fn aces(x: f32) -> f32 {
+	const A: f32 = 2.51;
+	const B: f32 = 0.03;
+	const C: f32 = 2.43;
+	const D: f32 = 0.59;
+	const E: f32 = 0.14;
+	let x = x.max(0.0);
+
+	((x * (A * x + B)) / (x * (C * x + D) + E)).min(1.0)
+}
+
+/// Extended Reinhard tone curve, mapping linear light to `[0, 1]` with `white_point` (and
+/// anything brighter) mapped to pure white.
+pub fn reinhard(x: f32, white_point: f32) -> f32 {
+	let x = x.max(0.0);
+	(x / (1.0 + x) * (1.0 + x / (white_point * white_point))).min(1.0)
+}
+
+/// Tone maps the image with the ACES filmic curve (see `aces`), in linear light. The output is
+/// an 8-bit sRGB image.
+pub fn tone_map_aces(img: &DynamicImage) -> DynamicImage {
+	LinearImage::from_image(img).map_rgb(aces).to_image()
+}
+
+/// Tone maps the image with the extended Reinhard operator (see `reinhard`), in linear light.
+/// The output is an 8-bit sRGB image.
+pub fn tone_map_reinhard(img: &DynamicImage, white_point: f64) -> DynamicImage {
+	let white_point = white_point as f32;
+	LinearImage::from_image(img)
+		.map_rgb(|x| reinhard(x, white_point))
+		.to_image()
+}
+
+/// Tone maps the image with an exponential exposure curve, `1 - exp(-exposure * x)` in linear
+/// light: higher exposures brighten the image, and highlights never clip. The output is an 8-bit
+/// sRGB image.
+pub fn tone_map_exposure(img: &DynamicImage, exposure: f64) -> DynamicImage {
+	let exposure = exposure as f32;
+	LinearImage::from_image(img)
+		.map_rgb(|x| 1.0 - (-exposure * x.max(0.0)).exp())
+		.to_image()
+}
+
+/// Makes bright areas glow: pixels with a relative luminance above `threshold` (in linear light)
+/// are blurred with a Gaussian of the given radius in pixels, and added back onto the image
+/// weighted by `strength`.
+pub fn bloom(img: &DynamicImage, threshold: f64, radius: u32, strength: f64) -> DynamicImage {
+	let linear = LinearImage::from_image(img);
+	let threshold = threshold as f32;
+	let bright = LinearImage {
+		width: linear.width,
+		height: linear.height,
+		pixels: linear
+			.pixels
+			.iter()
+			.map(|px| {
+				let luminance = 0.2126 * px[0] + 0.7152 * px[1] + 0.0722 * px[2];
+				if luminance > threshold {
+					*px
+				} else {
+					[0.0, 0.0, 0.0, px[3]]
+				}
+			})
+			.collect(),
+	};
+	let glow = bright.convolve_separable(&gaussian_kernel(radius));
+
+	let strength = strength as f32;
+	let pixels = linear
+		.pixels
+		.iter()
+		.zip(&glow.pixels)
+		.map(|(px, g)| {
+			[
+				px[0] + strength * g[0],
+				px[1] + strength * g[1],
+				px[2] + strength * g[2],
+				px[3],
+			]
+		})
+		.collect();
+
+	LinearImage { pixels, ..linear }.to_image()
+}
+
+/// Lens-like fringing: the red channel is shifted away from the center of the image and the blue
+/// channel towards it, by up to `strength` pixels in the corners. The shift grows linearly from
+/// the center, and sub-pixel shifts are bilinearly resampled.
+pub fn chromatic_aberration(img: &DynamicImage, strength: f64) -> DynamicImage {
+	let linear = LinearImage::from_image(img);
+	let (cx, cy) = (
+		(linear.width as f32 - 1.0) / 2.0,
+		(linear.height as f32 - 1.0) / 2.0,
+	);
+	let corner = (cx * cx + cy * cy).sqrt();
+	let scale = if corner > 0.0 {
+		strength as f32 / corner
+	} else {
+		0.0
+	};
+
+	let mut pixels = Vec::with_capacity(linear.pixels.len());
+	for y in 0..linear.height {
+		for x in 0..linear.width {
+			let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+			let px = linear.pixels[(y * linear.width + x) as usize];
+			// A channel shifted outwards shows, at each pixel, what was closer to the center
+			let red = linear.sample(cx + dx * (1.0 - scale), cy + dy * (1.0 - scale));
+			let blue = linear.sample(cx + dx * (1.0 + scale), cy + dy * (1.0 + scale));
+			pixels.push([red[0], px[1], blue[2], px[3]]);
+		}
+	}
+
+	LinearImage { pixels, ..linear }.to_image()
+}
+
+/// Visualizes a buffer of texture coordinates, such as the UV AOV of `render_with_aovs`, as an
+/// RGB image with U in the red channel and V in the green channel. Coordinates are clamped to
+/// [0, 1].
+pub fn from_uv_buffer(uv: &[[f32; 2]], width: u32, height: u32) -> DynamicImage {
+	let to_u8 = |x: f32| (clamp(x, 0.0, 1.0) * 255.0).round() as u8;
+	DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+		let [u, v] = uv[(y * width + x) as usize];
+		Rgb([to_u8(u), to_u8(v), 0])
+	}))
+}
+
+/// Normalized Gaussian kernel spanning `2 * radius + 1` samples, with the radius at 3 sigmas
+fn gaussian_kernel(radius: u32) -> Vec<f32> {
+	if radius == 0 {
+		return vec![1.0];
+	}
+	let sigma = radius as f32 / 3.0;
+	let weights: Vec<f32> = (-(radius as i64)..=radius as i64)
+		.map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+		.collect();
+	let sum: f32 = weights.iter().sum();
+
+	weights.into_iter().map(|w| w / sum).collect()
+}
+
+fn clamp<T: PartialOrd>(x: T, min: T, max: T) -> T {
+	if x < min {
+		min
+	} else if x > max {
+		max
+	} else {
+		x
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		aces, bloom, chromatic_aberration, gaussian_kernel, reinhard, tone_map_aces,
+		tone_map_exposure, tone_map_reinhard, ToneMapping,
+	};
+
+	use approx::assert_relative_eq;
+	use image::{DynamicImage, ImageBuffer, Rgba};
+
+	#[test]
+	fn tone_curves_compress_highlights() {
+		assert_eq!(aces(0.0), 0.0);
+		assert_eq!(reinhard(0.0, 4.0), 0.0);
+		assert_relative_eq!(aces(1.0), 2.54 / 3.16, epsilon = 1e-6);
+		assert_eq!(aces(100.0), 1.0);
+		assert_relative_eq!(reinhard(1.0, 1e6), 0.5, epsilon = 1e-6);
+		assert_relative_eq!(reinhard(4.0, 4.0), 1.0, epsilon = 1e-6);
+		for &(x, y) in &[(0.1, 0.2), (0.5, 1.0), (2.0, 8.0)] {
+			assert!(aces(x) < aces(y));
+			assert!(reinhard(x, 10.0) < reinhard(y, 10.0));
+		}
+	}
+
+	#[test]
+	fn tone_maps_images() {
+		let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 1, |x, _| {
+			if x == 0 {
+				Rgba([0, 0, 0, 255])
+			} else {
+				Rgba([255, 255, 255, 128])
+			}
+		}));
+
+		let mapped = tone_map_aces(&img);
+		let buf = mapped.as_rgba8().unwrap();
+		assert_eq!(*buf.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+		let white = buf.get_pixel(1, 0);
+		assert!(white[0] < 255 && white[0] > 200);
+		assert_eq!(white[3], 128);
+
+		// White maps to itself when it is the white point
+		let mapped = tone_map_reinhard(&img, 1.0);
+		assert_eq!(mapped.raw_pixels(), img.raw_pixels());
+
+		let dim = tone_map_exposure(&img, 1.0);
+		let bright = tone_map_exposure(&img, 4.0);
+		assert_eq!(
+			*dim.as_rgba8().unwrap().get_pixel(0, 0),
+			Rgba([0, 0, 0, 255])
+		);
+		assert!(
+			dim.as_rgba8().unwrap().get_pixel(1, 0)[0]
+				< bright.as_rgba8().unwrap().get_pixel(1, 0)[0]
+		);
+
+		assert_eq!(
+			ToneMapping::None.apply(img.clone()).raw_pixels(),
+			img.raw_pixels()
+		);
+		assert_eq!(
+			ToneMapping::Aces.apply(img.clone()).raw_pixels(),
+			tone_map_aces(&img).raw_pixels()
+		);
+	}
+
+	#[test]
+	fn blooms_bright_pixels() {
+		let kernel = gaussian_kernel(3);
+		assert_eq!(kernel.len(), 7);
+		assert_relative_eq!(kernel.iter().sum::<f32>(), 1.0, epsilon = 1e-6);
+		assert!(kernel[3] > kernel[2] && kernel[2] > kernel[1]);
+		assert_eq!(gaussian_kernel(0), vec![1.0]);
+
+		let black = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(8, 8, Rgba([0, 0, 0, 255])));
+		for &(threshold, radius, strength) in &[(0.0, 0, 1.0), (-1.0, 3, 5.0), (0.5, 10, 0.2)] {
+			let bloomed = bloom(&black, threshold, radius, strength);
+			assert_eq!(bloomed.raw_pixels(), black.raw_pixels());
+		}
+
+		let mut buf = ImageBuffer::from_pixel(9, 1, Rgba([0, 0, 0, 255]));
+		buf.put_pixel(4, 0, Rgba([255, 255, 255, 255]));
+		let img = DynamicImage::ImageRgba8(buf);
+		let bloomed = bloom(&img, 0.5, 2, 1.0);
+		let out = bloomed.as_rgba8().unwrap();
+		assert_eq!(*out.get_pixel(4, 0), Rgba([255, 255, 255, 255]));
+		assert!(out.get_pixel(3, 0)[0] > 0 && out.get_pixel(5, 0)[0] > 0);
+		assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+
+		// Nothing is bright enough to glow
+		assert_eq!(bloom(&img, 2.0, 2, 1.0).raw_pixels(), img.raw_pixels());
+	}
+
+	#[test]
+	fn shifts_red_out_and_blue_in() {
+		// A white column right of the center
+		let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(9, 9, |x, _| {
+			if x == 6 {
+				Rgba([255, 255, 255, 255])
+			} else {
+				Rgba([0, 0, 0, 255])
+			}
+		}));
+		assert_eq!(
+			chromatic_aberration(&img, 0.0).raw_pixels(),
+			img.raw_pixels()
+		);
+
+		let shifted = chromatic_aberration(&img, 2.0);
+		let buf = shifted.as_rgba8().unwrap();
+		assert_eq!(buf.dimensions(), (9, 9));
+		// Where each channel of the middle row is, on average
+		let mean_x = |c: usize| {
+			let weights: Vec<f64> = (0..9).map(|x| buf.get_pixel(x, 4)[c] as f64).collect();
+			let sum: f64 = weights.iter().sum();
+			weights
+				.iter()
+				.enumerate()
+				.map(|(x, w)| x as f64 * w)
+				.sum::<f64>()
+				/ sum
+		};
+		assert!(mean_x(0) > 6.0);
+		assert_eq!(mean_x(1), 6.0);
+		assert!(mean_x(2) < 6.0);
+		// Nothing moves at the center
+		assert_eq!(*buf.get_pixel(4, 4), Rgba([0, 0, 0, 255]));
+	}
+}
@@ -1,12 +1,19 @@
 use color::Color;
-use image::{DynamicImage, Pixel, Rgb};
+use image::DynamicImage;
 use nalgebra::{
-	Isometry3, Perspective3, Point2, Point3, Translation3, Unit, UnitQuaternion, Vector2, Vector3,
+	Isometry3, Matrix3, Perspective3, Point2, Point3, Rotation3, Translation3, Unit,
+	UnitQuaternion, Vector2, Vector3,
 };
+use rayon::ThreadPool;
 use std::f64;
+use std::fmt;
 
-use crate::texture::{Texture, TextureFiltering, TextureMode};
-use crate::utils::cartesian_to_spherical;
+use self::render::{
+	render_region, render_with_config, render_with_pool, RenderConfig, RenderError, Reporter,
+};
+use crate::schwardzchild::isco_radius;
+use crate::texture::{MipmappedTexture, Texture, TextureFiltering, TextureMode};
+use crate::utils::background_uv_from_direction;
 
 pub type Point = Point3<f64>;
 pub type Vector = Vector3<f64>;
@@ -19,6 +26,7 @@ pub struct Ray {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sphere {
 	pub pos: Point,
 	pub radius: f64,
@@ -26,38 +34,141 @@ pub struct Sphere {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ring {
 	pub pos: Point,
+	/// Inner and outer radius of the ring
 	pub radius: (f64, f64),
 	pub texture_top: Texture,
 	pub texture_bottom: Texture,
+	/// Angular velocity of the ring material about the z axis, in radians per unit time,
+	/// counterclockwise seen from above. It must keep the material slower than light, of speed 1.
+	pub angular_velocity: f64,
+	/// Mip chains of the top and bottom textures, see `with_mipmaps`. Not serialized, as they
+	/// are derived from the textures.
+	#[cfg_attr(feature = "serde", serde(skip))]
+	pub mipmaps: Option<(MipmappedTexture, MipmappedTexture)>,
+}
+
+/// Radial band of a `MultiRing`, between two radii
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RingBand {
+	pub inner: f64,
+	pub outer: f64,
+	/// Fraction of the light from behind the band that it blocks, from 0 (invisible) to 1
+	/// (opaque)
+	pub opacity: f64,
+}
+
+/// Disk made of concentric bands with gaps in between, like the rings of Saturn. The textures
+/// span each band, V running from its inner to its outer edge.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiRing {
+	pub pos: Point,
+	pub bands: Vec<RingBand>,
+	pub texture_top: Texture,
+	pub texture_bottom: Texture,
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera {
 	pub width: u32,
 	pub height: u32,
 	pub isometry: Isometry3<f64>,
+	#[cfg_attr(feature = "serde", serde(with = "serde_perspective"))]
 	pub perspective: Perspective3<f64>,
+	/// Radius of the lens, 0 for a pinhole camera where everything is in focus
+	pub aperture: f64,
+	/// Distance along primary rays at which objects are in focus, with a non-zero aperture
+	pub focal_distance: f64,
+	pub projection: ProjectionMode,
+}
+
+/// How a camera spreads its primary rays over the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProjectionMode {
+	/// Rays diverge from the camera position, as given by `Camera::perspective`
+	Perspective,
+	/// Rays are parallel, starting from a rectangle centered on the camera position, of the given
+	/// width in world units and of the aspect ratio of the image
+	Orthographic { world_width: f64 },
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scene {
 	pub camera: Camera,
 	pub sphere: Sphere,
+	/// Additional spheres, such as planets or companion bodies, rendered alongside `sphere`
+	pub objects: Vec<Sphere>,
 	pub ring: Ring,
 	pub bgtex: Option<Texture>,
 }
 
+/// Axis-aligned bounding box
+#[derive(Clone, Debug, PartialEq)]
+pub struct AABB {
+	pub min: Point,
+	pub max: Point,
+}
+
 pub trait Intersectable {
 	fn intersect(&self, ray: &Ray) -> Option<f64>;
 	fn surface_normal(&self, hit: &Point) -> Unit<Vector>;
 	fn texture_coords(&self, hit: &Point) -> TexCoords;
 }
 
+/// What a primary ray ended up hitting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayHit {
+	Sphere,
+	Ring,
+	Background,
+}
+
+/// Arbitrary output variables (AOVs) of a pixel, recorded alongside its color for
+/// post-processing. Each is `None` where the primary ray hit nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelAovs {
+	/// Distance from the camera to the hit
+	pub depth: Option<f64>,
+	/// World-space normal of the surface at the hit
+	pub normal: Option<Unit<Vector>>,
+	/// Texture coordinates of the hit on the surface, before the texture lookup
+	pub uv: Option<TexCoords>,
+}
+
 pub trait Renderable {
 	fn render_px(&self, x: u32, y: u32) -> Color;
 	fn get_dimensions(&self) -> (u32, u32);
+
+	/// Renders a pixel and reports what its primary ray hit, if the renderable knows
+	fn trace_px(&self, x: u32, y: u32) -> (Color, Option<RayHit>) {
+		(self.render_px(x, y), None)
+	}
+
+	/// Traces at a fractional pixel position, used for antialiasing, through the given point of
+	/// the camera lens (see `Camera::create_primary_lens`). Renderables that can't sample within
+	/// a pixel fall back to the whole pixel.
+	fn trace_subpx(&self, x: f64, y: f64, _lens: Vector2<f64>) -> (Color, Option<RayHit>) {
+		self.trace_px(x as u32, y as u32)
+	}
+
+	/// Renders a pixel along with its AOVs, left empty if the renderable doesn't know them
+	fn render_px_aovs(&self, x: u32, y: u32) -> (Color, PixelAovs) {
+		(self.render_px(x, y), PixelAovs::default())
+	}
+
+	/// Renders a pixel along with the distance from the camera to what its primary ray hit, or
+	/// `None` if it hit nothing or the renderable doesn't know. See `render_px_aovs`.
+	fn render_px_depth(&self, x: u32, y: u32) -> (Color, Option<f64>) {
+		let (col, aovs) = self.render_px_aovs(x, y);
+		(col, aovs.depth)
+	}
 }
 
 impl Intersectable for Sphere {
@@ -76,9 +187,7 @@ impl Intersectable for Sphere {
 		Unit::new_normalize(*hit - self.pos)
 	}
 	fn texture_coords(&self, hit: &Point) -> TexCoords {
-		let dir = *hit - self.pos;
-		let (_, theta, phi) = cartesian_to_spherical(&dir);
-		return TexCoords::new(theta / f64::consts::PI, 0.5 * phi / f64::consts::PI + 0.5);
+		background_uv_from_direction(&self.surface_normal(hit))
 	}
 }
 
@@ -92,9 +201,12 @@ impl Intersectable for Ring {
 			ray,
 		) {
 			Some(t) => {
-				let p = ray.origin + ray.direction.as_ref() * t;
-				let d2 = p.coords.dot(&p.coords);
-				if d2 > self.radius.0 * self.radius.0 || d2 < self.radius.1 * self.radius.1 {
+				let local = ray.origin + ray.direction.as_ref() * t - self.pos;
+				let d2 = local.dot(&local);
+				if t < 0.0
+					|| d2 < self.radius.0 * self.radius.0
+					|| d2 > self.radius.1 * self.radius.1
+				{
 					return None;
 				} else {
 					return Some(t);
@@ -109,11 +221,260 @@ impl Intersectable for Ring {
 	}
 
 	fn texture_coords(&self, hit: &Point) -> TexCoords {
+		self.texture_coords_seamless(hit).0
+	}
+}
+
+impl Intersectable for MultiRing {
+	fn intersect(&self, ray: &Ray) -> Option<f64> {
+		let plane = Ray {
+			origin: self.pos,
+			direction: Vector::z_axis(),
+		};
+		// Bands share the plane of the disk, so the plane hit is the closest one
+		ray_plane(&plane, ray).filter(|&t| {
+			t >= 0.0
+				&& self
+					.band_at(&(ray.origin + ray.direction.as_ref() * t))
+					.is_some()
+		})
+	}
+
+	fn surface_normal(&self, _hit: &Point) -> Unit<Vector> {
+		Vector::z_axis()
+	}
+
+	/// Azimuth as U, starting from the x axis like `Ring`, and the position across the band
+	/// containing the point as V. Points outside of the bands get a V of 0.
+	fn texture_coords(&self, hit: &Point) -> TexCoords {
+		let local = hit - self.pos;
+		let u = (local.y.atan2(local.x) / (2.0 * f64::consts::PI)).rem_euclid(1.0);
+		let v = self.band_at(hit).map_or(0.0, |band| {
+			(local.norm() - band.inner) / (band.outer - band.inner)
+		});
+
+		TexCoords::new(u, v)
+	}
+}
+
+impl AABB {
+	pub fn new(min: Point, max: Point) -> Self {
+		AABB { min, max }
+	}
+
+	/// Smallest box containing both boxes
+	pub fn union(&self, other: &AABB) -> Self {
+		AABB {
+			min: Point::from(self.min.coords.zip_map(&other.min.coords, f64::min)),
+			max: Point::from(self.max.coords.zip_map(&other.max.coords, f64::max)),
+		}
+	}
+
+	/// Slab test: whether the ray (as a half-line) crosses the box
+	pub fn intersects(&self, ray: &Ray) -> bool {
+		let mut t_min = f64::NEG_INFINITY;
+		let mut t_max = f64::INFINITY;
+		for i in 0..3 {
+			let inv = 1.0 / ray.direction[i];
+			let t1 = (self.min[i] - ray.origin[i]) * inv;
+			let t2 = (self.max[i] - ray.origin[i]) * inv;
+			t_min = t_min.max(t1.min(t2));
+			t_max = t_max.min(t1.max(t2));
+		}
+
+		t_max >= t_min.max(0.0)
+	}
+}
+
+impl Sphere {
+	pub fn new(radius: f64, texture: Texture) -> Self {
+		Sphere {
+			pos: Point::origin(),
+			radius,
+			texture,
+		}
+	}
+
+	pub fn with_position(mut self, pos: Point) -> Self {
+		self.pos = pos;
+		self
+	}
+
+	pub fn with_radius(mut self, radius: f64) -> Self {
+		self.radius = radius;
+		self
+	}
+}
+
+impl Ring {
+	pub fn new(inner_radius: f64, outer_radius: f64, texture: Texture) -> Self {
+		Ring {
+			pos: Point::origin(),
+			radius: (inner_radius, outer_radius),
+			texture_top: texture.clone(),
+			texture_bottom: texture,
+			angular_velocity: 0.0,
+			mipmaps: None,
+		}
+	}
+
+	pub fn with_position(mut self, pos: Point) -> Self {
+		self.pos = pos;
+		self
+	}
+
+	pub fn with_angular_velocity(mut self, angular_velocity: f64) -> Self {
+		self.angular_velocity = angular_velocity;
+		self
+	}
+
+	/// Velocity of the ring material at a point of the ring
+	pub fn velocity_at(&self, hit: &Point) -> Vector {
+		let local = hit - self.pos;
+		self.angular_velocity * Vector::new(-local.y, local.x, 0.0)
+	}
+
+	/// Sets the radii from the black hole's Schwarzschild radius: the inner edge sits at the
+	/// innermost stable circular orbit (see `isco_radius`), the outer edge at `outer_factor * rs`.
+	pub fn with_isco(mut self, schwarzschild_radius: f64, outer_factor: f64) -> Self {
+		self.radius = (
+			isco_radius(schwarzschild_radius),
+			outer_factor * schwarzschild_radius,
+		);
+		self
+	}
+
+	pub fn with_top_texture(mut self, texture: Texture) -> Self {
+		self.texture_top = texture;
+		self.rebuild_mipmaps()
+	}
+
+	pub fn with_bottom_texture(mut self, texture: Texture) -> Self {
+		self.texture_bottom = texture;
+		self.rebuild_mipmaps()
+	}
+
+	/// Builds the mip chains of both textures, so renders sample them at a level of detail
+	/// matching the pixels' footprint on the ring. This avoids aliasing on rings seen edge-on.
+	pub fn with_mipmaps(mut self) -> Self {
+		self.mipmaps = Some((
+			self.texture_top.build_mipmaps(),
+			self.texture_bottom.build_mipmaps(),
+		));
+		self
+	}
+
+	fn rebuild_mipmaps(self) -> Self {
+		if self.mipmaps.is_some() {
+			self.with_mipmaps()
+		} else {
+			self
+		}
+	}
+
+	/// Texture coordinates of a point on the ring, with the azimuth as U (starting from the x axis)
+	/// and the distance from the inner edge as V. The flag tells whether the point is close to the
+	/// azimuthal seam, where filtering has to wrap around the texture.
+	pub fn texture_coords_seamless(&self, hit: &Point) -> (TexCoords, bool) {
+		const SEAM_WIDTH: f64 = 0.01;
 		let local = hit - self.pos;
-		let r = local.dot(&local).sqrt();
-		let theta = local.normalize().dot(&Vector::x_axis().as_ref());
+		let u = (local.y.atan2(local.x) / (2.0 * f64::consts::PI)).rem_euclid(1.0);
+		let v = (local.norm() - self.radius.0) / (self.radius.1 - self.radius.0);
+
+		let seam = !(SEAM_WIDTH..=1.0 - SEAM_WIDTH).contains(&u);
+
+		(TexCoords::new(u, v), seam)
+	}
+
+	/// Whether the ray comes from below the ring, travelling along its normal
+	pub fn hits_bottom(&self, ray: &Ray) -> bool {
+		ray.direction.dot(&Vector::z_axis()) > 0.0
+	}
+
+	/// Normal of the face the ray hits, pointing back towards the ray's origin
+	pub fn facing_normal(&self, ray: &Ray) -> Unit<Vector> {
+		if self.hits_bottom(ray) {
+			-Vector::z_axis()
+		} else {
+			Vector::z_axis()
+		}
+	}
+
+	/// Samples the texture of the face the ray hit: rays travelling along the ring normal come
+	/// from below and hit the bottom face, the others hit the top face.
+	pub fn sample_texture(&self, hit: Point, ray: &Ray) -> Color {
+		let (uv, seam) = self.texture_coords_seamless(&hit);
+		if self.hits_bottom(ray) {
+			self.texture_bottom.uv_seamless(uv, seam)
+		} else {
+			self.texture_top.uv_seamless(uv, seam)
+		}
+	}
 
-		return TexCoords::new(r * theta.cos(), r * theta.sin());
+	/// Samples like `sample_texture`, choosing the mip level from `footprint`, the width of the
+	/// pixel seen from the camera at the distance of the hit. Without mipmaps, this is the same
+	/// as `sample_texture`.
+	pub fn sample_texture_lod(&self, hit: Point, ray: &Ray, footprint: f64) -> Color {
+		let (top, bottom) = match &self.mipmaps {
+			Some(mipmaps) => mipmaps,
+			None => return self.sample_texture(hit, ray),
+		};
+		let (uv, seam) = self.texture_coords_seamless(&hit);
+		let cos = ray.direction.dot(&self.facing_normal(ray));
+		let (texture, mipmaps) = if self.hits_bottom(ray) {
+			(&self.texture_bottom, bottom)
+		} else {
+			(&self.texture_top, top)
+		};
+
+		// The footprint stretches along the ring at grazing angles
+		let footprint = footprint / cos.abs().max(1e-3);
+		let (w, h) = texture.dimensions();
+		let circumference = 2.0 * f64::consts::PI * (hit - self.pos).norm();
+		let texels_per_unit =
+			(w as f64 / circumference).max(h as f64 / (self.radius.1 - self.radius.0));
+		mipmaps.uv_lod_seamless(uv, (footprint * texels_per_unit).log2(), seam)
+	}
+}
+
+impl MultiRing {
+	pub fn new(bands: Vec<RingBand>, texture: Texture) -> Self {
+		MultiRing {
+			pos: Point::origin(),
+			bands,
+			texture_top: texture.clone(),
+			texture_bottom: texture,
+		}
+	}
+
+	pub fn with_position(mut self, pos: Point) -> Self {
+		self.pos = pos;
+		self
+	}
+
+	/// Band containing a point of the disk, if any
+	pub fn band_at(&self, hit: &Point) -> Option<&RingBand> {
+		let r = (hit - self.pos).norm();
+		self.bands.iter().find(|b| r >= b.inner && r <= b.outer)
+	}
+
+	/// Normal of the face the ray hits, pointing back towards the ray's origin
+	pub fn facing_normal(&self, ray: &Ray) -> Unit<Vector> {
+		if ray.direction.dot(&Vector::z_axis()) > 0.0 {
+			-Vector::z_axis()
+		} else {
+			Vector::z_axis()
+		}
+	}
+
+	/// Samples the texture of the face the ray hit, like `Ring::sample_texture`
+	pub fn sample_texture(&self, hit: Point, ray: &Ray) -> Color {
+		let uv = self.texture_coords(&hit);
+		if ray.direction.dot(&Vector::z_axis()) > 0.0 {
+			self.texture_bottom.uv(uv)
+		} else {
+			self.texture_top.uv(uv)
+		}
 	}
 }
 
@@ -129,12 +490,120 @@ impl Camera {
 				200.0,
 			),
 			isometry: Isometry3::identity(),
+			aperture: 0.0,
+			focal_distance: 1.0,
+			projection: ProjectionMode::Perspective,
+		}
+	}
+
+	/// Camera with parallel rays, all looking down its local -z axis, covering `world_width`
+	/// world units across the image. Objects keep the same size whatever their distance.
+	pub fn orthographic(width: u32, height: u32, world_width: f64) -> Self {
+		Self {
+			projection: ProjectionMode::Orthographic { world_width },
+			..Camera::new(width, height, 30.0)
 		}
 	}
 
+	/// Sets the lens of the camera, for depth of field. Only renders with more than one sample
+	/// per pixel spread their rays over the lens.
+	pub fn with_depth_of_field(mut self, aperture: f64, focal_distance: f64) -> Self {
+		self.aperture = aperture;
+		self.focal_distance = focal_distance;
+		self
+	}
+
+	/// Camera at `from`, aimed at `target`. `up` is the direction that should appear upwards in
+	/// the image, it only needs to not be parallel to the view direction.
+	pub fn look_at(
+		from: Point,
+		target: Point,
+		up: Vector,
+		fov_deg: f64,
+		width: u32,
+		height: u32,
+	) -> Self {
+		let direction = (target - from).normalize();
+		let right = direction.cross(&up).normalize();
+		let up = right.cross(&direction);
+		// The camera looks down its local -z axis
+		let rotation =
+			Rotation3::from_matrix_unchecked(Matrix3::from_columns(&[right, up, -direction]));
+
+		let mut camera = Camera::new(width, height, fov_deg);
+		camera.isometry = Isometry3::from_parts(
+			Translation3::from(from.coords),
+			UnitQuaternion::from_rotation_matrix(&rotation),
+		);
+		camera
+	}
+
+	/// Camera in between `a` (at `t = 0`) and `b` (at `t = 1`), for animations. The position,
+	/// field of view and lens are interpolated linearly, the orientation spherically along the
+	/// shortest arc. The image size and projection are the ones of `a`.
+	pub fn interpolate(a: &Camera, b: &Camera, t: f64) -> Camera {
+		let lerp = |x: f64, y: f64| x + (y - x) * t;
+		let from = a.isometry.rotation;
+		// `q` and `-q` are the same rotation, pick the one closest to `from`
+		let to = if from.coords.dot(&b.isometry.rotation.coords) < 0.0 {
+			UnitQuaternion::new_unchecked(-b.isometry.rotation.into_inner())
+		} else {
+			b.isometry.rotation
+		};
+		let translation = a
+			.isometry
+			.translation
+			.vector
+			.lerp(&b.isometry.translation.vector, t);
+
+		let mut camera = a.clone();
+		camera.isometry =
+			Isometry3::from_parts(Translation3::from(translation), from.slerp(&to, t));
+		camera
+			.perspective
+			.set_fovy(lerp(a.perspective.fovy(), b.perspective.fovy()));
+		camera.aperture = lerp(a.aperture, b.aperture);
+		camera.focal_distance = lerp(a.focal_distance, b.focal_distance);
+		camera
+	}
+
 	pub fn create_primary(&self, x: u32, y: u32) -> Ray {
-		let normalized = Point2::new(x as f64 / self.width as f64, y as f64 / self.height as f64);
+		self.create_primary_at(x as f64, y as f64)
+	}
+
+	/// Creates a primary ray through a fractional pixel position, starting from a point of the
+	/// lens. `lens` is in [0, 1)², (0, 0) being the center of the lens, where rays are the same
+	/// as with a pinhole camera.
+	pub fn create_primary_lens(&self, x: f64, y: f64, lens: Vector2<f64>) -> Ray {
+		let pinhole = self.create_primary_at(x, y);
+		let radius = self.aperture * lens.x.sqrt();
+		if radius <= 0.0 {
+			return pinhole;
+		}
+
+		let focus = pinhole.origin + pinhole.direction.as_ref() * self.focal_distance;
+		let angle = 2.0 * f64::consts::PI * lens.y;
+		let offset = Vector::new(radius * angle.cos(), radius * angle.sin(), 0.0);
+		let origin = pinhole.origin + self.isometry.rotation * offset;
+		Ray {
+			origin,
+			direction: Unit::new_normalize(focus - origin),
+		}
+	}
+
+	/// Creates a primary ray through a fractional pixel position, for sampling within a pixel
+	pub fn create_primary_at(&self, x: f64, y: f64) -> Ray {
+		let normalized = Point2::new(x / self.width as f64, y / self.height as f64);
 		let nds = normalized * 2.0 - Point2::new(1.0, 1.0);
+		if let ProjectionMode::Orthographic { world_width } = self.projection {
+			let half_extent = self.orthographic_half_extent(world_width);
+			let offset = Point::new(nds.x * half_extent.x, nds.y * half_extent.y, 0.0);
+			return Ray {
+				origin: self.isometry.transform_point(&offset),
+				direction: self.isometry.rotation * -Vector::z_axis(),
+			};
+		}
+
 		let ndc_near = Point::new(nds.x, nds.y, -1.0);
 		let ndc_far = Point::new(nds.x, nds.y, 1.0);
 
@@ -149,6 +618,62 @@ impl Camera {
 		Ray { origin, direction }
 	}
 
+	/// Projects a point in world space to (fractional) pixel coordinates, the inverse of
+	/// `create_primary`. Returns `None` for points behind the camera.
+	pub fn world_to_pixel(&self, point: &Point) -> Option<(f64, f64)> {
+		let view = self.isometry.inverse_transform_point(point);
+		if view.z >= 0.0 {
+			return None;
+		}
+		let ndc = match self.projection {
+			ProjectionMode::Perspective => self.perspective.project_point(&view),
+			ProjectionMode::Orthographic { world_width } => {
+				let half_extent = self.orthographic_half_extent(world_width);
+				Point::new(view.x / half_extent.x, view.y / half_extent.y, 0.0)
+			}
+		};
+
+		Some((
+			(ndc.x + 1.0) / 2.0 * self.width as f64,
+			(ndc.y + 1.0) / 2.0 * self.height as f64,
+		))
+	}
+
+	/// Solid angle (in steradians) subtended by the pixel at `(x, y)`, computed from the spherical
+	/// quadrilateral spanned by the directions through its four corners. Pixels of orthographic
+	/// cameras, whose rays are parallel, subtend none.
+	pub fn pixel_solid_angle(&self, x: u32, y: u32) -> f64 {
+		if let ProjectionMode::Orthographic { .. } = self.projection {
+			return 0.0;
+		}
+		let (x, y) = (x as f64, y as f64);
+		let a = self.view_direction(x, y);
+		let b = self.view_direction(x + 1.0, y);
+		let c = self.view_direction(x + 1.0, y + 1.0);
+		let d = self.view_direction(x, y + 1.0);
+
+		triangle_solid_angle(&a, &b, &c) + triangle_solid_angle(&a, &c, &d)
+	}
+
+	fn view_direction(&self, x: f64, y: f64) -> Unit<Vector> {
+		let nds = Point2::new(x / self.width as f64, y / self.height as f64) * 2.0
+			- Point2::new(1.0, 1.0);
+		let near = self
+			.perspective
+			.unproject_point(&Point::new(nds.x, nds.y, -1.0));
+		let far = self
+			.perspective
+			.unproject_point(&Point::new(nds.x, nds.y, 1.0));
+
+		Unit::new_normalize(far - near)
+	}
+
+	/// Half the width and height, in world units, of the rectangle orthographic rays start from
+	fn orthographic_half_extent(&self, world_width: f64) -> Vector2<f64> {
+		let world_height = world_width * self.height as f64 / self.width as f64;
+		Vector2::new(world_width, world_height) / 2.0
+	}
+
 	pub fn set_position(&mut self, pos: Translation3<f64>) {
 		self.isometry = Isometry3::from_parts(pos, self.isometry.rotation);
 	}
@@ -158,7 +683,79 @@ impl Camera {
 	}
 }
 
+impl fmt::Display for Ray {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Ray {{ origin: ({:.3}, {:.3}, {:.3}), direction: ({:.3}, {:.3}, {:.3}) }}",
+			self.origin.x,
+			self.origin.y,
+			self.origin.z,
+			self.direction.x,
+			self.direction.y,
+			self.direction.z
+		)
+	}
+}
+
+impl fmt::Display for Camera {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let pos = self.isometry.translation.vector;
+		let dir = self.isometry.rotation * -Vector::z();
+		write!(
+			f,
+			"Camera {{ position: ({:.3}, {:.3}, {:.3}), direction: ({:.3}, {:.3}, {:.3}), fov: {:.1}°, resolution: {}x{} }}",
+			pos.x,
+			pos.y,
+			pos.z,
+			dir.x,
+			dir.y,
+			dir.z,
+			self.perspective.fovy().to_degrees(),
+			self.width,
+			self.height
+		)
+	}
+}
+
 impl Scene {
+	pub fn camera(&self) -> &Camera {
+		&self.camera
+	}
+
+	pub fn camera_mut(&mut self) -> &mut Camera {
+		&mut self.camera
+	}
+
+	pub fn sphere(&self) -> &Sphere {
+		&self.sphere
+	}
+
+	pub fn add_sphere(&mut self, sphere: Sphere) -> &mut Self {
+		self.objects.push(sphere);
+		self
+	}
+
+	/// The main sphere followed by the additional objects
+	pub fn spheres(&self) -> impl Iterator<Item = &Sphere> {
+		std::iter::once(&self.sphere).chain(self.objects.iter())
+	}
+
+	/// Closest sphere in front of the ray, along with the distance to its surface
+	fn closest_sphere(&self, ray: &Ray) -> Option<(&Sphere, f64)> {
+		self.spheres()
+			.filter_map(|s| s.intersect(ray).filter(|&t| t >= 0.0).map(|t| (s, t)))
+			.min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+	}
+
+	/// The scene's accretion ring. Scenes currently always have one.
+	pub fn ring(&self) -> Option<&Ring> {
+		Some(&self.ring)
+	}
+
+	/// Updates the camera transform and field of view. Each component is applied independently: a
+	/// `None` translation or rotation keeps the camera's current value for that component, and a
+	/// `None` field of view (in degrees) leaves the perspective untouched.
 	pub fn set_camera(
 		&mut self,
 		trans: Option<Translation3<f64>>,
@@ -184,6 +781,89 @@ impl Scene {
 			.set_aspect(width as f64 / height as f64);
 	}
 
+	/// Tightest axis-aligned box containing every object of the scene
+	pub fn scene_aabb(&self) -> AABB {
+		let outer = self.ring.radius.1;
+		let ring = AABB::new(
+			self.ring.pos - Vector::new(outer, outer, 0.0),
+			self.ring.pos + Vector::new(outer, outer, 0.0),
+		);
+
+		self.spheres().fold(ring, |aabb, s| {
+			let r = Vector::repeat(s.radius);
+			aabb.union(&AABB::new(s.pos - r, s.pos + r))
+		})
+	}
+
+	/// Pixels whose primary ray hits the sphere at the given surface point, within the size of
+	/// the pixel's footprint on the sphere. Hits hidden behind the ring are left out.
+	pub fn pixels_for_surface_point(&self, point: Point) -> Vec<(u32, u32)> {
+		let mut pixels = vec![];
+		for y in 0..self.camera.height {
+			for x in 0..self.camera.width {
+				let ray = self.camera.create_primary(x, y);
+				let t = match self.sphere.intersect(&ray) {
+					Some(t) => t,
+					None => continue,
+				};
+				if let Some(tr) = self.ring.intersect(&ray) {
+					if tr >= 0.0 && tr < t {
+						continue;
+					}
+				}
+				let hit = ray.origin + ray.direction.as_ref() * t;
+				let tolerance = t * self.camera.pixel_solid_angle(x, y).sqrt();
+				if (hit - point).norm() <= tolerance {
+					pixels.push((x, y));
+				}
+			}
+		}
+
+		pixels
+	}
+
+	/// Renders the scene, same as the `render_with_config` free function.
+	pub fn render(
+		&self,
+		config: RenderConfig,
+		r: Option<Reporter>,
+	) -> Result<DynamicImage, RenderError> {
+		render_with_config(self.clone(), config, r)
+	}
+
+	/// Renders only the pixels of `[x0, x1) × [y0, y1)`, to check a detail of the scene without
+	/// rendering the whole image. The result has the size of the region, clipped to the image.
+	pub fn render_region(
+		&self,
+		x0: u32,
+		y0: u32,
+		x1: u32,
+		y1: u32,
+		reporter: Option<Reporter>,
+	) -> Result<DynamicImage, RenderError> {
+		render_region(
+			self.clone(),
+			(x0, y0, x1, y1),
+			RenderConfig::default(),
+			reporter,
+		)
+	}
+
+	/// Renders the scene's surface normals, remapped from [-1, 1] to [0, 1] per channel. Pixels
+	/// that don't hit any object are flat blue, `(0.5, 0.5, 1.0)`.
+	pub fn render_normal_map(&self, config: RenderConfig) -> Result<DynamicImage, RenderError> {
+		render_with_config(NormalMap(self.clone()), config, None)
+	}
+
+	/// Renders the normal map like `render_normal_map`, on an already-built thread pool
+	pub fn render_normal_map_with_pool(
+		&self,
+		pool: &ThreadPool,
+		config: RenderConfig,
+	) -> Result<DynamicImage, RenderError> {
+		render_with_pool(NormalMap(self.clone()), pool, config, None)
+	}
+
 	pub fn get_background(mut self) -> Texture {
 		if let Some(tex) = self.bgtex {
 			return tex;
@@ -194,52 +874,108 @@ impl Scene {
 	}
 
 	pub fn create_bg_texture(width: u32, height: u32) -> Result<Texture, &'static str> {
-		let mut bg_img = DynamicImage::new_rgb8(width, height);
-
-		match bg_img.as_mut_rgb8() {
-			Some(buf) => {
-				for (x, y, p) in buf.enumerate_pixels_mut() {
-					if (x + y) % 2 == 0 {
-						*p = Rgb::from_channels(255, 255, 0, 255);
-					} else {
-						*p = Rgb::from_channels(0, 255, 255, 255);
-					}
+		let (w, h) = (width as f64, height as f64);
+		let bgtex = Texture::from_fn(
+			width,
+			height,
+			|u, v| {
+				let (x, y) = ((u * w) as u32, (v * h) as u32);
+				if (x + y) % 2 == 0 {
+					Color::from_u32(0xFFFFFF00)
+				} else {
+					Color::from_u32(0xFF00FFFF)
 				}
+			},
+			TextureFiltering::Nearest,
+			TextureMode::Repeat,
+		);
+		return Ok(bgtex);
+	}
+}
+
+impl Scene {
+	/// Color of the primary ray through the pixel position and lens point, along with what it
+	/// hit and the AOVs of the hit
+	fn shade(&self, x: f64, y: f64, lens: Vector2<f64>) -> (Color, Option<RayHit>, PixelAovs) {
+		let this = self.clone();
+		let bgtex = this.get_background();
+		let ray = self.camera.create_primary_lens(x, y, lens);
+		let background = || {
+			let uv = background_uv_from_direction(&ray.direction);
+			(bgtex.uv(uv), Some(RayHit::Background), PixelAovs::default())
+		};
+
+		if !self.scene_aabb().intersects(&ray) {
+			return background();
+		}
+
+		// Whichever of the ring and the closest sphere is hit first hides the other
+		let ring_hit = self.ring.intersect(&ray);
+		let sphere_hit = self.closest_sphere(&ray);
+		match (ring_hit, sphere_hit) {
+			(Some(p), sphere) if sphere.is_none_or(|(_, t)| p <= t) => {
+				let hit = ray.origin + ray.direction.as_ref() * p;
+				let col = if self.ring.mipmaps.is_some() {
+					let footprint = p * self.camera.pixel_solid_angle(x as u32, y as u32).sqrt();
+					self.ring.sample_texture_lod(hit, &ray, footprint)
+				} else {
+					self.ring.sample_texture(hit, &ray)
+				};
+				let aovs = PixelAovs {
+					depth: Some(p),
+					normal: Some(self.ring.facing_normal(&ray)),
+					uv: Some(self.ring.texture_coords(&hit)),
+				};
+				(col, Some(RayHit::Ring), aovs)
+			}
+			(_, Some((sphere, p))) => {
+				let hit = ray.origin + ray.direction.as_ref() * p;
+				let uv = sphere.texture_coords(&hit);
+				let aovs = PixelAovs {
+					depth: Some(p),
+					normal: Some(sphere.surface_normal(&hit)),
+					uv: Some(uv),
+				};
+				(sphere.texture.uv(uv), Some(RayHit::Sphere), aovs)
 			}
-			None => return Err("Couldn't create background texture"),
+			(_, None) => background(),
 		}
+	}
+}
 
-		let bgtex = Texture(bg_img, TextureFiltering::Nearest, TextureMode::Repeat);
-		return Ok(bgtex);
+#[derive(Clone)]
+struct NormalMap(Scene);
+
+impl Renderable for NormalMap {
+	fn render_px(&self, x: u32, y: u32) -> Color {
+		match self.0.shade(x as f64, y as f64, Vector2::zeros()).2.normal {
+			Some(n) => Color::new((n.x + 1.0) / 2.0, (n.y + 1.0) / 2.0, (n.z + 1.0) / 2.0),
+			None => Color::new(0.5, 0.5, 1.0),
+		}
+	}
+
+	fn get_dimensions(&self) -> (u32, u32) {
+		self.0.get_dimensions()
 	}
 }
 
 impl Renderable for Scene {
 	fn render_px(&self, x: u32, y: u32) -> Color {
-		let this = self.clone();
-		let bgtex = this.get_background();
-		let ray = self.camera.create_primary(x, y);
+		self.trace_px(x, y).0
+	}
 
-		self.ring
-			.intersect(&ray)
-			.map(|p| {
-				let hit = ray.origin + ray.direction.as_ref() * p;
-				let uv = self.ring.texture_coords(&hit);
-				return self.ring.texture_top.uv(uv);
-			})
-			.or_else(|| {
-				self.sphere.intersect(&ray).map(|p| {
-					let hit = ray.origin + ray.direction.as_ref() * p;
-					let uv = self.sphere.texture_coords(&hit);
-					return self.sphere.texture.uv(uv);
-				})
-			})
-			.or_else(|| {
-				let (_, theta, phi) = cartesian_to_spherical(&ray.direction);
-				let uv = TexCoords::new(theta / f64::consts::PI, 0.5 * phi / f64::consts::PI + 0.5);
-				return Some(bgtex.uv(uv));
-			})
-			.unwrap()
+	fn trace_px(&self, x: u32, y: u32) -> (Color, Option<RayHit>) {
+		self.trace_subpx(x as f64, y as f64, Vector2::zeros())
+	}
+
+	fn trace_subpx(&self, x: f64, y: f64, lens: Vector2<f64>) -> (Color, Option<RayHit>) {
+		let (col, hit, _) = self.shade(x, y, lens);
+		(col, hit)
+	}
+
+	fn render_px_aovs(&self, x: u32, y: u32) -> (Color, PixelAovs) {
+		let (col, _, aovs) = self.shade(x as f64, y as f64, Vector2::zeros());
+		(col, aovs)
 	}
 
 	fn get_dimensions(&self) -> (u32, u32) {
@@ -248,98 +984,942 @@ impl Renderable for Scene {
 }
 
 pub mod render {
-	use super::Renderable;
+	use super::{PixelAovs, RayHit, Renderable};
+	use nalgebra::Vector2;
 
-	use crate::utils::{color_to_rgba, DimIterator};
-	use image::{DynamicImage, GenericImageView};
-	// use rayon::prelude::*;
-	use rayon::ThreadPoolBuilder;
+	use crate::postprocess::ToneMapping;
+	use crate::utils::DimIterator;
+	use color::Color;
+	use deflate::write::ZlibEncoder;
+	use image::DynamicImage;
+	use rayon::prelude::*;
+	use rayon::{ThreadPool, ThreadPoolBuilder};
 
+	use std::error::Error;
+	use std::fmt;
+	use std::fs;
+	use std::io::{self, BufWriter, Write};
+	use std::path::Path;
+	use std::sync::atomic::{AtomicU64, Ordering};
 	use std::sync::{mpsc, Arc, Mutex};
 
-	type Reporter<'a> = &'a Fn(f64, String);
+	pub type Reporter<'a> = &'a dyn Fn(f64, String);
 
-	pub fn render<'a, R: Renderable + Clone + Send + Sync + 'static>(
-		o: R,
-		r: Option<Reporter<'a>>,
-	) -> Result<DynamicImage, &'static str> {
-		let (width, height) = o.get_dimensions();
-		let num_threads = num_cpus::get().min(30); // Set an upper bound on the number of threads to not overwhelm the OS
-		let chunk_size = 32u32;
-		let num_columns = 1 + width / chunk_size;
-		let num_rows = 1 + height / chunk_size;
-
-		let pool = ThreadPoolBuilder::new()
-			.num_threads(num_threads)
-			.build()
-			.or(Err("Cannot setup threading"))?;
-		let (tx, rx) = mpsc::channel();
+	/// Traces a pixel given the subpixel offsets and lens positions of its samples, recording
+	/// what they hit
+	type PixelTracer<R, T> = fn(&R, u32, u32, &[(Vector2<f64>, Vector2<f64>)], &StatsCounter) -> T;
 
-		let osrc = Arc::new(o);
-		let misses = Arc::new(Mutex::new(0u32));
+	/// Number of tiles traced between two checkpoints of `render_with_checkpoint`
+	const CHECKPOINT_TILES: usize = 16;
 
-		for cy in 0..num_rows {
-			for cx in 0..num_columns {
-				let x = chunk_size * cx;
-				let y = chunk_size * cy;
-				let x_size = chunk_size.min(width - x);
-				let y_size = chunk_size.min(height - y);
-
-				let ttx = tx.clone();
-				let this = Arc::clone(&osrc);
-				let m = Arc::clone(&misses);
-
-				pool.spawn(move || {
-					for (x, y) in DimIterator::create(x_size, y_size, x, y) {
-						match ttx.send((x, y, this.render_px(x, y))) {
-							Ok(_) => (),
-							Err(_) => {
-								let mut mref = m.lock().unwrap();
-								*mref += 1;
-							}
-						}
-					}
+	#[derive(Clone, Debug)]
+	pub struct RenderConfig {
+		/// Side length, in pixels, of the square tiles dispatched to the thread pool
+		pub chunk_size: u32,
+		/// Number of render threads, or `None` to use one per CPU (capped at 30)
+		pub num_threads: Option<usize>,
+		/// Order in which tiles are dispatched
+		pub tile_order: TileOrder,
+		/// Number of rays averaged per pixel for antialiasing, 1 to disable it
+		pub samples: u32,
+		/// Operator mapping the rendered linear light to the display range
+		pub tone_mapping: ToneMapping,
+	}
+
+	#[derive(Clone, Copy, Debug, PartialEq)]
+	pub enum TileOrder {
+		/// Left to right, top to bottom
+		RowMajor,
+		/// Closest to the image center first, where the black hole usually is, for faster previews
+		CenterOut,
+	}
+
+	/// Counts of what the primary rays of a render hit
+	#[derive(Clone, Copy, Debug, Default, PartialEq)]
+	pub struct SceneStats {
+		pub sphere_hits: u64,
+		pub ring_hits: u64,
+		pub background_hits: u64,
+		pub total_rays: u64,
+	}
+
+	pub struct RenderResult {
+		pub image: DynamicImage,
+		pub stats: SceneStats,
+	}
+
+	/// Beauty pass of a render along with its arbitrary output variables (AOVs), each stored per
+	/// pixel in row-major order
+	pub struct RenderOutput {
+		pub image: DynamicImage,
+		/// Distance from the camera to what each pixel's primary ray hit, NaN where it hit
+		/// nothing
+		pub depth: Vec<f32>,
+		/// World-space surface normal at each pixel's hit, zero where it hit nothing
+		pub normals: Vec<[f32; 3]>,
+		/// Texture coordinates of each pixel's hit, zero where it hit nothing. See
+		/// `postprocess::from_uv_buffer` to view them.
+		pub uv: Vec<[f32; 2]>,
+	}
+
+	#[derive(Default)]
+	struct StatsCounter {
+		sphere_hits: AtomicU64,
+		ring_hits: AtomicU64,
+		background_hits: AtomicU64,
+		total_rays: AtomicU64,
+	}
+
+	#[derive(Clone, Debug, PartialEq)]
+	pub enum RenderError {
+		ThreadPoolBuildFailed,
+		ImageBufferAllocationFailed,
+		/// Some pixels never made it into the image, with how many
+		MissedPixels(u32),
+		/// Writing the image out failed, with the reason
+		WriteFailed(String),
+		/// The checkpoint to resume from is unreadable or belongs to another render
+		InvalidCheckpoint(String),
+	}
+
+	/// Image formats `render_streaming` can write
+	#[derive(Clone, Copy, Debug, PartialEq)]
+	pub enum OutputFormat {
+		/// 8-bit RGBA PNG
+		PNG,
+		/// Binary (P6) PPM, which has no alpha channel
+		PPM,
+	}
+
+	impl Default for RenderConfig {
+		fn default() -> Self {
+			RenderConfig {
+				chunk_size: 32,
+				num_threads: None,
+				tile_order: TileOrder::RowMajor,
+				samples: 1,
+				tone_mapping: ToneMapping::None,
+			}
+		}
+	}
+
+	impl TileOrder {
+		/// Splits the image into tiles, returned as (x, y, width, height) in dispatch order
+		pub(super) fn tiles(
+			self,
+			width: u32,
+			height: u32,
+			chunk_size: u32,
+		) -> Vec<(u32, u32, u32, u32)> {
+			let mut tiles: Vec<_> = (0..height)
+				.step_by(chunk_size as usize)
+				.flat_map(|y| {
+					(0..width)
+						.step_by(chunk_size as usize)
+						.map(move |x| (x, y, chunk_size.min(width - x), chunk_size.min(height - y)))
 				})
+				.collect();
+			if self == TileOrder::CenterOut {
+				let dist2 = |&(x, y, w, h): &(u32, u32, u32, u32)| {
+					// Doubled coordinates keep tile centers integral
+					let dx = i64::from(2 * x + w) - i64::from(width);
+					let dy = i64::from(2 * y + h) - i64::from(height);
+					dx * dx + dy * dy
+				};
+				// Stable sort, so tiles at the same distance stay in row-major order
+				tiles.sort_by_key(dist2);
 			}
+
+			tiles
 		}
+	}
 
-		drop(tx);
+	/// Clips tiles to the `(x0, y0, x1, y1)` region, dropping the ones entirely outside of it
+	fn clip_tiles(
+		tiles: Vec<(u32, u32, u32, u32)>,
+		(x0, y0, x1, y1): (u32, u32, u32, u32),
+	) -> Vec<(u32, u32, u32, u32)> {
+		tiles
+			.into_iter()
+			.filter_map(|(x, y, w, h)| {
+				let (left, top) = (x.max(x0), y.max(y0));
+				let (right, bottom) = ((x + w).min(x1), (y + h).min(y1));
+				if left < right && top < bottom {
+					Some((left, top, right - left, bottom - top))
+				} else {
+					None
+				}
+			})
+			.collect()
+	}
+
+	impl RenderConfig {
+		fn thread_count(&self) -> usize {
+			// Set an upper bound on the number of threads to not overwhelm the OS
+			self.num_threads.unwrap_or_else(|| num_cpus::get().min(30))
+		}
+
+		/// Builds a thread pool of `num_threads` threads, to be reused across renders with
+		/// `render_with_pool`
+		pub fn thread_pool(&self) -> Result<ThreadPool, RenderError> {
+			ThreadPoolBuilder::new()
+				.num_threads(self.thread_count())
+				.build()
+				.or(Err(RenderError::ThreadPoolBuildFailed))
+		}
+
+		/// Subpixel offsets and lens positions of the samples, following the Halton sequence in
+		/// bases 2 and 3, and 5 and 7 for the lens. The first sample is always the pixel's own
+		/// corner and the lens center, so a single sample matches no antialiasing.
+		pub(crate) fn sample_offsets(&self) -> Vec<(Vector2<f64>, Vector2<f64>)> {
+			(0..self.samples.max(1))
+				.map(|i| {
+					(
+						Vector2::new(halton(i, 2), halton(i, 3)),
+						Vector2::new(halton(i, 5), halton(i, 7)),
+					)
+				})
+				.collect()
+		}
+	}
+
+	impl StatsCounter {
+		fn record(&self, hit: Option<RayHit>) {
+			self.total_rays.fetch_add(1, Ordering::Relaxed);
+			match hit {
+				Some(RayHit::Sphere) => self.sphere_hits.fetch_add(1, Ordering::Relaxed),
+				Some(RayHit::Ring) => self.ring_hits.fetch_add(1, Ordering::Relaxed),
+				Some(RayHit::Background) => self.background_hits.fetch_add(1, Ordering::Relaxed),
+				None => 0,
+			};
+		}
+
+		fn snapshot(&self) -> SceneStats {
+			SceneStats {
+				sphere_hits: self.sphere_hits.load(Ordering::Relaxed),
+				ring_hits: self.ring_hits.load(Ordering::Relaxed),
+				background_hits: self.background_hits.load(Ordering::Relaxed),
+				total_rays: self.total_rays.load(Ordering::Relaxed),
+			}
+		}
+	}
+
+	impl fmt::Display for RenderError {
+		fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+			match self {
+				RenderError::ThreadPoolBuildFailed => write!(f, "Cannot setup threading"),
+				RenderError::ImageBufferAllocationFailed => write!(f, "Couldn't create image"),
+				RenderError::MissedPixels(n) => write!(f, "Missed/Overshot {} pixels", n),
+				RenderError::WriteFailed(reason) => write!(f, "Couldn't write image: {}", reason),
+				RenderError::InvalidCheckpoint(reason) => {
+					write!(f, "Can't resume from checkpoint: {}", reason)
+				}
+			}
+		}
+	}
+
+	impl Error for RenderError {}
+
+	impl From<io::Error> for RenderError {
+		fn from(err: io::Error) -> Self {
+			RenderError::WriteFailed(err.to_string())
+		}
+	}
+
+	pub fn render<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		render_with_config(o, RenderConfig::default(), r)
+	}
+
+	pub fn render_with_config<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		let pool = config.thread_pool()?;
+
+		render_with_pool(o, &pool, config, r)
+	}
+
+	/// Renders like `render_with_config`, also returning statistics on what the rays hit
+	pub fn render_with_stats<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<RenderResult, RenderError> {
+		let pool = config.thread_pool()?;
+
+		render_tiles(o, &pool, config, r)
+	}
+
+	/// Renders on an already-built thread pool, so it can be reused across frames. The
+	/// `num_threads` setting of the configuration is ignored in favor of the pool's own.
+	pub fn render_with_pool<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		pool: &ThreadPool,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		render_tiles(o, pool, config, r).map(|res| res.image)
+	}
+
+	/// Renders only the pixels of `[x0, x1) × [y0, y1)`, into an image of the size of the
+	/// region. The region is clipped to the image. Pixels are the same as in a full render.
+	pub fn render_region<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		region: (u32, u32, u32, u32),
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		let pool = config.thread_pool()?;
+		let (width, height) = o.get_dimensions();
+		let (x1, y1) = (region.2.min(width), region.3.min(height));
+		let region = (region.0.min(x1), region.1.min(y1), x1, y1);
+
+		render_region_tiles(o, &pool, region, config, r).map(|res| res.image)
+	}
+
+	/// Renders the beauty pass along with the AOVs of `RenderOutput`. The beauty pass is
+	/// antialiased like `render_with_config`, but the AOVs come from the first sample of each
+	/// pixel, through its corner, as blending depths between samples makes no sense.
+	pub fn render_with_aovs<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<RenderOutput, RenderError> {
+		let pool = config.thread_pool()?;
+		let (width, height) = o.get_dimensions();
+		let stats = Arc::new(StatsCounter::default());
+		let pixels = trace_region(
+			&Arc::new(o),
+			&pool,
+			(0, 0, width, height),
+			&config,
+			&stats,
+			sample_px_aovs,
+			r,
+		)?;
+
+		let mut img = DynamicImage::new_rgba8(width, height);
+		let buf = img
+			.as_mut_rgba8()
+			.ok_or(RenderError::ImageBufferAllocationFailed)?;
+		let mut depth = Vec::with_capacity(pixels.len());
+		let mut normals = Vec::with_capacity(pixels.len());
+		let mut uv = Vec::with_capacity(pixels.len());
+		for (i, (col, aovs)) in pixels.into_iter().enumerate() {
+			buf.put_pixel(i as u32 % width, i as u32 / width, col.into());
+			depth.push(aovs.depth.map_or(f32::NAN, |d| d as f32));
+			normals.push(
+				aovs.normal
+					.map_or([0.0; 3], |n| [n.x as f32, n.y as f32, n.z as f32]),
+			);
+			uv.push(aovs.uv.map_or([0.0; 2], |uv| [uv.x as f32, uv.y as f32]));
+		}
+
+		Ok(RenderOutput {
+			image: config.tone_mapping.apply(img),
+			depth,
+			normals,
+			uv,
+		})
+	}
+
+	/// Renders in full precision, without tone mapping, returning every pixel's color in
+	/// row-major order. Unlike the 8-bit images of `render`, values above 1 are kept, for HDR
+	/// output.
+	pub fn render_hdr<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<Vec<(u32, u32, Color)>, RenderError> {
+		let pool = config.thread_pool()?;
+		let width = o.get_dimensions().0.max(1);
+
+		trace_tiles(o, &pool, &config, r).map(|pixels| {
+			pixels
+				.into_iter()
+				.enumerate()
+				.map(|(i, col)| (i as u32 % width, i as u32 / width, col))
+				.collect()
+		})
+	}
+
+	/// Renders straight into `writer`, for images too large to be held in memory. The image is
+	/// traced in bands of `chunk_size` rows, tile by tile like `render_with_config`, and only one
+	/// band is ever in memory. Its rows are tone mapped and written out in order once the whole
+	/// band is traced. As rows only ever go after the previous ones, the writer doesn't need to
+	/// be seekable, so pipes and sockets work too.
+	pub fn render_streaming<'a, R, W>(
+		o: R,
+		mut writer: W,
+		format: OutputFormat,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<(), RenderError>
+	where
+		R: Renderable + Clone + Send + Sync + 'static,
+		W: Write,
+	{
+		let pool = config.thread_pool()?;
+		let (width, height) = o.get_dimensions();
+
+		match format {
+			OutputFormat::PPM => {
+				write!(writer, "P6\n{} {}\n255\n", width, height)?;
+				trace_rows(o, &pool, &config, r, |row| {
+					let bytes: Vec<u8> = row
+						.chunks(4)
+						.flat_map(|px| px[..3].iter().copied())
+						.collect();
+					writer.write_all(&bytes)
+				})?;
+				writer.flush()?;
+			}
+			OutputFormat::PNG => {
+				let mut encoder = png::Encoder::new(writer, width, height);
+				encoder.set_color(png::ColorType::RGBA);
+				encoder.set_depth(png::BitDepth::Eight);
+				let mut png = encoder
+					.write_header()
+					.map_err(|e| RenderError::WriteFailed(e.to_string()))?;
+				let idat = BufWriter::new(IdatWriter(&mut png));
+				let mut zlib = ZlibEncoder::new(idat, deflate::Compression::Default);
+
+				trace_rows(o, &pool, &config, r, |row| {
+					// Each scanline starts with its filter type, none here
+					zlib.write_all(&[0])?;
+					zlib.write_all(row)
+				})?;
+				zlib.finish()?.flush()?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Renders like `render`, saving progress to `checkpoint_path` every `CHECKPOINT_TILES` tiles
+	/// so that an interrupted render can be resumed. With `resume`, an existing checkpoint is
+	/// loaded and the tiles it holds are skipped; it must come from a render of the same size.
+	///
+	/// The checkpoint is a header of little-endian `u32`s (width, height and number of completed
+	/// tiles) followed by the traced pixels in row-major order, as little-endian `f32` RGBA.
+	/// Samples are placed deterministically, so there is no random state to save.
+	pub fn render_with_checkpoint<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		checkpoint_path: &Path,
+		resume: bool,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		let config = RenderConfig::default();
+		render_checkpointed(o, config, checkpoint_path, resume, None, r)
+	}
+
+	/// Checkpointed render, stopping after `tile_limit` tiles if given. The image then only has
+	/// the tiles traced so far.
+	pub(super) fn render_checkpointed<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		config: RenderConfig,
+		checkpoint_path: &Path,
+		resume: bool,
+		tile_limit: Option<usize>,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		let pool = config.thread_pool()?;
+		let (width, height) = o.get_dimensions();
+		let tiles = config
+			.tile_order
+			.tiles(width, height, config.chunk_size.max(1));
+		let osrc = Arc::new(o);
+		let stats = Arc::new(StatsCounter::default());
+
+		let (mut done, mut pixels) = if resume && checkpoint_path.exists() {
+			read_checkpoint(checkpoint_path, width, height, tiles.len())?
+		} else {
+			(0, vec![[0.0; 4]; (width * height) as usize])
+		};
+		let end = tile_limit.map_or(tiles.len(), |limit| limit.min(tiles.len()));
+		while done < end {
+			if let Some(f) = r {
+				(*f)(
+					done as f64 / tiles.len() as f64,
+					String::from("Raytracing..."),
+				);
+			}
+			let batch = &tiles[done..(done + CHECKPOINT_TILES).min(end)];
+			let traced = trace_tile_set(&osrc, &pool, batch, &config, &stats, sample_px, None)?;
+			for (x, y, col) in traced {
+				pixels[(y * width + x) as usize] = [
+					col.red as f32,
+					col.green as f32,
+					col.blue as f32,
+					col.alpha as f32,
+				];
+			}
+			done += batch.len();
+			write_checkpoint(checkpoint_path, width, height, done, &pixels)?;
+		}
+
+		let mut img = DynamicImage::new_rgba8(width, height);
+		let buf = img
+			.as_mut_rgba8()
+			.ok_or(RenderError::ImageBufferAllocationFailed)?;
+		for (i, px) in pixels.iter().enumerate() {
+			let mut col = Color::new(px[0] as f64, px[1] as f64, px[2] as f64);
+			col.alpha = px[3] as f64;
+			buf.put_pixel(i as u32 % width, i as u32 / width, col.into());
+		}
+
+		Ok(config.tone_mapping.apply(img))
+	}
+
+	/// Saves the checkpoint next to its destination first, so that an interruption while writing
+	/// leaves the previous checkpoint intact
+	fn write_checkpoint(
+		path: &Path,
+		width: u32,
+		height: u32,
+		tiles_done: usize,
+		pixels: &[[f32; 4]],
+	) -> Result<(), RenderError> {
+		let mut data = Vec::with_capacity(12 + pixels.len() * 16);
+		for v in &[width, height, tiles_done as u32] {
+			data.extend_from_slice(&v.to_le_bytes());
+		}
+		for v in pixels.iter().flatten() {
+			data.extend_from_slice(&v.to_le_bytes());
+		}
+		let tmp = path.with_extension("tmp");
+		fs::write(&tmp, data)?;
+		fs::rename(&tmp, path)?;
+
+		Ok(())
+	}
+
+	/// Loads a checkpoint, returning the number of completed tiles and the pixels
+	fn read_checkpoint(
+		path: &Path,
+		width: u32,
+		height: u32,
+		tile_count: usize,
+	) -> Result<(usize, Vec<[f32; 4]>), RenderError> {
+		let data = fs::read(path).map_err(|e| RenderError::InvalidCheckpoint(e.to_string()))?;
+		let word = |i: usize| {
+			let mut bytes = [0; 4];
+			bytes.copy_from_slice(&data[4 * i..4 * i + 4]);
+			bytes
+		};
+		if data.len() < 12 {
+			return Err(RenderError::InvalidCheckpoint(String::from(
+				"truncated header",
+			)));
+		}
+		let (w, h) = (u32::from_le_bytes(word(0)), u32::from_le_bytes(word(1)));
+		if (w, h) != (width, height) {
+			return Err(RenderError::InvalidCheckpoint(format!(
+				"saved from a {}x{} render",
+				w, h
+			)));
+		}
+		let done = u32::from_le_bytes(word(2)) as usize;
+		if done > tile_count {
+			return Err(RenderError::InvalidCheckpoint(format!(
+				"{} tiles completed out of {}",
+				done, tile_count
+			)));
+		}
+		let pixel_count = (width * height) as usize;
+		if data.len() != 12 + pixel_count * 16 {
+			return Err(RenderError::InvalidCheckpoint(String::from(
+				"unexpected file size",
+			)));
+		}
+
+		let pixels = (0..pixel_count)
+			.map(|i| {
+				let channel = |c: usize| f32::from_le_bytes(word(3 + 4 * i + c));
+				[channel(0), channel(1), channel(2), channel(3)]
+			})
+			.collect();
+
+		Ok((done, pixels))
+	}
+
+	/// Sends everything written to it as IDAT chunks of the PNG being encoded
+	struct IdatWriter<'w, W: Write>(&'w mut png::Writer<W>);
+
+	impl<'w, W: Write> Write for IdatWriter<'w, W> {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0
+				.write_chunk(png::chunk::IDAT, buf)
+				.map_err(|e| io::Error::other(e.to_string()))?;
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	/// Traces the image in bands of `chunk_size` rows, each one tile by tile like `trace_region`,
+	/// handing the tone mapped rows of a band to `emit` in order, as 8-bit RGBA, before moving on
+	/// to the next band
+	fn trace_rows<'a, R, F>(
+		o: R,
+		pool: &ThreadPool,
+		config: &RenderConfig,
+		r: Option<Reporter<'a>>,
+		mut emit: F,
+	) -> Result<(), RenderError>
+	where
+		R: Renderable + Clone + Send + Sync + 'static,
+		F: FnMut(&[u8]) -> io::Result<()>,
+	{
+		let (width, height) = o.get_dimensions();
+		let band_rows = config.chunk_size.max(1);
+		let osrc = Arc::new(o);
+		let stats = Arc::new(StatsCounter::default());
+
+		for band in (0..height).step_by(band_rows as usize) {
+			if let Some(f) = r {
+				(*f)(band as f64 / height as f64, String::from("Raytracing..."));
+			}
+			let bottom = (band + band_rows).min(height);
+			let region = (0, band, width, bottom);
+			let pixels = trace_region(&osrc, pool, region, config, &stats, sample_px, None)?;
+			let mut img = DynamicImage::new_rgba8(width, bottom - band);
+			let buf = img
+				.as_mut_rgba8()
+				.ok_or(RenderError::ImageBufferAllocationFailed)?;
+			for (i, col) in pixels.into_iter().enumerate() {
+				buf.put_pixel(i as u32 % width, i as u32 / width, col.into());
+			}
+			// Tone mapping works pixel by pixel, so bands map like the whole image would
+			let bytes = config.tone_mapping.apply(img).to_rgba().into_raw();
+			for row in bytes.chunks(4 * width.max(1) as usize) {
+				emit(row)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn render_tiles<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		pool: &ThreadPool,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<RenderResult, RenderError> {
+		let (width, height) = o.get_dimensions();
+		render_region_tiles(o, pool, (0, 0, width, height), config, r)
+	}
+
+	fn render_region_tiles<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		pool: &ThreadPool,
+		region: (u32, u32, u32, u32),
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<RenderResult, RenderError> {
+		let (width, height) = (region.2 - region.0, region.3 - region.1);
+		let stats = Arc::new(StatsCounter::default());
+		let pixels = trace_region(&Arc::new(o), pool, region, &config, &stats, sample_px, r)?;
 
 		let mut img = DynamicImage::new_rgba8(width, height);
 		match img.as_mut_rgba8() {
 			Some(buf) => {
-				let mut i: usize = 0;
-				let tot = width * height;
-				for (x, y, col) in rx.into_iter() {
-					if let Some(f) = r {
-						if i % 40 == 0 {
-							let nm = *misses.lock().unwrap();
-							if nm > 0 {
-								(*f)(
-									i as f64 / tot as f64,
-									format!("Raytracing ({} missed/overshot pixels)...", nm),
-								);
-							} else {
-								(*f)(i as f64 / tot as f64, format!("Raytracing..."));
-							}
+				for (i, col) in pixels.into_iter().enumerate() {
+					buf.put_pixel(i as u32 % width, i as u32 / width, col.into());
+				}
+				Ok(RenderResult {
+					image: config.tone_mapping.apply(img),
+					stats: stats.snapshot(),
+				})
+			}
+			None => Err(RenderError::ImageBufferAllocationFailed),
+		}
+	}
+
+	/// Traces every pixel on the pool, returning their colors in row-major order
+	fn trace_tiles<'a, R: Renderable + Clone + Send + Sync + 'static>(
+		o: R,
+		pool: &ThreadPool,
+		config: &RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<Vec<Color>, RenderError> {
+		let (width, height) = o.get_dimensions();
+		let stats = Arc::new(StatsCounter::default());
+		trace_region(
+			&Arc::new(o),
+			pool,
+			(0, 0, width, height),
+			config,
+			&stats,
+			sample_px,
+			r,
+		)
+	}
+
+	/// Traces the pixels of the `(x0, y0, x1, y1)` region on the pool with `trace`, returning
+	/// them in row-major order. Tiles are laid out over the whole image, so tiles outside of the
+	/// region are skipped and the ones on its border clipped.
+	fn trace_region<'a, R, T>(
+		o: &Arc<R>,
+		pool: &ThreadPool,
+		region: (u32, u32, u32, u32),
+		config: &RenderConfig,
+		stats: &Arc<StatsCounter>,
+		trace: PixelTracer<R, T>,
+		r: Option<Reporter<'a>>,
+	) -> Result<Vec<T>, RenderError>
+	where
+		R: Renderable + Send + Sync + 'static,
+		T: Send + 'static,
+	{
+		let (full_width, full_height) = o.get_dimensions();
+		let (x0, y0) = (region.0, region.1);
+		let (width, height) = (region.2 - x0, region.3 - y0);
+		let tiles = config
+			.tile_order
+			.tiles(full_width, full_height, config.chunk_size.max(1));
+		let tiles = clip_tiles(tiles, region);
+
+		let region_px = DimIterator::create(width, height, x0, y0);
+		let mut pixels: Vec<Option<T>> = Vec::with_capacity(region_px.total_pixels() as usize);
+		pixels.extend(region_px.map(|_| None));
+		for (x, y, px) in trace_tile_set(o, pool, &tiles, config, stats, trace, r)? {
+			pixels[((y - y0) * width + x - x0) as usize] = Some(px);
+		}
+
+		// The clipped tiles cover the region, and pixels that never came in fail the trace
+		Ok(pixels.into_iter().map(Option::unwrap).collect())
+	}
+
+	/// Traces the pixels of `tiles` on the pool with `trace`, returning them along with their
+	/// position, in the order they came in. Fails with `MissedPixels` if some of them never did.
+	fn trace_tile_set<'a, R, T>(
+		o: &Arc<R>,
+		pool: &ThreadPool,
+		tiles: &[(u32, u32, u32, u32)],
+		config: &RenderConfig,
+		stats: &Arc<StatsCounter>,
+		trace: PixelTracer<R, T>,
+		r: Option<Reporter<'a>>,
+	) -> Result<Vec<(u32, u32, T)>, RenderError>
+	where
+		R: Renderable + Send + Sync + 'static,
+		T: Send + 'static,
+	{
+		let (tx, rx) = mpsc::channel();
+
+		let misses = Arc::new(Mutex::new(0u32));
+		let offsets = Arc::new(config.sample_offsets());
+
+		for &(x, y, x_size, y_size) in tiles {
+			let ttx = tx.clone();
+			let this = Arc::clone(o);
+			let m = Arc::clone(&misses);
+			let st = Arc::clone(stats);
+			let offs = Arc::clone(&offsets);
+
+			pool.spawn(move || {
+				for (x, y) in DimIterator::create(x_size, y_size, x, y) {
+					let px = trace(&*this, x, y, &offs, &st);
+					match ttx.send((x, y, px)) {
+						Ok(_) => (),
+						Err(_) => {
+							let mut mref = m.lock().unwrap();
+							*mref += 1;
 						}
 					}
-					i += 1;
-					if buf.in_bounds(x, y) {
-						buf.put_pixel(x, y, color_to_rgba(&col));
+				}
+			})
+		}
+
+		drop(tx);
+
+		let tot: u64 = tiles
+			.iter()
+			.map(|&(x, y, w, h)| DimIterator::create(w, h, x, y).total_pixels())
+			.sum();
+		let mut pixels = Vec::with_capacity(tot as usize);
+		for (i, px) in rx.into_iter().enumerate() {
+			if let Some(f) = r {
+				if i % 40 == 0 {
+					let nm = *misses.lock().unwrap();
+					if nm > 0 {
+						(*f)(
+							i as f64 / tot as f64,
+							format!("Raytracing ({} missed/overshot pixels)...", nm),
+						);
 					} else {
-						*misses.lock().unwrap() += 1;
+						(*f)(i as f64 / tot as f64, format!("Raytracing..."));
 					}
 				}
-				let num_misses = *misses.lock().unwrap();
-				if num_misses > 0 {
-					println!("WARNING: Missed/Overshot {} pixels", num_misses);
-				}
-				Ok(img)
 			}
-			None => Err("Couldn't create image"),
+			pixels.push(px);
+		}
+		// Pixels of tiles whose thread died never came in
+		let num_misses = *misses.lock().unwrap() + (tot - pixels.len() as u64) as u32;
+		if num_misses > 0 {
+			return Err(RenderError::MissedPixels(num_misses));
 		}
+
+		Ok(pixels)
+	}
+
+	/// Averages the samples of a pixel, recording what each of them hit
+	fn sample_px<R: Renderable>(
+		o: &R,
+		x: u32,
+		y: u32,
+		offsets: &[(Vector2<f64>, Vector2<f64>)],
+		stats: &StatsCounter,
+	) -> Color {
+		if offsets.len() <= 1 {
+			let (col, hit) = o.trace_px(x, y);
+			stats.record(hit);
+			return col;
+		}
+		let sum = offsets
+			.iter()
+			.map(|(offset, lens)| {
+				let (col, hit) = o.trace_subpx(x as f64 + offset.x, y as f64 + offset.y, *lens);
+				stats.record(hit);
+				col
+			})
+			.fold(None, |acc: Option<Color>, col| match acc {
+				Some(acc) => Some(acc + col),
+				None => Some(col),
+			})
+			.unwrap();
+
+		sum / offsets.len() as f64
 	}
+
+	/// Averages the samples of a pixel like `sample_px`, along with the AOVs of its first sample.
+	/// `render_px_aovs` doesn't tell what that one hit, so only the other ones are recorded.
+	fn sample_px_aovs<R: Renderable>(
+		o: &R,
+		x: u32,
+		y: u32,
+		offsets: &[(Vector2<f64>, Vector2<f64>)],
+		stats: &StatsCounter,
+	) -> (Color, PixelAovs) {
+		// The first sample goes through the pixel's corner, like `render_px_aovs`
+		let (first, aovs) = o.render_px_aovs(x, y);
+		if offsets.len() <= 1 {
+			return (first, aovs);
+		}
+		let col = offsets
+			.iter()
+			.skip(1)
+			.map(|(offset, lens)| {
+				let (col, hit) = o.trace_subpx(x as f64 + offset.x, y as f64 + offset.y, *lens);
+				stats.record(hit);
+				col
+			})
+			.fold(first, |acc, col| acc + col);
+
+		(col / offsets.len() as f64, aovs)
+	}
+
+	/// Element `index` of the van der Corput sequence in `base`, in [0, 1)
+	fn halton(mut index: u32, base: u32) -> f64 {
+		let mut f = 1.0;
+		let mut res = 0.0;
+		while index > 0 {
+			f /= base as f64;
+			res += f * (index % base) as f64;
+			index /= base;
+		}
+
+		res
+	}
+
+	/// Renders using rayon's parallel iterators on the global pool instead of a dedicated thread
+	/// pool and channel. Pixels are processed in bands of rows so progress can be reported from
+	/// the calling thread between bands.
+	pub fn render_par<'a, R: Renderable + Sync>(
+		o: &R,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		render_par_with_config(o, RenderConfig::default(), r)
+	}
+
+	/// Renders like `render_par`, antialiased and tone mapped following the configuration. The
+	/// work goes to rayon's global pool rather than to tiles, so `num_threads`, `chunk_size` and
+	/// `tile_order` are ignored.
+	pub fn render_par_with_config<'a, R: Renderable + Sync>(
+		o: &R,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		render_par_on(o, None, config, r)
+	}
+
+	/// Renders like `render_par_with_config`, on an already-built thread pool so it can be
+	/// reused across frames
+	pub fn render_par_with_pool<'a, R: Renderable + Sync>(
+		o: &R,
+		pool: &ThreadPool,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		render_par_on(o, Some(pool), config, r)
+	}
+
+	/// Renders with rayon's parallel iterators on `pool`, or the global pool without one
+	fn render_par_on<'a, R: Renderable + Sync>(
+		o: &R,
+		pool: Option<&ThreadPool>,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> Result<DynamicImage, RenderError> {
+		const BAND_ROWS: u32 = 16;
+		let (width, height) = o.get_dimensions();
+		let offsets = config.sample_offsets();
+		let stats = StatsCounter::default();
+
+		let mut img = DynamicImage::new_rgba8(width, height);
+		let buf = img
+			.as_mut_rgba8()
+			.ok_or(RenderError::ImageBufferAllocationFailed)?;
+		for band in (0..height).step_by(BAND_ROWS as usize) {
+			if let Some(f) = r {
+				(*f)(band as f64 / height as f64, String::from("Raytracing..."));
+			}
+			let start = band * width;
+			let end = (band + BAND_ROWS).min(height) * width;
+			let trace_band = || -> Vec<_> {
+				(start..end)
+					.into_par_iter()
+					.map(|i| {
+						let (x, y) = (i % width, i / width);
+						(x, y, sample_px(o, x, y, &offsets, &stats))
+					})
+					.collect()
+			};
+			let pixels = match pool {
+				Some(pool) => pool.install(trace_band),
+				None => trace_band(),
+			};
+			for (x, y, col) in pixels {
+				buf.put_pixel(x, y, col.into());
+			}
+		}
+
+		Ok(config.tone_mapping.apply(img))
+	}
+}
+
+fn triangle_solid_angle(a: &Unit<Vector>, b: &Unit<Vector>, c: &Unit<Vector>) -> f64 {
+	let num = a.dot(&b.cross(c)).abs();
+	let den = 1.0 + a.dot(b) + b.dot(c) + c.dot(a);
+
+	2.0 * num.atan2(den)
 }
 
 fn ray_plane(plane: &Ray, ray: &Ray) -> Option<f64> {
@@ -350,13 +1930,1001 @@ fn ray_plane(plane: &Ray, ray: &Ray) -> Option<f64> {
 	return Some((plane.origin - ray.origin).dot(&plane.direction) / ln);
 }
 
+/// Perspective projections serialize as their parameters rather than as their matrix, to keep
+/// scene files editable by hand
+#[cfg(feature = "serde")]
+mod serde_perspective {
+	use nalgebra::Perspective3;
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	#[derive(Serialize, Deserialize)]
+	struct PerspectiveDesc {
+		aspect: f64,
+		/// Vertical field of view, in degrees
+		fov: f64,
+		znear: f64,
+		zfar: f64,
+	}
+
+	pub fn serialize<S: Serializer>(
+		perspective: &Perspective3<f64>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		PerspectiveDesc {
+			aspect: perspective.aspect(),
+			fov: perspective.fovy().to_degrees(),
+			znear: perspective.znear(),
+			zfar: perspective.zfar(),
+		}
+		.serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Perspective3<f64>, D::Error> {
+		let desc = PerspectiveDesc::deserialize(deserializer)?;
+		Ok(Perspective3::new(
+			desc.aspect,
+			desc.fov.to_radians(),
+			desc.znear,
+			desc.zfar,
+		))
+	}
+}
+
 #[cfg(test)]
 mod tests {
-	use super::Camera;
-	use nalgebra::{Point3, Translation3, Vector3};
+	use super::render::{
+		render, render_checkpointed, render_hdr, render_streaming, render_with_aovs,
+		render_with_config, render_with_pool, render_with_stats, OutputFormat, RenderConfig,
+		RenderError, TileOrder,
+	};
+	use super::{Camera, Intersectable, MultiRing, Ray, Ring, RingBand, Scene, Sphere};
+	use crate::postprocess::{from_uv_buffer, tone_map_aces, ToneMapping};
+	use crate::texture::{Texture, TextureFiltering, TextureMode};
+	use color::Color;
+	use image::{DynamicImage, ImageFormat, Rgba};
+	use nalgebra::{Point3, Translation3, Unit, UnitQuaternion, Vector2, Vector3};
+	use rayon::ThreadPoolBuilder;
+
+	use std::cell::Cell;
+	use std::io::Cursor;
 
 	use approx::assert_relative_eq;
 
+	fn test_scene() -> Scene {
+		let tex = Scene::create_bg_texture(2, 2).unwrap();
+		Scene {
+			camera: Camera::new(10, 10, 45.0),
+			sphere: Sphere {
+				pos: Point3::origin(),
+				radius: 1.0,
+				texture: tex.clone(),
+			},
+			objects: vec![],
+			ring: Ring {
+				pos: Point3::origin(),
+				radius: (2.0, 3.0),
+				texture_top: tex.clone(),
+				texture_bottom: tex,
+				angular_velocity: 0.0,
+				mipmaps: None,
+			},
+			bgtex: None,
+		}
+	}
+
+	#[test]
+	fn renders_normal_map() {
+		let mut scn = test_scene();
+		scn.set_size(32, 32);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let img = scn
+			.render_normal_map(RenderConfig::default())
+			.expect("Couldn't render normal map");
+		let buf = img.as_rgba8().unwrap();
+
+		assert_eq!(*buf.get_pixel(0, 0), Rgba([127, 127, 255, 255]));
+		let center = buf.get_pixel(16, 16);
+		assert!(center[2] > 250);
+		let left = buf.get_pixel(10, 16);
+		assert!(left[0] < 127);
+	}
+
+	#[test]
+	fn normal_map_shows_spheres_in_front_of_the_ring() {
+		let mut scn = test_scene();
+		scn.set_size(32, 32);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		let mut moon = Sphere::new(0.5, scn.sphere.texture.clone());
+		moon.pos = Point3::new(2.0, 0.0, 1.0);
+		scn.objects.push(moon);
+		let img = scn.render_normal_map(RenderConfig::default()).unwrap();
+		let buf = img.as_rgba8().unwrap();
+
+		// The ring behind faces the camera, the moon in front of it leans towards +x there
+		let (x, y) = scn
+			.camera
+			.world_to_pixel(&Point3::new(2.4, 0.0, 1.3))
+			.unwrap();
+		let px = buf.get_pixel(x as u32, y as u32);
+		assert!(px[0] > 160, "{:?}", px);
+	}
+
+	#[test]
+	fn renders_depth_aov() {
+		let mut scn = test_scene();
+		scn.set_size(32, 32);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		let out = render_with_aovs(scn.clone(), RenderConfig::default(), None).unwrap();
+		let beauty = render_with_config(scn.clone(), RenderConfig::default(), None).unwrap();
+		assert_eq!(out.image.raw_pixels(), beauty.raw_pixels());
+		// The beauty pass is antialiased like any other render
+		let config = RenderConfig {
+			samples: 4,
+			tile_order: TileOrder::CenterOut,
+			..RenderConfig::default()
+		};
+		let antialiased = render_with_aovs(scn.clone(), config.clone(), None).unwrap();
+		let beauty = render_with_config(scn, config, None).unwrap();
+		assert_eq!(antialiased.image.raw_pixels(), beauty.raw_pixels());
+		assert_eq!(antialiased.depth[16 * 32 + 16], out.depth[16 * 32 + 16]);
+
+		let depth = |x: usize, y: usize| out.depth[y * 32 + x];
+		assert_eq!(out.depth.len(), 32 * 32);
+		// Top of the sphere, then the ring and the background around it
+		assert_relative_eq!(depth(16, 16), 9.0, epsilon = 0.05);
+		assert!(depth(6, 16) > 10.0 && depth(6, 16) < 10.5);
+		assert!(depth(0, 0).is_nan());
+	}
+
+	#[test]
+	fn renders_normals_aov() {
+		let mut scn = test_scene();
+		scn.set_size(32, 32);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		let out = render_with_aovs(scn.clone(), RenderConfig::default(), None).unwrap();
+		assert_eq!(out.normals.len(), 32 * 32);
+
+		let normal = |x: usize, y: usize| out.normals[y * 32 + x];
+		// Top of the sphere faces the camera, the ring around it faces up as well
+		let [x, y, z] = normal(16, 16);
+		assert_relative_eq!(Vector3::new(x, y, z), Vector3::z(), epsilon = 0.1);
+		assert_eq!(normal(6, 16), [0.0, 0.0, 1.0]);
+		assert_eq!(normal(0, 0), [0.0; 3]);
+	}
+
+	#[test]
+	fn renders_uv_aov() {
+		let mut scn = test_scene();
+		scn.set_size(32, 32);
+		// Looking at the sphere from the +x axis, where its texture is centered
+		scn.set_camera(
+			Some(Translation3::new(10.0, 0.0, 0.0)),
+			Some(UnitQuaternion::from_axis_angle(
+				&Vector3::y_axis(),
+				std::f64::consts::FRAC_PI_2,
+			)),
+			None,
+		);
+		let out = render_with_aovs(scn.clone(), RenderConfig::default(), None).unwrap();
+		assert_eq!(out.uv.len(), 32 * 32);
+
+		let [u, v] = out.uv[16 * 32 + 16];
+		assert_relative_eq!(u, 0.5, epsilon = 0.05);
+		assert_relative_eq!(v, 0.5, epsilon = 0.05);
+		assert_eq!(out.uv[0], [0.0; 2]);
+
+		let img = from_uv_buffer(&out.uv, 32, 32).to_rgb();
+		let px = img.get_pixel(16, 16);
+		assert!((120..=135).contains(&px[0]) && (120..=135).contains(&px[1]));
+		assert_eq!(px[2], 0);
+	}
+
+	#[test]
+	fn streams_rows_to_writer() {
+		let mut scn = test_scene();
+		// Several bands, the last one partial
+		scn.set_size(40, 70);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 8.0)), None, None);
+		let expected = render(scn.clone(), None).unwrap();
+
+		let mut png = Cursor::new(vec![]);
+		let config = RenderConfig::default();
+		render_streaming(
+			scn.clone(),
+			&mut png,
+			OutputFormat::PNG,
+			config.clone(),
+			None,
+		)
+		.unwrap();
+		let img = image::load_from_memory_with_format(png.get_ref(), ImageFormat::PNG).unwrap();
+		assert_eq!(img.to_rgba().into_raw(), expected.raw_pixels());
+
+		let mut ppm = Cursor::new(vec![]);
+		render_streaming(scn.clone(), &mut ppm, OutputFormat::PPM, config, None).unwrap();
+		assert!(ppm.get_ref().starts_with(b"P6\n40 70\n255\n"));
+		let img = image::load_from_memory_with_format(ppm.get_ref(), ImageFormat::PNM).unwrap();
+		assert_eq!(img.to_rgb().into_raw(), expected.to_rgb().into_raw());
+
+		// Following the configuration, with bands of another height
+		let config = RenderConfig {
+			chunk_size: 16,
+			samples: 4,
+			tone_mapping: ToneMapping::Aces,
+			..RenderConfig::default()
+		};
+		let expected = render_with_config(scn.clone(), config.clone(), None).unwrap();
+		let mut png = Cursor::new(vec![]);
+		render_streaming(scn, &mut png, OutputFormat::PNG, config, None).unwrap();
+		let img = image::load_from_memory_with_format(png.get_ref(), ImageFormat::PNG).unwrap();
+		assert_eq!(img.to_rgba().into_raw(), expected.raw_pixels());
+	}
+
+	#[test]
+	fn resumes_from_checkpoint() {
+		let mut scn = test_scene();
+		scn.set_size(64, 64);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 8.0)), None, None);
+		let config = RenderConfig {
+			chunk_size: 8,
+			..RenderConfig::default()
+		};
+		let dir = std::env::temp_dir().join(format!("gargantua-checkpoint-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let (full_path, path) = (dir.join("full.ckpt"), dir.join("resumed.ckpt"));
+
+		let full = render_checkpointed(scn.clone(), config.clone(), &full_path, false, None, None)
+			.unwrap();
+		assert_eq!(
+			full.raw_pixels(),
+			render(scn.clone(), None).unwrap().raw_pixels()
+		);
+
+		// Interrupted halfway through the 64 tiles, leaving the bottom half blank
+		let half =
+			render_checkpointed(scn.clone(), config.clone(), &path, false, Some(32), None).unwrap();
+		assert_ne!(half.raw_pixels(), full.raw_pixels());
+		let ran = Cell::new(0);
+		let reporter = |_: f64, _: String| ran.set(ran.get() + 1);
+		let resumed = render_checkpointed(
+			scn.clone(),
+			config.clone(),
+			&path,
+			true,
+			None,
+			Some(&reporter),
+		)
+		.unwrap();
+		assert_eq!(resumed.raw_pixels(), full.raw_pixels());
+		// Only the two remaining batches of tiles were traced
+		assert_eq!(ran.get(), 2);
+
+		// The header tells what's wrong before the size of the pixels does
+		scn.set_size(32, 32);
+		assert_eq!(
+			render_checkpointed(scn.clone(), config.clone(), &path, true, None, None).err(),
+			Some(RenderError::InvalidCheckpoint(String::from(
+				"saved from a 64x64 render"
+			)))
+		);
+		std::fs::write(&path, [0; 8]).unwrap();
+		assert_eq!(
+			render_checkpointed(scn, config, &path, true, None, None).err(),
+			Some(RenderError::InvalidCheckpoint(String::from(
+				"truncated header"
+			)))
+		);
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn reuses_thread_pool_across_frames() {
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+
+		let expected = render(scn.clone(), None).unwrap();
+		for _ in 0..2 {
+			let img = render_with_pool(scn.clone(), &pool, RenderConfig::default(), None).unwrap();
+			assert_eq!(img.raw_pixels(), expected.raw_pixels());
+		}
+	}
+
+	#[test]
+	fn counts_ray_hits() {
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let res = render_with_stats(scn, RenderConfig::default(), None).unwrap();
+		let stats = res.stats;
+
+		assert_eq!(stats.total_rays, 16 * 16);
+		assert_eq!(
+			stats.sphere_hits + stats.ring_hits + stats.background_hits,
+			stats.total_rays
+		);
+		assert!(stats.sphere_hits > 0);
+		assert!(stats.ring_hits > 0);
+		assert!(stats.background_hits > 0);
+	}
+
+	#[test]
+	fn antialiases_with_multiple_samples() {
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let single = RenderConfig {
+			samples: 1,
+			..RenderConfig::default()
+		};
+		let expected = render(scn.clone(), None).unwrap();
+		let img = render_with_stats(scn.clone(), single, None).unwrap().image;
+		assert_eq!(img.raw_pixels(), expected.raw_pixels());
+
+		let multi = RenderConfig {
+			samples: 4,
+			..RenderConfig::default()
+		};
+		let res = render_with_stats(scn, multi, None).unwrap();
+		assert_eq!(res.stats.total_rays, 4 * 16 * 16);
+		assert_eq!(res.image.raw_pixels().len(), 16 * 16 * 4);
+		// Edges get blended, so some pixels must change
+		assert_ne!(res.image.raw_pixels(), expected.raw_pixels());
+	}
+
+	#[test]
+	fn applies_tone_mapping() {
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let plain = render(scn.clone(), None).unwrap();
+		let config = RenderConfig {
+			tone_mapping: ToneMapping::None,
+			..RenderConfig::default()
+		};
+		let untouched = render_with_config(scn.clone(), config, None).unwrap();
+		assert_eq!(untouched.raw_pixels(), plain.raw_pixels());
+
+		let config = RenderConfig {
+			tone_mapping: ToneMapping::Aces,
+			..RenderConfig::default()
+		};
+		let mapped = render_with_config(scn, config, None).unwrap();
+		assert_eq!(mapped.raw_pixels(), tone_map_aces(&plain).raw_pixels());
+		assert_ne!(mapped.raw_pixels(), plain.raw_pixels());
+	}
+
+	#[test]
+	fn renders_full_precision_colors() {
+		let mut scn = test_scene();
+		scn.set_size(8, 4);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let img = render(scn.clone(), None).unwrap();
+		let pixels = render_hdr(scn, RenderConfig::default(), None).unwrap();
+		assert_eq!(pixels.len(), 8 * 4);
+		assert_eq!((pixels[0].0, pixels[0].1), (0, 0));
+		assert_eq!((pixels[9].0, pixels[9].1), (1, 1));
+
+		let buf = img.as_rgba8().unwrap();
+		for (x, y, col) in pixels {
+			let px: Rgba<u8> = col.into();
+			assert_eq!(*buf.get_pixel(x, y), px);
+		}
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serializes_scenes() {
+		let mut scn = test_scene();
+		scn.set_size(16, 8);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		scn.objects
+			.push(Sphere::new(0.5, scn.sphere.texture.clone()));
+		let json = serde_json::to_string(&scn).unwrap();
+		let loaded: Scene = serde_json::from_str(&json).unwrap();
+		assert_eq!((loaded.camera.width, loaded.camera.height), (16, 8));
+		assert_eq!(loaded.objects.len(), 1);
+
+		let img = render(scn, None).unwrap();
+		let loaded_img = render(loaded, None).unwrap();
+		assert_eq!(img.raw_pixels(), loaded_img.raw_pixels());
+	}
+
+	#[test]
+	fn orders_tiles_from_center() {
+		let row_major = TileOrder::RowMajor.tiles(50, 30, 10);
+		assert_eq!(row_major.len(), 15);
+		assert_eq!(row_major[0], (0, 0, 10, 10));
+		assert_eq!(row_major[1], (10, 0, 10, 10));
+
+		let center_out = TileOrder::CenterOut.tiles(50, 30, 10);
+		assert_eq!(center_out.len(), row_major.len());
+		assert_eq!(center_out[0], (20, 10, 10, 10));
+		assert_eq!(&center_out[13..], &[(0, 20, 10, 10), (40, 20, 10, 10)]);
+
+		let partial = TileOrder::RowMajor.tiles(25, 10, 10);
+		assert_eq!(partial.last(), Some(&(20, 0, 5, 10)));
+
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let config = RenderConfig {
+			chunk_size: 4,
+			tile_order: TileOrder::CenterOut,
+			..RenderConfig::default()
+		};
+		let expected = render(scn.clone(), None).unwrap();
+		let img = render_with_stats(scn, config, None).unwrap().image;
+		assert_eq!(img.raw_pixels(), expected.raw_pixels());
+	}
+
+	#[test]
+	fn scene_renders_itself() {
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+
+		let expected = render(scn.clone(), None).unwrap();
+		let img = scn.render(RenderConfig::default(), None).unwrap();
+		assert_eq!(img.raw_pixels(), expected.raw_pixels());
+	}
+
+	#[test]
+	fn renders_regions() {
+		let mut scn = test_scene();
+		scn.set_size(48, 40);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let full = render(scn.clone(), None).unwrap();
+		let full = full.as_rgba8().unwrap();
+
+		// Straddling the default 32 pixel tiles
+		let region = scn.render_region(20, 10, 45, 37, None).unwrap();
+		let region = region.as_rgba8().unwrap();
+		assert_eq!(region.dimensions(), (25, 27));
+		for (x, y, px) in region.enumerate_pixels() {
+			assert_eq!(px, full.get_pixel(x + 20, y + 10));
+		}
+
+		// Clipped to the image
+		let corner = scn.render_region(40, 30, 100, 100, None).unwrap();
+		assert_eq!(corner.as_rgba8().unwrap().dimensions(), (8, 10));
+		let empty = scn.render_region(30, 30, 10, 10, None).unwrap();
+		assert_eq!(empty.as_rgba8().unwrap().dimensions(), (0, 0));
+	}
+
+	#[test]
+	fn interpolates_cameras() {
+		let a = Camera::look_at(
+			Point3::new(4.0, 0.0, 0.0),
+			Point3::origin(),
+			Vector3::z(),
+			30.0,
+			100,
+			100,
+		);
+		let b = Camera::look_at(
+			Point3::new(0.0, 4.0, 0.0),
+			Point3::origin(),
+			Vector3::z(),
+			60.0,
+			100,
+			100,
+		);
+		let mid = Camera::interpolate(&a, &b, 0.5);
+		assert_relative_eq!(
+			mid.isometry.translation.vector,
+			Vector3::new(2.0, 2.0, 0.0),
+			epsilon = 1e-9
+		);
+		assert_relative_eq!(mid.perspective.fovy(), 45f64.to_radians(), epsilon = 1e-9);
+		// Halfway through the quarter turn, the camera looks along the diagonal
+		let expected = -Vector3::new(1.0, 1.0, 0.0).normalize();
+		assert_relative_eq!(
+			mid.create_primary(50, 50).direction.into_inner(),
+			expected,
+			epsilon = 1e-9
+		);
+
+		// The same orientation with the opposite quaternion doesn't spin the camera around
+		let mut flipped = a.clone();
+		flipped.isometry.rotation =
+			UnitQuaternion::new_unchecked(-a.isometry.rotation.into_inner());
+		let same = Camera::interpolate(&a, &flipped, 0.5);
+		assert_relative_eq!(
+			same.isometry.rotation.angle_to(&a.isometry.rotation),
+			0.0,
+			epsilon = 1e-9
+		);
+		assert_eq!(Camera::interpolate(&a, &b, 0.0).isometry, a.isometry);
+	}
+
+	#[test]
+	fn hits_each_band_of_multi_ring() {
+		let tex = Scene::create_bg_texture(2, 2).unwrap();
+		let band = |inner, outer| RingBand {
+			inner,
+			outer,
+			opacity: 1.0,
+		};
+		let disk = MultiRing::new(vec![band(1.0, 2.0), band(3.0, 4.0), band(5.0, 6.0)], tex)
+			.with_position(Point3::new(0.0, 0.0, 1.0));
+		let down = |x: f64, y: f64| Ray {
+			origin: Point3::new(x, y, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+
+		for &(x, v) in &[(1.5, 0.5), (3.25, 0.25), (5.75, 0.75)] {
+			let ray = down(x, 0.0);
+			assert_relative_eq!(disk.intersect(&ray).unwrap(), 4.0, epsilon = 1e-12);
+			let uv = disk.texture_coords(&Point3::new(x, 0.0, 1.0));
+			assert_relative_eq!(uv, Vector2::new(0.0, v), epsilon = 1e-12);
+		}
+		let uv = disk.texture_coords(&Point3::new(0.0, 3.5, 1.0));
+		assert_relative_eq!(uv, Vector2::new(0.25, 0.5), epsilon = 1e-12);
+
+		// The gaps between bands, and the hole in the middle, let rays through
+		for &x in &[0.5, 2.5, 4.5, 6.5] {
+			assert_eq!(disk.intersect(&down(x, 0.0)), None);
+		}
+		assert_eq!(
+			disk.facing_normal(&down(1.5, 0.0)).into_inner(),
+			Vector3::z()
+		);
+	}
+
+	#[test]
+	fn camera_looks_at_target() {
+		let cam = Camera::look_at(
+			Point3::new(0.0, 0.0, 5.0),
+			Point3::origin(),
+			Vector3::y(),
+			45.0,
+			500,
+			500,
+		);
+		let ray = cam.create_primary(250, 250);
+		assert_relative_eq!(ray.origin.x, 0.0, epsilon = 1e-9);
+		assert_relative_eq!(ray.origin.y, 0.0, epsilon = 1e-9);
+		assert_relative_eq!(ray.direction.into_inner(), -Vector3::z(), epsilon = 1e-9);
+
+		// Off-axis targets end up in the middle of the image too, with `up` staying vertical
+		let cam = Camera::look_at(
+			Point3::new(3.0, -4.0, 1.0),
+			Point3::new(0.0, 0.0, 1.0),
+			Vector3::z(),
+			45.0,
+			500,
+			500,
+		);
+		let expected = Vector3::new(-0.6, 0.8, 0.0);
+		assert_relative_eq!(
+			cam.create_primary(250, 250).direction.into_inner(),
+			expected,
+			epsilon = 1e-9
+		);
+		let (x, y) = cam.world_to_pixel(&Point3::new(0.0, 0.0, 2.0)).unwrap();
+		assert_relative_eq!(x, 250.0, epsilon = 1e-6);
+		assert!((y - 250.0).abs() > 1.0);
+		assert_eq!(cam.world_to_pixel(&Point3::new(6.0, -8.0, 1.0)), None);
+	}
+
+	#[test]
+	fn focuses_lens_rays_at_focal_distance() {
+		let pinhole = Camera::new(100, 100, 45.0);
+		let ray = pinhole.create_primary_lens(30.0, 60.0, Vector2::new(0.7, 0.2));
+		let expected = pinhole.create_primary(30, 60);
+		assert_eq!(ray.origin, expected.origin);
+		assert_eq!(ray.direction, expected.direction);
+
+		let cam = Camera::new(100, 100, 45.0).with_depth_of_field(0.5, 4.0);
+		let focus = expected.origin + expected.direction.as_ref() * 4.0;
+		for &lens in &[Vector2::new(0.7, 0.2), Vector2::new(0.3, 0.9)] {
+			let ray = cam.create_primary_lens(30.0, 60.0, lens);
+			assert!(ray.origin != expected.origin);
+			assert!((ray.origin - expected.origin).norm() <= 0.5);
+			let t = (focus - ray.origin).norm();
+			assert_relative_eq!(
+				ray.origin + ray.direction.as_ref() * t,
+				focus,
+				epsilon = 1e-9
+			);
+		}
+
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+		let config = RenderConfig {
+			samples: 4,
+			..RenderConfig::default()
+		};
+		let sharp = render_with_config(scn.clone(), config.clone(), None).unwrap();
+		scn.camera = scn.camera.with_depth_of_field(0.5, 1.0);
+		let blurred = render_with_config(scn, config, None).unwrap();
+		assert_ne!(blurred.raw_pixels(), sharp.raw_pixels());
+	}
+
+	#[test]
+	fn displays_render_errors() {
+		assert_eq!(
+			RenderError::MissedPixels(3).to_string(),
+			"Missed/Overshot 3 pixels"
+		);
+		let err: Box<dyn std::error::Error> = Box::new(RenderError::ThreadPoolBuildFailed);
+		assert_eq!(err.to_string(), "Cannot setup threading");
+	}
+
+	#[test]
+	fn displays_ray_and_camera() {
+		let ray = Ray {
+			origin: Point3::new(1.0, -2.0, 0.5),
+			direction: Unit::new_normalize(Vector3::new(0.0, 0.0, -3.0)),
+		};
+		assert_eq!(
+			ray.to_string(),
+			"Ray { origin: (1.000, -2.000, 0.500), direction: (0.000, 0.000, -1.000) }"
+		);
+
+		let mut cam = Camera::new(640, 360, 30.0);
+		cam.set_position(Translation3::new(0.0, 0.0, 5.0));
+		assert_eq!(
+			cam.to_string(),
+			"Camera { position: (0.000, 0.000, 5.000), direction: (0.000, 0.000, -1.000), fov: 30.0°, resolution: 640x360 }"
+		);
+	}
+
+	#[test]
+	fn pixel_solid_angles_cover_frustum() {
+		let fov = 60f64.to_radians();
+		let cam = Camera::new(20, 20, 60.0);
+		let total: f64 = (0..20)
+			.flat_map(|y| (0..20).map(move |x| (x, y)))
+			.map(|(x, y)| cam.pixel_solid_angle(x, y))
+			.sum();
+		let expected = 4.0 * ((fov / 2.0).sin() * (fov / 2.0).sin()).asin();
+
+		assert_relative_eq!(total, expected, epsilon = 1e-9);
+		assert!(cam.pixel_solid_angle(0, 0) < cam.pixel_solid_angle(10, 10));
+	}
+
+	#[test]
+	fn sphere_builder_sets_fields() {
+		let tex = Scene::create_bg_texture(2, 2).unwrap();
+		let sphere = Sphere::new(1.0, tex.clone());
+		assert_eq!(sphere.pos, Point3::origin());
+		assert_eq!(sphere.radius, 1.0);
+
+		let sphere = Sphere::new(1.0, tex)
+			.with_position(Point3::new(1.0, 2.0, 3.0))
+			.with_radius(0.5);
+		assert_eq!(sphere.pos, Point3::new(1.0, 2.0, 3.0));
+		assert_eq!(sphere.radius, 0.5);
+	}
+
+	#[test]
+	fn ring_hits_between_radii() {
+		let tex = Scene::create_bg_texture(2, 2).unwrap();
+		let ring = Ring::new(2.0, 3.0, tex).with_position(Point3::new(1.0, 0.0, 0.0));
+		let ray_at = |x: f64| Ray {
+			origin: Point3::new(x, 0.0, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+
+		assert_eq!(ring.radius, (2.0, 3.0));
+		assert_eq!(ring.intersect(&ray_at(3.5)), Some(5.0));
+		assert_eq!(ring.intersect(&ray_at(1.0)), None);
+		assert_eq!(ring.intersect(&ray_at(4.5)), None);
+	}
+
+	#[test]
+	fn scene_aabb_bounds_objects() {
+		let scn = test_scene();
+		let aabb = scn.scene_aabb();
+		assert_eq!(aabb.min, Point3::new(-3.0, -3.0, -1.0));
+		assert_eq!(aabb.max, Point3::new(3.0, 3.0, 1.0));
+
+		let towards = Ray {
+			origin: Point3::new(2.5, 0.0, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+		let away = Ray {
+			origin: Point3::new(2.5, 0.0, 5.0),
+			direction: Vector3::z_axis(),
+		};
+		let beside = Ray {
+			origin: Point3::new(3.5, 0.0, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+		assert!(aabb.intersects(&towards));
+		assert!(!aabb.intersects(&away));
+		assert!(!aabb.intersects(&beside));
+	}
+
+	#[test]
+	fn renders_additional_spheres() {
+		let solid = |px| {
+			Texture::new(
+				DynamicImage::ImageRgba8(image::ImageBuffer::from_pixel(1, 1, Rgba(px))),
+				TextureFiltering::Nearest,
+				TextureMode::Clamp,
+			)
+		};
+		let mut scn = test_scene();
+		scn.set_size(64, 32);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		scn.sphere =
+			Sphere::new(1.0, solid([255, 0, 0, 255])).with_position(Point3::new(-2.0, 0.0, 0.0));
+		scn.ring = Ring::new(100.0, 101.0, solid([0, 0, 0, 255]));
+		scn.add_sphere(
+			Sphere::new(1.0, solid([0, 0, 255, 255])).with_position(Point3::new(2.0, 0.0, 0.0)),
+		);
+		assert_eq!(scn.spheres().count(), 2);
+		assert!(scn.scene_aabb().max.x >= 3.0);
+
+		let img = render(scn, None).unwrap();
+		let buf = img.as_rgba8().unwrap();
+		let px = |x: u32| *buf.get_pixel(x, 16);
+		// The spheres don't overlap: the middle of the image shows the background
+		assert_ne!(px(32), Rgba([255, 0, 0, 255]));
+		assert_ne!(px(32), Rgba([0, 0, 255, 255]));
+		assert!((0..32).any(|x| px(x) == Rgba([255, 0, 0, 255])));
+		assert!((32..64).any(|x| px(x) == Rgba([0, 0, 255, 255])));
+
+		// A sphere between the camera and the ring hides it
+		let mut scn = test_scene();
+		scn.set_size(64, 32);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		scn.sphere = Sphere::new(0.1, solid([0, 0, 0, 255]));
+		scn.ring = Ring::new(0.5, 8.0, solid([255, 0, 0, 255]));
+		scn.add_sphere(
+			Sphere::new(1.0, solid([0, 255, 0, 255])).with_position(Point3::new(1.5, 0.0, 3.0)),
+		);
+		let img = render(scn, None).unwrap();
+		let buf = img.as_rgba8().unwrap();
+		assert!(buf.pixels().any(|&px| px == Rgba([0, 255, 0, 255])));
+		assert!(buf.pixels().any(|&px| px == Rgba([255, 0, 0, 255])));
+	}
+
+	#[test]
+	fn ring_samples_face_facing_the_ray() {
+		let solid = |px| {
+			let mut img = DynamicImage::new_rgba8(1, 1);
+			img.as_mut_rgba8().unwrap().put_pixel(0, 0, Rgba(px));
+			Texture::new(img, TextureFiltering::Nearest, TextureMode::Clamp)
+		};
+		let ring = Ring::new(2.0, 3.0, solid([255, 0, 0, 255]))
+			.with_bottom_texture(solid([0, 0, 255, 255]));
+		let hit = Point3::new(2.5, 0.0, 0.0);
+		let from_above = Ray {
+			origin: Point3::new(2.5, 0.0, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+		let from_below = Ray {
+			origin: Point3::new(2.5, 0.0, -5.0),
+			direction: Vector3::z_axis(),
+		};
+
+		assert_eq!(
+			ring.sample_texture(hit, &from_above),
+			Color::from_u32(0xFFFF0000)
+		);
+		assert_eq!(
+			ring.sample_texture(hit, &from_below),
+			Color::from_u32(0xFF0000FF)
+		);
+	}
+
+	#[test]
+	fn ring_faces_the_ray() {
+		let ring = Ring::new(2.0, 3.0, Scene::create_bg_texture(2, 2).unwrap());
+		let from_above = Ray {
+			origin: Point3::new(2.5, 0.0, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+		let from_below = Ray {
+			origin: Point3::new(2.5, 0.0, -5.0),
+			direction: Vector3::z_axis(),
+		};
+		let away = Ray {
+			origin: Point3::new(2.5, 0.0, 5.0),
+			direction: Vector3::z_axis(),
+		};
+		assert_eq!(ring.intersect(&from_above), Some(5.0));
+		assert_eq!(ring.intersect(&from_below), Some(5.0));
+		assert_eq!(ring.intersect(&away), None);
+		assert_eq!(ring.facing_normal(&from_above), Vector3::z_axis());
+		assert_eq!(ring.facing_normal(&from_below), -Vector3::z_axis());
+
+		// Only the ring is in view, from either side
+		let solid = |px| {
+			Texture::new(
+				DynamicImage::ImageRgba8(image::ImageBuffer::from_pixel(1, 1, Rgba(px))),
+				TextureFiltering::Nearest,
+				TextureMode::Clamp,
+			)
+		};
+		let (red, blue) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+		let mut scn = test_scene();
+		scn.set_size(32, 32);
+		scn.sphere = Sphere::new(0.1, solid([0, 0, 0, 255]));
+		scn.ring = Ring::new(1.0, 3.0, solid(red.0)).with_bottom_texture(solid(blue.0));
+		let count = |scn: Scene, col: Rgba<u8>| {
+			let img = render(scn, None).unwrap();
+			img.as_rgba8()
+				.unwrap()
+				.pixels()
+				.filter(|&&p| p == col)
+				.count()
+		};
+
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		assert!(count(scn.clone(), red) > 0);
+		assert_eq!(count(scn.clone(), blue), 0);
+
+		scn.set_camera(
+			Some(Translation3::new(0.0, 0.0, -10.0)),
+			Some(UnitQuaternion::from_axis_angle(
+				&Vector3::x_axis(),
+				std::f64::consts::PI,
+			)),
+			None,
+		);
+		assert_eq!(count(scn.clone(), red), 0);
+		assert!(count(scn, blue) > 0);
+	}
+
+	#[test]
+	fn ring_samples_mipmaps_by_footprint() {
+		let mut img = DynamicImage::new_rgba8(64, 4);
+		for (x, _, p) in img.as_mut_rgba8().unwrap().enumerate_pixels_mut() {
+			let v = if x % 2 == 0 { 0 } else { 255 };
+			*p = Rgba([v, v, v, 255]);
+		}
+		let tex = Texture::new(img, TextureFiltering::Nearest, TextureMode::Repeat);
+		let ring = Ring::new(2.0, 3.0, tex);
+		let hit = Point3::new(0.0, 2.5, 0.0);
+		let ray = Ray {
+			origin: Point3::new(0.0, 2.5, 5.0),
+			direction: -Vector3::z_axis(),
+		};
+		let sharp = ring.sample_texture(hit, &ray);
+		assert_eq!(ring.sample_texture_lod(hit, &ray, 10.0), sharp);
+
+		let ring = ring.with_mipmaps();
+		let far = ring.sample_texture_lod(hit, &ray, 10.0);
+		assert!(far.red > 0.1 && far.red < 0.9);
+		// Grazing rays cover more of the ring for the same footprint
+		let grazing = Ray {
+			origin: Point3::new(0.0, -100.0, 1.0),
+			direction: Unit::new_normalize(Vector3::new(0.0, 102.5, -1.0)),
+		};
+		let near = ring.sample_texture_lod(hit, &ray, 0.05);
+		assert_relative_eq!(near.red, sharp.red, epsilon = 1e-2);
+		let grazing_near = ring.sample_texture_lod(hit, &grazing, 0.05);
+		assert!(grazing_near.red > 0.1 && grazing_near.red < 0.9);
+	}
+
+	#[test]
+	fn finds_pixels_for_surface_point() {
+		let mut scn = test_scene();
+		scn.set_size(16, 16);
+		scn.set_camera(Some(Translation3::new(0.0, 0.0, 5.0)), None, None);
+
+		let front = scn.pixels_for_surface_point(Point3::new(0.0, 0.0, 1.0));
+		assert!(!front.is_empty());
+		assert!(front
+			.iter()
+			.all(|&(x, y)| (7..=8).contains(&x) && (7..=8).contains(&y)));
+		assert!(scn
+			.pixels_for_surface_point(Point3::new(0.0, 0.0, -1.0))
+			.is_empty());
+	}
+
+	#[test]
+	fn world_to_pixel_inverts_primary_rays() {
+		let mut cam = Camera::new(20, 10, 45.0);
+		cam.set_position(Translation3::new(1.0, 2.0, 5.0));
+		cam.set_rotation(UnitQuaternion::from_euler_angles(0.1, 0.2, 0.0));
+		for &(x, y) in &[(0, 0), (5, 7), (19, 9)] {
+			let ray = cam.create_primary(x, y);
+			let (px, py) = cam
+				.world_to_pixel(&(ray.origin + ray.direction.as_ref() * 3.0))
+				.unwrap();
+			assert_relative_eq!(px, x as f64, epsilon = 1e-6);
+			assert_relative_eq!(py, y as f64, epsilon = 1e-6);
+		}
+
+		let behind = cam.isometry.transform_point(&Point3::new(0.0, 0.0, 1.0));
+		assert_eq!(cam.world_to_pixel(&behind), None);
+	}
+
+	#[test]
+	fn orthographic_rays_are_parallel() {
+		let mut cam = Camera::orthographic(20, 10, 4.0);
+		cam.set_position(Translation3::new(1.0, 2.0, 5.0));
+		cam.set_rotation(UnitQuaternion::from_euler_angles(0.1, 0.2, 0.0));
+		let (a, b) = (cam.create_primary(5, 7), cam.create_primary(6, 7));
+		assert_relative_eq!(a.direction, b.direction);
+		assert_relative_eq!(
+			a.direction.into_inner(),
+			cam.isometry.rotation * -Vector3::z(),
+			epsilon = 1e-12
+		);
+		// Adjacent pixels are a fifth of a world unit apart, at any distance along the rays
+		for &t in &[0.0, 10.0, 1000.0] {
+			let gap = (b.origin + b.direction.as_ref() * t) - (a.origin + a.direction.as_ref() * t);
+			assert_relative_eq!(gap.norm(), 0.2, epsilon = 1e-9);
+		}
+
+		let corner = cam.create_primary(0, 0);
+		let (px, py) = cam
+			.world_to_pixel(&(corner.origin + corner.direction.as_ref() * 3.0))
+			.unwrap();
+		assert_relative_eq!(px, 0.0, epsilon = 1e-9);
+		assert_relative_eq!(py, 0.0, epsilon = 1e-9);
+		assert_eq!(cam.pixel_solid_angle(5, 7), 0.0);
+	}
+
+	#[test]
+	fn scene_accessors() {
+		let mut scn = test_scene();
+		assert_eq!(scn.camera().width, 10);
+		assert_eq!(scn.sphere().radius, 1.0);
+		assert_eq!(scn.ring().map(|r| r.radius), Some((2.0, 3.0)));
+
+		scn.camera_mut().width = 20;
+		assert_eq!(scn.camera.width, 20);
+	}
+
+	#[test]
+	fn ring_coords_flag_the_seam() {
+		let ring = test_scene().ring.with_position(Point3::new(1.0, 1.0, 0.0));
+		let (uv, seam) = ring.texture_coords_seamless(&Point3::new(3.5, 1.0, 0.0));
+		assert_relative_eq!(uv, Vector2::new(0.0, 0.5));
+		assert!(seam);
+
+		let (uv, seam) = ring.texture_coords_seamless(&Point3::new(1.0, 3.0, 0.0));
+		assert_relative_eq!(uv, Vector2::new(0.25, 0.0));
+		assert!(!seam);
+
+		let (uv, seam) = ring.texture_coords_seamless(&Point3::new(3.999, 0.95, 0.0));
+		assert!(uv.x > 0.99);
+		assert!(seam);
+	}
+
+	#[test]
+	fn set_camera_applies_each_component() {
+		let trans = Translation3::new(1.0, 2.0, 3.0);
+		let rot = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5);
+		let initial_trans = Translation3::new(-1.0, 0.0, 0.0);
+		let initial_rot = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 0.25);
+
+		let mut scn = test_scene();
+		scn.set_camera(Some(initial_trans), Some(initial_rot), None);
+
+		let mut both = scn.clone();
+		both.set_camera(Some(trans), Some(rot), None);
+		assert_eq!(both.camera.isometry.translation, trans);
+		assert_eq!(both.camera.isometry.rotation, rot);
+
+		let mut trans_only = scn.clone();
+		trans_only.set_camera(Some(trans), None, None);
+		assert_eq!(trans_only.camera.isometry.translation, trans);
+		assert_eq!(trans_only.camera.isometry.rotation, initial_rot);
+
+		let mut rot_only = scn.clone();
+		rot_only.set_camera(None, Some(rot), None);
+		assert_eq!(rot_only.camera.isometry.translation, initial_trans);
+		assert_eq!(rot_only.camera.isometry.rotation, rot);
+
+		let mut neither = scn.clone();
+		neither.set_camera(None, None, Some(60.0));
+		assert_eq!(neither.camera.isometry, scn.camera.isometry);
+		assert_relative_eq!(neither.camera.perspective.fovy(), 60f64.to_radians());
+	}
+
 	#[test]
 	fn camera_creates_primary() {
 		let mut cam = Camera::new(500, 500, 50.0);
@@ -1,25 +1,112 @@
+use crate::debug::draw_line;
 use crate::physics::Particle;
-use crate::raytrace::{Intersectable, Point, Renderable, Scene, Sphere, TexCoords, Vector};
-use crate::utils::cartesian_to_spherical;
+use crate::postprocess::encode_linear;
+use crate::raytrace::render::{
+	render_par, render_par_with_config, render_par_with_pool, RenderConfig, RenderError, Reporter,
+};
+use crate::raytrace::{
+	Intersectable, MultiRing, PixelAovs, Point, Ray, RayHit, Renderable, Ring, RingBand, Scene,
+	Sphere, Vector,
+};
+use crate::utils::{background_uv_from_direction, SplitMix64};
 
-use color::Color;
-use nalgebra::{Translation3, UnitQuaternion};
+use color::{doppler_shift, redshift, Color};
+use image::{DynamicImage, Rgba};
+use nalgebra::{Translation3, Unit, UnitQuaternion, Vector2};
+use rayon::prelude::*;
+use rayon::ThreadPool;
 
-use std::f64;
+use std::f64::consts::PI;
+
+/// Bounds of the adaptive timestep, relative to the initial one
+const MIN_DT_FACTOR: f64 = 1.0 / 64.0;
+const MAX_DT_FACTOR: f64 = 64.0;
+
+/// Schwarzschild radius of the implicit black hole at the center of the sphere, the unit in which
+/// the geodesic equations are written
+const SCHWARZSCHILD_RADIUS: f64 = 1.0;
+
+/// Integrator of the geodesics unless set otherwise
+#[cfg(not(feature = "euler-integrator"))]
+const DEFAULT_INTEGRATOR: Integrator = Integrator::RungeKutta4;
+#[cfg(feature = "euler-integrator")]
+const DEFAULT_INTEGRATOR: Integrator = Integrator::SymplecticEuler;
 
 pub struct GRParticle {
 	particle: Particle,
 	dt: f64,
+	tolerance: Option<f64>,
+	trajectory: Option<Vec<Point>>,
+	bodies: Vec<(Point, f64)>,
+	min_radius: Option<f64>,
+	captured: bool,
+	evals: u32,
+	schwarzschild_radius: f64,
+	integrator: Integrator,
+	pub geodesic_type: GeodesicType,
+}
+
+/// Numerical scheme stepping the geodesics
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Integrator {
+	/// Explicit Euler: the position is moved with the current velocity, which is then updated
+	/// from the current acceleration
+	Euler,
+	/// Semi-implicit Euler, see `Particle::integrate_symplectic`
+	SymplecticEuler,
+	/// Störmer-Verlet, see `GRParticle::step_leapfrog`
+	Leapfrog,
+	/// Classic 4th order Runge-Kutta, the most precise for a given timestep
+	RungeKutta4,
 }
 
+/// Kind of geodesic followed by a particle, in units where the Schwarzschild radius is 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeodesicType {
+	/// Null geodesic, followed by light
+	Photon,
+	/// Timelike geodesic of a test particle, with its energy per unit mass (`1.0` is at rest at
+	/// infinity)
+	Massive { specific_energy: f64 },
+}
+
+/// Scene in Schwarzschild spacetime, with the settings of the geodesics traced through it
 #[derive(Clone)]
-pub struct GRScene(pub Scene, pub f64, pub u32);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "GRSceneDesc", into = "GRSceneDesc"))]
+pub struct GRScene {
+	/// The flat scene
+	pub scene: Scene,
+	/// Initial timestep of the geodesics
+	pub dt: f64,
+	/// Budget of force evaluations per geodesic
+	pub max_evals: u32,
+	/// Tolerance of the adaptive timestep, see `GRParticle::with_tolerance`
+	pub tolerance: f64,
+	/// Gravitating bodies, as their position and Schwarzschild radius. Without any, a single
+	/// black hole with a Schwarzschild radius of 1 sits at the center of the sphere.
+	pub bodies: Vec<(Point, f64)>,
+	/// Integrator of the geodesics
+	pub integrator: Integrator,
+	/// Optional disk of several bands, drawn along with the ring of the scene
+	pub multi_ring: Option<MultiRing>,
+}
 
 impl GRParticle {
 	pub fn new(pos: Point, dt: f64) -> Self {
 		GRParticle {
 			particle: Particle::new(pos),
 			dt,
+			tolerance: None,
+			trajectory: None,
+			bodies: vec![],
+			min_radius: None,
+			captured: false,
+			evals: 0,
+			schwarzschild_radius: SCHWARZSCHILD_RADIUS,
+			integrator: Integrator::default(),
+			geodesic_type: GeodesicType::Photon,
 		}
 	}
 
@@ -27,42 +114,537 @@ impl GRParticle {
 		GRParticle {
 			particle: part.clone(),
 			dt,
+			tolerance: None,
+			trajectory: None,
+			bodies: vec![],
+			min_radius: None,
+			captured: false,
+			evals: 0,
+			schwarzschild_radius: SCHWARZSCHILD_RADIUS,
+			integrator: Integrator::default(),
+			geodesic_type: GeodesicType::Photon,
+		}
+	}
+
+	pub fn from_ray(ray: &Ray, dt: f64) -> Self {
+		GRParticle {
+			particle: Particle::from_ray(ray),
+			dt,
+			tolerance: None,
+			trajectory: None,
+			bodies: vec![],
+			min_radius: None,
+			captured: false,
+			evals: 0,
+			schwarzschild_radius: SCHWARZSCHILD_RADIUS,
+			integrator: Integrator::default(),
+			geodesic_type: GeodesicType::Photon,
+		}
+	}
+
+	pub fn with_dt(mut self, dt: f64) -> Self {
+		self.dt = dt;
+		self
+	}
+
+	/// Enables the adaptive timestep: `dt` is halved whenever the fractional change of the
+	/// potential in a step exceeds `tolerance`, and doubled when it stays below 1% of it.
+	pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+		self.tolerance = Some(tolerance);
+		self
+	}
+
+	/// Sets the gravitating bodies bending the geodesic, as their position and Schwarzschild
+	/// radius. Without any, the particle orbits a single black hole with a Schwarzschild radius of
+	/// 1 at the center of the sphere it is intersected with.
+	pub fn with_bodies(mut self, bodies: &[(Point, f64)]) -> Self {
+		self.bodies = bodies.to_vec();
+		self
+	}
+
+	/// Sets the Schwarzschild radius of the implicit black hole at the center of the sphere, used
+	/// when no bodies are set. It defaults to 1, the unit of the geodesics.
+	pub fn with_schwarzschild_radius(mut self, radius: f64) -> Self {
+		self.schwarzschild_radius = radius;
+		self
+	}
+
+	/// Sets the numerical scheme stepping the geodesic
+	pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+		self.integrator = integrator;
+		self
+	}
+
+	/// Records the position of the particle at every integration step from now on, see
+	/// `trajectory`.
+	pub fn with_trajectory_recording(mut self) -> Self {
+		self.trajectory = Some(vec![self.particle.pos()]);
+		self
+	}
+
+	/// Positions of the particle since recording was enabled, or `None` if it wasn't
+	pub fn trajectory(&self) -> Option<&[Point]> {
+		self.trajectory.as_deref()
+	}
+
+	/// Smallest distance to a gravitating body the particle reached over its integration steps,
+	/// or `None` before the first step
+	pub fn min_radius(&self) -> Option<f64> {
+		self.min_radius
+	}
+
+	/// Force evaluations spent on the geodesic so far, over all of its integrations
+	pub fn evals(&self) -> u32 {
+		self.evals
+	}
+
+	/// Whether the particle came within the Schwarzschild radius of one of the bodies, and thus
+	/// won't ever escape. This tells photons running out of evaluations on their way out apart
+	/// from captured ones.
+	pub fn is_captured(&self) -> bool {
+		self.captured
+	}
+
+	/// Sets the geodesic followed by the particle. Massive particles are launched in their
+	/// current direction, at the speed they would have at infinity given their specific energy.
+	pub fn with_geodesic_type(mut self, geodesic_type: GeodesicType) -> Self {
+		if let GeodesicType::Massive { specific_energy } = geodesic_type {
+			let speed = (1.0 - specific_energy.powi(-2)).max(0.0).sqrt();
+			let dir = self.particle.vel().normalize();
+			self.particle.set_vel(dir * speed);
 		}
+		self.geodesic_type = geodesic_type;
+		self
+	}
+
+	/// Integrates the geodesic until the particle enters the sphere, returning where it did, or
+	/// until `max_evals` force evaluations are spent.
+	pub fn intersect(&mut self, sphere: &Sphere, max_evals: u32) -> Option<Point> {
+		self.intersect_with(sphere, max_evals, &|_, _| Vector::zeros())
 	}
 
-	pub fn intersect(&mut self, sphere: &Sphere, max_iter: u32) -> Option<Point> {
-		let h2vec = self.particle.pos().coords.cross(&self.particle.vel());
-		let h2 = h2vec.dot(&h2vec);
-		for _ in 0..max_iter {
-			// let from_sphere = self.particle.pos() - sphere.pos;
-			// self.particle.add_force(gr_potential(from_sphere, h2));
-			// self.particle.update(self.dt);
-			self.rk4_gr(sphere.pos, h2);
-			let to_sphere = sphere.pos - self.particle.pos();
+	/// Like `intersect`, adding `correction` to the acceleration of the geodesic. It is given
+	/// the position relative to the sphere and the velocity of the particle.
+	pub(crate) fn intersect_with(
+		&mut self,
+		sphere: &Sphere,
+		max_evals: u32,
+		correction: &dyn Fn(Vector, Vector) -> Vector,
+	) -> Option<Point> {
+		let mut hit = None;
+		self.integrate(sphere.pos, max_evals, correction, |pos| {
+			let to_sphere = sphere.pos - pos;
 			if to_sphere.dot(&to_sphere) < sphere.radius * sphere.radius {
-				return Some(self.particle.pos());
+				hit = Some(pos);
+			}
+			hit.is_some()
+		});
+
+		hit
+	}
+
+	/// Integrates the geodesic like `intersect`, checking each sphere of the scene in turn.
+	/// Returns where the particle entered a sphere, along with that sphere.
+	pub fn intersect_spheres<'s>(
+		&mut self,
+		scene: &'s Scene,
+		max_evals: u32,
+	) -> Option<(Point, &'s Sphere)> {
+		self.intersect_spheres_with(scene, max_evals, &|_, _| Vector::zeros())
+	}
+
+	/// Like `intersect_spheres`, adding `correction` to the acceleration of the geodesic, see
+	/// `intersect_with`.
+	pub(crate) fn intersect_spheres_with<'s>(
+		&mut self,
+		scene: &'s Scene,
+		max_evals: u32,
+		correction: &dyn Fn(Vector, Vector) -> Vector,
+	) -> Option<(Point, &'s Sphere)> {
+		let mut hit = None;
+		self.integrate(scene.sphere.pos, max_evals, correction, |pos| {
+			hit = scene.spheres().find(|s| contains(s, pos)).map(|s| (pos, s));
+			hit.is_some()
+		});
+
+		hit
+	}
+
+	/// Integrates the geodesic like `intersect`, checking each sphere of the scene in turn and
+	/// also stopping where it crosses the ring. Returns what was hit, and the step of the
+	/// geodesic that hit it as a ray from the previous position.
+	pub fn intersect_scene(
+		&mut self,
+		scene: &Scene,
+		max_evals: u32,
+	) -> Option<(Point, Ray, RayHit)> {
+		self.intersect_scene_with_disk(scene, None, max_evals)
+			.map(|(pt, step, hit, _)| (pt, step, hit))
+	}
+
+	/// Like `intersect_scene`, also stopping where the geodesic crosses a band of `disk`. Such
+	/// hits are reported as `RayHit::Ring`, along with the band that was hit.
+	pub fn intersect_scene_with_disk<'d>(
+		&mut self,
+		scene: &Scene,
+		disk: Option<&'d MultiRing>,
+		max_evals: u32,
+	) -> Option<(Point, Ray, RayHit, Option<&'d RingBand>)> {
+		self.intersect_scene_with(scene, disk, max_evals, &|_, _| Vector::zeros())
+	}
+
+	/// Like `intersect_scene_with_disk`, adding `correction` to the acceleration of the
+	/// geodesic, see `intersect_with`.
+	pub(crate) fn intersect_scene_with<'d>(
+		&mut self,
+		scene: &Scene,
+		disk: Option<&'d MultiRing>,
+		max_evals: u32,
+		correction: &dyn Fn(Vector, Vector) -> Vector,
+	) -> Option<(Point, Ray, RayHit, Option<&'d RingBand>)> {
+		let mut hit = None;
+		let mut last = self.particle.pos();
+		let ring = &scene.ring;
+		self.integrate(scene.sphere.pos, max_evals, correction, |pos| {
+			let step = Ray {
+				origin: last,
+				direction: Unit::new_normalize(pos - last),
+			};
+			// Rings are crossed before the sphere is entered if both happen in one step
+			let within_step = |&t: &f64| t * t <= (pos - last).norm_squared();
+			let ring_hit = ring.intersect(&step).filter(within_step);
+			let disk_hit =
+				disk.and_then(|d| d.intersect(&step).filter(within_step).map(|t| (d, t)));
+			let crossing = match (ring_hit, disk_hit) {
+				(Some(t), Some((_, td))) if t <= td => Some((t, None)),
+				(_, Some((d, t))) => Some((t, Some(d))),
+				(t, None) => t.map(|t| (t, None)),
+			};
+			if let Some((t, d)) = crossing {
+				let pt = last + step.direction.as_ref() * t;
+				let band = d.and_then(|d| d.band_at(&pt));
+				hit = Some((pt, step, RayHit::Ring, band));
+			} else if scene.spheres().any(|s| contains(s, pos)) {
+				hit = Some((pos, step, RayHit::Sphere, None));
+			}
+			last = pos;
+			hit.is_some()
+		});
+
+		hit
+	}
+
+	/// Integrates the geodesic like `intersect`, returning every position along the way, from
+	/// the starting point until the particle enters the sphere or `max_evals` force evaluations
+	/// are spent.
+	pub fn trace_path(&mut self, sphere: &Sphere, max_evals: u32) -> Vec<Point> {
+		let mut path = vec![self.particle.pos()];
+		let no_correction = |_, _| Vector::zeros();
+		self.integrate(sphere.pos, max_evals, &no_correction, |pos| {
+			path.push(pos);
+			let to_sphere = sphere.pos - pos;
+			to_sphere.dot(&to_sphere) < sphere.radius * sphere.radius
+		});
+
+		path
+	}
+
+	/// Steps the particle until `stop` returns true for its new position, or the force
+	/// evaluation budget runs out. With a tolerance set, the timestep is halved when the
+	/// potential changes too much in a step (which is then retried), and doubled when it barely
+	/// changes.
+	fn integrate<F: FnMut(Point) -> bool>(
+		&mut self,
+		sing_pos: Point,
+		max_evals: u32,
+		correction: &dyn Fn(Vector, Vector) -> Vector,
+		mut stop: F,
+	) {
+		let bodies = if self.bodies.is_empty() {
+			vec![(sing_pos, self.schwarzschild_radius)]
+		} else {
+			self.bodies.clone()
+		};
+		let mut dt = self.dt;
+		let mut evals = 0;
+		let evals_per_step = self.integrator.evals_per_step();
+		while evals + evals_per_step <= max_evals {
+			evals += evals_per_step;
+			self.evals += evals_per_step;
+			let before = self.particle.clone();
+			self.step(sing_pos, &bodies, dt, correction);
+			if let Some(tolerance) = self.tolerance {
+				let change = potential_change(&bodies, before.pos(), self.particle.pos());
+				if change > tolerance && dt > self.dt * MIN_DT_FACTOR {
+					self.particle = before;
+					dt /= 2.0;
+					continue;
+				} else if change < tolerance * 1e-2 && dt < self.dt * MAX_DT_FACTOR {
+					dt *= 2.0;
+				}
+			}
+			if let Some(trajectory) = &mut self.trajectory {
+				trajectory.push(self.particle.pos());
+			}
+			let pos = self.particle.pos();
+			let radius = bodies
+				.iter()
+				.map(|(body, _)| (pos - body).norm())
+				.fold(f64::INFINITY, f64::min);
+			self.min_radius = Some(self.min_radius.map_or(radius, |r| r.min(radius)));
+			self.captured |= bodies.iter().any(|(body, rs)| (pos - body).norm() <= *rs);
+			if stop(self.particle.pos()) {
+				return;
 			}
 		}
+	}
 
-		return None;
+	pub fn particle(&self) -> &Particle {
+		&self.particle
 	}
 
-	fn rk4_gr(&mut self, sing_pos: Point, h2: f64) {
-		// print!("Iteration: old pos: {}", self.particle.pos());
-		let vel = runge_kutta4(
-			&|v| gr_potential(v, h2),
-			self.particle.pos() - sing_pos,
-			self.dt,
-		);
-		self.particle.set_vel(vel);
-		self.particle.set_pos(self.particle.pos() + vel * self.dt);
-		println!("Position: {} Velocity: {}", self.particle.pos(), vel);
+	/// Advances the particle by `dt` with the leapfrog (Störmer-Verlet) scheme: half a kick of
+	/// the velocity, a drift of the position, and the other half kick from the acceleration at
+	/// the new position. `force_fn` gives the acceleration at a position, for the squared angular
+	/// momentum of the particle at the start of the step. This is symplectic and time-reversible,
+	/// so orbits don't drift, but velocity-dependent forces are only approximated.
+	pub fn step_leapfrog(&mut self, dt: f64, force_fn: &dyn Fn(Point, f64) -> Vector) {
+		let h2 = self.particle.angular_momentum().norm_squared();
+		let pos = self.particle.pos();
+		let half_vel = self.particle.vel() + force_fn(pos, h2) * dt / 2.0;
+		let pos = pos + half_vel * dt;
+		self.particle.set_pos(pos);
+		self.particle
+			.set_vel(half_vel + force_fn(pos, h2) * dt / 2.0);
+		self.particle.set_acc(Vector::zeros());
+	}
+
+	fn step(
+		&mut self,
+		sing_pos: Point,
+		bodies: &[(Point, f64)],
+		dt: f64,
+		correction: &dyn Fn(Vector, Vector) -> Vector,
+	) {
+		let geodesic_type = self.geodesic_type;
+		let force = |p: &Particle| {
+			geodesic_type.acceleration_from(bodies, p) + correction(p.pos() - sing_pos, p.vel())
+		};
+		match self.integrator {
+			Integrator::Euler => {
+				let acc = force(&self.particle);
+				let (pos, vel) = (self.particle.pos(), self.particle.vel());
+				self.particle.set_pos(pos + vel * dt);
+				self.particle.set_vel(vel + acc * dt);
+			}
+			Integrator::SymplecticEuler => self.particle.integrate_symplectic(dt, &force),
+			Integrator::Leapfrog => {
+				// Forces depend on the velocity, which is frozen to the start of the step
+				let vel = self.particle.vel();
+				self.step_leapfrog(dt, &|pos, _| {
+					let mut p = Particle::new(pos);
+					p.set_vel(vel);
+					force(&p)
+				});
+			}
+			Integrator::RungeKutta4 => self.particle.update_rk4(dt, &force),
+		}
+	}
+}
+
+impl Integrator {
+	/// Force evaluations done by a single integration step
+	pub const fn evals_per_step(self) -> u32 {
+		match self {
+			Integrator::Euler | Integrator::SymplecticEuler => 1,
+			Integrator::Leapfrog => 2,
+			Integrator::RungeKutta4 => 4,
+		}
+	}
+}
+
+/// Runge-Kutta, or symplectic Euler with the `euler-integrator` feature, kept to benchmark
+/// against it
+impl Default for Integrator {
+	fn default() -> Self {
+		DEFAULT_INTEGRATOR
+	}
+}
+
+impl GeodesicType {
+	/// Acceleration of the particle, summed over the gravitating bodies. Each contribution is
+	/// computed with the angular momentum of the particle around that body.
+	fn acceleration_from(&self, bodies: &[(Point, f64)], part: &Particle) -> Vector {
+		bodies
+			.iter()
+			.map(|(pos, rs)| {
+				let rel = part.pos() - pos;
+				let h2 = rel.cross(&part.vel()).norm_squared();
+				*rs * self.acceleration(rel, h2)
+			})
+			.sum()
+	}
+
+	/// Acceleration at `pos`, relative to the singularity, for a specific angular momentum `h2`
+	fn acceleration(&self, pos: Vector, h2: f64) -> Vector {
+		match self {
+			GeodesicType::Photon => gr_potential(pos, h2),
+			GeodesicType::Massive { .. } => {
+				gr_potential(pos, h2) - 0.5 * pos / pos.dot(&pos).powf(1.5)
+			}
+		}
 	}
 }
 
 impl GRScene {
+	/// Default tolerance on the fractional change of the potential in a step, see
+	/// `GRParticle::with_tolerance`
+	pub const DEFAULT_TOLERANCE: f64 = 1e-4;
+
+	/// Scene with a single black hole at the center of the sphere, tracing geodesics with the
+	/// default tolerance and integrator
+	pub fn new(scene: Scene, dt: f64, max_evals: u32) -> Self {
+		GRScene {
+			scene,
+			dt,
+			max_evals,
+			tolerance: Self::DEFAULT_TOLERANCE,
+			bodies: vec![],
+			integrator: Integrator::default(),
+			multi_ring: None,
+		}
+	}
+
+	/// Schwarzschild radius `r_s = 2GM/c²` of the black hole at the center of the sphere: that of
+	/// the closest body. Without any, the geodesic equations are written in units where it is 1,
+	/// whatever the radius of the sphere.
+	pub fn schwarzschild_radius(&self) -> f64 {
+		let center = self.scene.sphere.pos;
+		self.bodies()
+			.into_iter()
+			.map(|(pos, rs)| ((pos - center).norm(), rs))
+			.min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+			.map_or(SCHWARZSCHILD_RADIUS, |(_, rs)| rs)
+	}
+
+	/// Redshift `z = 1/sqrt(1 - r_s/r) - 1` of light climbing out of the gravitational well, from
+	/// a distance `r` of the black hole to the far away camera. It is infinite at and below the
+	/// event horizon.
+	pub fn gravitational_redshift(&self, r: f64) -> f64 {
+		let rs = self.schwarzschild_radius();
+		if r <= rs {
+			return f64::INFINITY;
+		}
+		1.0 / (1.0 - rs / r).sqrt() - 1.0
+	}
+
+	/// Gravitating bodies of the scene, as their position and Schwarzschild radius: the ones set
+	/// on the scene, or the implicit black hole at the center of the sphere.
+	pub fn bodies(&self) -> Vec<(Point, f64)> {
+		if self.bodies.is_empty() {
+			vec![(self.scene.sphere.pos, SCHWARZSCHILD_RADIUS)]
+		} else {
+			self.bodies.clone()
+		}
+	}
+
+	/// Gravitational redshift of light emitted at `pt`, adding up the potentials of all bodies.
+	/// With a single body, this is `gravitational_redshift` of the distance to it.
+	fn redshift_at(&self, pt: &Point) -> f64 {
+		let potential: f64 = self
+			.bodies()
+			.iter()
+			.map(|(pos, rs)| rs / (pt - pos).norm())
+			.sum();
+		if potential >= 1.0 {
+			return f64::INFINITY;
+		}
+		1.0 / (1.0 - potential).sqrt() - 1.0
+	}
+
+	/// Color of the geodesic through the pixel position and lens point, along with what it hit
+	/// and the AOVs of the hit. The depth is the straight distance from the camera to the hit.
+	/// Geodesics go on through translucent bands of the disk, whose color is blended over what's
+	/// behind them. `correction` is added to their acceleration, see
+	/// `GRParticle::intersect_with`.
+	pub(crate) fn shade(
+		&self,
+		x: f64,
+		y: f64,
+		lens: Vector2<f64>,
+		correction: &dyn Fn(Vector, Vector) -> Vector,
+	) -> (Color, Option<RayHit>, PixelAovs) {
+		let scene = self.scene.clone();
+		let bg = scene.get_background();
+		let ring = &self.scene.ring;
+		let ray = self.scene.camera.create_primary_lens(x, y, lens);
+		let mut part = GRParticle::from_ray(&ray, self.dt)
+			.with_tolerance(self.tolerance)
+			.with_bodies(&self.bodies)
+			.with_integrator(self.integrator);
+
+		// Layers are summed in linear light, in the color system of the first one
+		let mut col: Option<Color> = None;
+		let mut add = |layer: Color| {
+			col = Some(match col.take() {
+				Some(c) => c + layer,
+				None => layer,
+			})
+		};
+		let mut transmittance = 1.0;
+		let mut first = None;
+		// The budget of evaluations is shared by all the layers of the geodesic
+		while let Some((pt, step, hit, band)) = part.intersect_scene_with(
+			&self.scene,
+			self.multi_ring.as_ref(),
+			self.max_evals.saturating_sub(part.evals()),
+			correction,
+		) {
+			let (surface, normal, uv) = match (hit, self.multi_ring.as_ref().zip(band)) {
+				(RayHit::Ring, Some((disk, _))) => (
+					disk.sample_texture(pt, &step),
+					disk.facing_normal(&step),
+					disk.texture_coords(&pt),
+				),
+				(RayHit::Ring, None) => {
+					// Light reaching the camera travels back along the geodesic
+					let beta = ring.velocity_at(&pt).dot(&-step.direction.as_ref());
+					let col = doppler_shift(ring.sample_texture(pt, &step), beta);
+					(col, ring.facing_normal(&step), ring.texture_coords(&pt))
+				}
+				_ => {
+					let sphere = self.scene.spheres().find(|s| contains(s, pt));
+					let sphere = sphere.unwrap_or(&self.scene.sphere);
+					let uv = sphere.texture_coords(&pt);
+					(sphere.texture.uv(uv), sphere.surface_normal(&pt), uv)
+				}
+			};
+			if first.is_none() {
+				let aovs = PixelAovs {
+					depth: Some((pt - ray.origin).norm()),
+					normal: Some(normal),
+					uv: Some(uv),
+				};
+				first = Some((hit, aovs));
+			}
+			let opacity = band.map_or(1.0, |b| b.opacity);
+			add(redshift(surface, self.redshift_at(&pt)) * (transmittance * opacity));
+			transmittance *= 1.0 - opacity;
+			if transmittance <= 0.0 {
+				break;
+			}
+		}
+		if transmittance > 0.0 {
+			let uv = background_uv_from_direction(&Unit::new_normalize(part.particle.vel()));
+			add(bg.uv(uv) * transmittance);
+		}
+
+		let (hit, aovs) = first.unwrap_or((RayHit::Background, PixelAovs::default()));
+		return (col.unwrap_or_default(), Some(hit), aovs);
+	}
+
 	pub fn get_scene(self) -> Scene {
-		self.0
+		self.scene
 	}
 
 	pub fn set_camera(
@@ -71,66 +653,862 @@ impl GRScene {
 		rot: Option<UnitQuaternion<f64>>,
 		fov: Option<f64>,
 	) {
-		self.0.set_camera(trans, rot, fov);
+		self.scene.set_camera(trans, rot, fov);
 	}
 
 	pub fn set_size(&mut self, width: u32, height: u32) {
-		self.0.set_size(width, height);
+		self.scene.set_size(width, height);
+	}
+
+	/// The scene's ring, with its radii set from the black hole's Schwarzschild radius (see
+	/// `schwarzschild_radius`): from the ISCO out to 6 rs.
+	pub fn default_ring(&self) -> Ring {
+		self.scene
+			.ring
+			.clone()
+			.with_isco(self.schwarzschild_radius(), 6.0)
+	}
+
+	/// Overlays the analytic shadow of the black hole on a render of the scene, in green.
+	pub fn draw_shadow_circle(&self, img: &mut DynamicImage) {
+		self.draw_shadow_circle_with_color(img, Rgba([0, 255, 0, 255]));
+	}
+
+	/// Overlays the analytic shadow of the black hole on a render of the scene: a circle of
+	/// radius `3√3/2 rs` around the sphere, facing the camera.
+	pub fn draw_shadow_circle_with_color(&self, img: &mut DynamicImage, col: Rgba<u8>) {
+		const SEGMENTS: u32 = 128;
+		let camera = &self.scene.camera;
+		let sphere = &self.scene.sphere;
+		let r_shadow = 3f64.sqrt() * photon_sphere_radius(self.schwarzschild_radius());
+
+		let view =
+			Unit::new_normalize(sphere.pos - Point::from(camera.isometry.translation.vector));
+		let up = if view.cross(&Vector::y_axis()).norm() > 1e-6 {
+			Vector::y_axis()
+		} else {
+			Vector::x_axis()
+		};
+		let u = view.cross(&up).normalize();
+		let v = view.cross(&u);
+		let point = |i: u32| {
+			let angle = 2.0 * PI * i as f64 / SEGMENTS as f64;
+			camera.world_to_pixel(&(sphere.pos + r_shadow * (angle.cos() * u + angle.sin() * v)))
+		};
+
+		if img.as_rgba8().is_none() {
+			*img = DynamicImage::ImageRgba8(img.to_rgba());
+		}
+		let buf = img.as_mut_rgba8().unwrap();
+		for i in 0..SEGMENTS {
+			if let (Some(from), Some(to)) = (point(i), point(i + 1)) {
+				draw_line(buf, from, to, col);
+			}
+		}
+	}
+
+	/// Renders the scene with rayon's parallel iterators, as each geodesic is independent. See
+	/// `render_par_with_config` for the settings of the configuration it follows.
+	pub fn render(
+		&self,
+		config: RenderConfig,
+		r: Option<Reporter>,
+	) -> Result<DynamicImage, RenderError> {
+		render_par_with_config(self, config, r)
+	}
+
+	/// Renders like `render`, on an already-built thread pool so it can be reused across frames
+	pub fn render_with_pool(
+		&self,
+		pool: &ThreadPool,
+		config: RenderConfig,
+		r: Option<Reporter>,
+	) -> Result<DynamicImage, RenderError> {
+		render_par_with_pool(self, pool, config, r)
+	}
+
+	/// Renders the scene with rayon's parallel iterators, as each geodesic is independent.
+	pub fn render_par(&self, r: Option<Reporter>) -> Result<DynamicImage, RenderError> {
+		render_par(self, r)
+	}
+
+	/// Renders progressively, yielding the average of all passes so far after each of them, so
+	/// the image can be watched converging. Each pass traces every pixel once, jittered within
+	/// the pixel and on the lens along the same sequence as antialiasing, the first pass going
+	/// through the pixel corners. Take only the first image for a quick preview.
+	pub fn render_progressive<'a>(
+		&'a self,
+		passes: u32,
+		r: Option<Reporter<'a>>,
+	) -> impl Iterator<Item = DynamicImage> + 'a {
+		self.render_progressive_with_config(passes, RenderConfig::default(), r)
+	}
+
+	/// Same as `render_progressive`, tone mapping the images with the configuration's operator.
+	/// Passes run on rayon's global pool, and `samples` is replaced by the number of passes, so
+	/// only the tone mapping is taken from the configuration.
+	pub fn render_progressive_with_config<'a>(
+		&'a self,
+		passes: u32,
+		config: RenderConfig,
+		r: Option<Reporter<'a>>,
+	) -> impl Iterator<Item = DynamicImage> + 'a {
+		let (width, height) = self.get_dimensions();
+		let offsets = RenderConfig {
+			samples: passes,
+			..config.clone()
+		}
+		.sample_offsets();
+		// Sums of the passes, in linear light so they average like light does
+		let mut accum = vec![[0.0f64; 4]; (width * height) as usize];
+
+		offsets
+			.into_iter()
+			.take(passes as usize)
+			.enumerate()
+			.map(move |(pass, (offset, lens))| {
+				if let Some(f) = r {
+					(*f)(
+						pass as f64 / passes as f64,
+						format!("Pass {}/{}...", pass + 1, passes),
+					);
+				}
+				let colors: Vec<Color> = (0..width * height)
+					.into_par_iter()
+					.map(|i| {
+						let (x, y) = ((i % width) as f64, (i / width) as f64);
+						self.trace_subpx(x + offset.x, y + offset.y, lens).0
+					})
+					.collect();
+
+				for (acc, col) in accum.iter_mut().zip(colors) {
+					// Decoded with the color's own system, if any
+					let linear: [f64; 4] = col.into();
+					for (a, v) in acc.iter_mut().zip(&linear) {
+						*a += v;
+					}
+				}
+				let n = (pass + 1) as f64;
+				let pixels = accum
+					.iter()
+					.map(|acc| {
+						[
+							(acc[0] / n) as f32,
+							(acc[1] / n) as f32,
+							(acc[2] / n) as f32,
+							(acc[3] / n) as f32,
+						]
+					})
+					.collect();
+
+				encode_linear(width, height, pixels, config.tone_mapping)
+			})
+	}
+
+	/// Fraction of photons escaping to the background rather than falling into a sphere, out
+	/// of `sample_count` (at least one) rays fired through random points of the image. It is a
+	/// cheap check of the timestep and evaluation budget before a full render, as photons that
+	/// run out of evaluations count as escaped. The same seed always fires the same rays.
+	pub fn escape_fraction(&self, sample_count: u32, rng_seed: u64) -> f64 {
+		let GRScene {
+			scene,
+			dt,
+			max_evals,
+			tolerance,
+			bodies,
+			integrator,
+			..
+		} = self;
+		let (width, height) = (scene.camera.width as f64, scene.camera.height as f64);
+		let mut rng = SplitMix64(rng_seed);
+		let rays: Vec<Ray> = (0..sample_count.max(1))
+			.map(|_| {
+				let (x, y) = (rng.next_f64() * width, rng.next_f64() * height);
+				scene.camera.create_primary_at(x, y)
+			})
+			.collect();
+
+		let escaped = rays
+			.par_iter()
+			.filter(|ray| {
+				GRParticle::from_ray(ray, *dt)
+					.with_tolerance(*tolerance)
+					.with_bodies(bodies)
+					.with_integrator(*integrator)
+					.intersect_spheres(scene, *max_evals)
+					.is_none()
+			})
+			.count();
+
+		escaped as f64 / rays.len() as f64
 	}
 }
 
 impl Renderable for GRScene {
 	fn render_px(&self, x: u32, y: u32) -> Color {
-		let scene = self.0.clone();
-		let bg = scene.get_background();
-		let mut part = GRParticle::from_particle(
-			&Particle::from_ray(&self.0.camera.create_primary(x, y)),
-			self.1,
-		);
-		return part
-			.intersect(&self.0.sphere, self.2)
-			.map(|pt| {
-				let uv = self.0.sphere.texture_coords(&pt);
-				return self.0.sphere.texture.uv(uv);
-			})
-			.or_else(|| {
-				let (_, theta, phi) = cartesian_to_spherical(&part.particle.vel());
+		self.trace_px(x, y).0
+	}
 
-				let uv = TexCoords::new(theta / f64::consts::PI, phi / f64::consts::FRAC_PI_2);
+	fn trace_px(&self, x: u32, y: u32) -> (Color, Option<RayHit>) {
+		self.trace_subpx(x as f64, y as f64, Vector2::zeros())
+	}
 
-				return Some(bg.uv(uv));
-			})
-			.unwrap_or(Color::from_u32(0));
+	fn trace_subpx(&self, x: f64, y: f64, lens: Vector2<f64>) -> (Color, Option<RayHit>) {
+		let (col, hit, _) = self.shade(x, y, lens, &|_, _| Vector::zeros());
+		(col, hit)
+	}
+
+	fn render_px_aovs(&self, x: u32, y: u32) -> (Color, PixelAovs) {
+		let no_correction = |_, _| Vector::zeros();
+		let (col, _, aovs) = self.shade(x as f64, y as f64, Vector2::zeros(), &no_correction);
+		(col, aovs)
 	}
 
 	fn get_dimensions(&self) -> (u32, u32) {
-		return self.0.get_dimensions();
+		return self.scene.get_dimensions();
 	}
 }
 
-fn gr_potential(pos: Vector, h2: f64) -> Vector {
-	let pos_fifth = pos.dot(&pos).powf(2.5);
+/// Serialized form of a `GRScene`, with its settings ahead of the flat scene and the budget of
+/// force evaluations under its `max_iter` name
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GRSceneDesc {
+	dt: f64,
+	max_iter: u32,
+	tolerance: f64,
+	scene: Scene,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	bodies: Vec<(Point, f64)>,
+	#[serde(default)]
+	integrator: Integrator,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	multi_ring: Option<MultiRing>,
+}
 
-	return -1.5 * h2 * pos / pos_fifth;
+#[cfg(feature = "serde")]
+impl From<GRSceneDesc> for GRScene {
+	fn from(desc: GRSceneDesc) -> Self {
+		GRScene {
+			scene: desc.scene,
+			dt: desc.dt,
+			max_evals: desc.max_iter,
+			tolerance: desc.tolerance,
+			bodies: desc.bodies,
+			integrator: desc.integrator,
+			multi_ring: desc.multi_ring,
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+impl From<GRScene> for GRSceneDesc {
+	fn from(scene: GRScene) -> Self {
+		GRSceneDesc {
+			dt: scene.dt,
+			max_iter: scene.max_evals,
+			tolerance: scene.tolerance,
+			scene: scene.scene,
+			bodies: scene.bodies,
+			integrator: scene.integrator,
+			multi_ring: scene.multi_ring,
+		}
+	}
 }
 
-fn runge_kutta4(fx: &Fn(Vector) -> Vector, x: Vector, dt: f64) -> Vector {
-	let k1 = dt * fx(x);
-	let k2 = dt * fx(x + k1 / 2.0);
-	let k3 = dt * fx(x + k2 / 2.0);
-	let k4 = dt * fx(x + k3);
+/// Fractional change of the magnitude of the potential of the bodies between two positions. It
+/// doesn't depend on the angular momentum, which is left out so radial geodesics are handled
+/// too.
+fn potential_change(bodies: &[(Point, f64)], from: Point, to: Point) -> f64 {
+	let potential = |p: Point| -> f64 {
+		bodies
+			.iter()
+			.map(|(pos, rs)| rs * gr_potential(p - pos, 1.0).norm())
+			.sum()
+	};
+	let before = potential(from);
+	(potential(to) - before).abs() / before
+}
 
-	x + (k1 + 2.0 * k2 + 2.0 * k3 + k4) / 6.0
+/// Radius of the photon sphere of a black hole of the given Schwarzschild radius, where light
+/// can orbit in (unstable) circles. Light passing closer than that falls in.
+pub fn photon_sphere_radius(schwarzschild_radius: f64) -> f64 {
+	1.5 * schwarzschild_radius
+}
+
+/// Radius of the innermost stable circular orbit around a black hole of the given
+/// Schwarzschild radius, where accretion disks end
+pub fn isco_radius(schwarzschild_radius: f64) -> f64 {
+	3.0 * schwarzschild_radius
+}
+
+/// Whether the point is inside the sphere
+fn contains(sphere: &Sphere, pos: Point) -> bool {
+	let to_sphere = sphere.pos - pos;
+	to_sphere.dot(&to_sphere) < sphere.radius * sphere.radius
+}
+
+fn gr_potential(pos: Vector, h2: f64) -> Vector {
+	let pos_fifth = pos.dot(&pos).powf(2.5);
+
+	return -1.5 * h2 * pos / pos_fifth;
 }
 
 #[cfg(test)]
 mod tests {
-	use super::GRScene;
+	use super::{
+		isco_radius, photon_sphere_radius, GRParticle, GRScene, GeodesicType, Integrator,
+		DEFAULT_INTEGRATOR,
+	};
+
+	use crate::postprocess::ToneMapping;
+	use crate::raytrace::render::{render, render_with_config, RenderConfig};
+	use crate::raytrace::{Point, Ray, RayHit, Renderable};
+	use crate::{
+		Camera, MultiRing, Ring, RingBand, Scene, Sphere, Texture, TextureFiltering, TextureMode,
+	};
+	use color::Color;
+	use image::{DynamicImage, Pixel, Rgb, Rgba};
+	use nalgebra::{Translation3, UnitQuaternion, Vector3};
+
+	use std::cell::Cell;
+	use std::f64::consts::PI;
 
-	use crate::raytrace::render::render;
-	use crate::raytrace::Point;
-	use crate::{Camera, Ring, Scene, Sphere, Texture, TextureFiltering, TextureMode};
-	use image::{DynamicImage, Pixel, Rgb};
+	const EVALS_PER_STEP: u32 = DEFAULT_INTEGRATOR.evals_per_step();
+
+	const BLACK: [u8; 3] = [0, 0, 0];
+	const WHITE: [u8; 3] = [255, 255, 255];
+
+	/// Texture of a single pixel
+	fn plain_texture(rgb: [u8; 3]) -> Texture {
+		let img = DynamicImage::ImageRgb8(image::ImageBuffer::from_pixel(1, 1, Rgb(rgb)));
+		Texture::new(img, TextureFiltering::Nearest, TextureMode::Clamp)
+	}
+
+	/// Scene around a single black hole, with a sphere and a ring of the given radii, tracing
+	/// geodesics from a timestep of 0.1 with a tolerance of 0.1 and 2000 evaluations
+	fn test_scene(camera: Camera, sphere: f64, ring: (f64, f64), tex: Texture) -> GRScene {
+		let scene = Scene {
+			camera,
+			sphere: Sphere::new(sphere, tex.clone()),
+			objects: vec![],
+			ring: Ring::new(ring.0, ring.1, tex),
+			bgtex: None,
+		};
+		GRScene {
+			tolerance: 0.1,
+			..GRScene::new(scene, 0.1, 2000)
+		}
+	}
+
+	#[test]
+	fn parallel_render_matches_pooled_render() {
+		let camera = Camera::new(12, 12, 30.0);
+		let tex = Scene::create_bg_texture(4, 4).unwrap();
+		let mut scene = GRScene {
+			dt: 0.5,
+			max_evals: 10,
+			tolerance: GRScene::DEFAULT_TOLERANCE,
+			..test_scene(camera, 1.0, (2.0, 3.0), tex)
+		};
+		scene.set_camera(Some(Translation3::new(0.0, 0.0, 4.0)), None, None);
+
+		let pooled = render(scene.clone(), None).unwrap();
+		let parallel = scene.render_par(None).unwrap();
+		assert_eq!(pooled.raw_pixels(), parallel.raw_pixels());
+		let method = scene.render(RenderConfig::default(), None).unwrap();
+		assert_eq!(pooled.raw_pixels(), method.raw_pixels());
+		// The same pool renders any number of frames
+		let pool = RenderConfig::default().thread_pool().unwrap();
+		for _ in 0..2 {
+			let frame = scene
+				.render_with_pool(&pool, RenderConfig::default(), None)
+				.unwrap();
+			assert_eq!(pooled.raw_pixels(), frame.raw_pixels());
+		}
+
+		// Following the samples and tone mapping of the configuration
+		let config = RenderConfig {
+			samples: 4,
+			tone_mapping: ToneMapping::Aces,
+			..RenderConfig::default()
+		};
+		let pooled = render_with_config(scene.clone(), config.clone(), None).unwrap();
+		let method = scene.render(config, None).unwrap();
+		assert_eq!(pooled.raw_pixels(), method.raw_pixels());
+		assert_ne!(pooled.raw_pixels(), parallel.raw_pixels());
+	}
+
+	#[test]
+	fn renders_progressively() {
+		let tex = Scene::create_bg_texture(8, 8).unwrap();
+		let mut scene = GRScene {
+			dt: 0.5,
+			max_evals: 50,
+			tolerance: GRScene::DEFAULT_TOLERANCE,
+			..test_scene(Camera::new(16, 16, 30.0), 1.0, (2.0, 3.0), tex.clone())
+		};
+		scene.scene.bgtex = Some(tex);
+		scene.set_camera(Some(Translation3::new(0.0, 0.0, 8.0)), None, None);
+
+		let reports = Cell::new(0);
+		let reporter = |_: f64, _: String| reports.set(reports.get() + 1);
+		let images: Vec<_> = scene.render_progressive(4, Some(&reporter)).collect();
+		assert_eq!(images.len(), 4);
+		assert_eq!(reports.get(), 4);
+		assert_eq!(scene.render_progressive(0, None).count(), 0);
+
+		// The first pass goes through pixel corners, like a render without antialiasing
+		let single = render(scene.clone(), None).unwrap();
+		for (a, b) in images[0].raw_pixels().iter().zip(single.raw_pixels()) {
+			assert!((*a as i32 - b as i32).abs() <= 1, "{} != {}", a, b);
+		}
+		// Later passes smooth out the edges of the checkerboard
+		assert_ne!(images[3].raw_pixels(), images[0].raw_pixels());
+	}
+
+	#[test]
+	fn redshifts_light_from_the_sphere() {
+		let camera = Camera::new(9, 9, 30.0);
+		let mut scene = test_scene(camera, 2.0, (20.0, 21.0), plain_texture(WHITE));
+		scene.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		assert_eq!(scene.schwarzschild_radius(), 1.0);
+		assert!((scene.gravitational_redshift(4.0 / 3.0) - 1.0).abs() < 1e-12);
+		assert_eq!(scene.gravitational_redshift(0.5), f64::INFINITY);
+
+		// Light from just under r = 2 is redshifted by a bit more than sqrt(2) - 1
+		let (col, hit) = scene.trace_px(4, 4);
+		assert_eq!(hit, Some(RayHit::Sphere));
+		let [r, g, b, _]: [f64; 4] = col.into();
+		assert!(r < 2f64.sqrt().powi(-4) && r > 0.0, "{}", r);
+		let depth = scene.render_px_depth(4, 4).1.unwrap();
+		assert!(depth > 7.5 && depth < 8.5, "{}", depth);
+		assert!(r >= g && g > b, "{} {} {}", r, g, b);
+
+		// Nothing comes out of the event horizon
+		scene.scene.sphere.radius = 1.0;
+		let (col, hit) = scene.trace_px(4, 4);
+		assert_eq!(hit, Some(RayHit::Sphere));
+		let [r, g, b, _]: [f64; 4] = col.into();
+		assert_eq!((r, g, b), (0.0, 0.0, 0.0));
+	}
+
+	#[test]
+	fn computes_characteristic_radii() {
+		// In geometrized units, a black hole of mass M = 1 has r_s = 2GM/c² = 2
+		let rs = 2.0;
+		assert_eq!(photon_sphere_radius(rs), 3.0);
+		assert_eq!(isco_radius(rs), 6.0);
+
+		// Light skimming the photon sphere of the scene's black hole circles around it
+		let camera = Camera::new(1, 1, 30.0);
+		let scene = GRScene {
+			dt: 0.01,
+			..test_scene(camera, 1.0, (20.0, 21.0), plain_texture(BLACK))
+		};
+		let r = photon_sphere_radius(scene.schwarzschild_radius());
+		assert_eq!(r, 1.5);
+		let mut part = GRParticle::from_ray(
+			&Ray {
+				origin: Point::new(r, 0.0, 0.0),
+				direction: Vector3::y_axis(),
+			},
+			0.01,
+		);
+		let path = part.trace_path(&scene.scene.sphere, 200 * EVALS_PER_STEP);
+		assert_eq!(path.len(), 201);
+		for pt in path {
+			assert!((pt.coords.norm() - r).abs() < 0.01, "{}", pt);
+		}
+	}
+
+	#[test]
+	fn doppler_shifts_the_spinning_ring() {
+		let camera = Camera::look_at(
+			Point::new(0.0, -10.0, 10.0),
+			Point::origin(),
+			Vector3::z(),
+			60.0,
+			64,
+			32,
+		);
+		let mut scene = test_scene(camera, 1.0, (4.0, 6.0), plain_texture(WHITE));
+		scene.scene.ring.angular_velocity = 0.1;
+
+		// The ring material moves towards the camera on the left, away from it on the right
+		let ring_hits: Vec<Color> = (0..64)
+			.map(|x| scene.trace_px(x, 21))
+			.filter(|(_, hit)| *hit == Some(RayHit::Ring))
+			.map(|(col, _)| col)
+			.collect();
+		assert!(ring_hits.len() >= 2);
+		let left: [f64; 4] = ring_hits[0].clone().into();
+		let right: [f64; 4] = ring_hits[ring_hits.len() - 1].clone().into();
+		assert!(left[2] > left[0], "{:?}", left);
+		assert!(right[0] > right[2], "{:?}", right);
+	}
+
+	#[test]
+	fn blends_translucent_disk_bands() {
+		let tex = plain_texture(WHITE);
+		let camera = Camera::look_at(
+			Point::new(0.0, -10.0, 10.0),
+			Point::new(0.0, 8.0, 0.0),
+			Vector3::z(),
+			30.0,
+			9,
+			9,
+		);
+		let band = |inner, outer, opacity| RingBand {
+			inner,
+			outer,
+			opacity,
+		};
+		let disk = MultiRing::new(
+			vec![band(3.0, 9.0, 0.5), band(12.0, 14.0, 1.0)],
+			tex.clone(),
+		);
+		let mut scene = GRScene {
+			multi_ring: Some(disk),
+			..test_scene(camera, 1.0, (30.0, 31.0), tex)
+		};
+		scene.scene.bgtex = Some(plain_texture(BLACK));
+
+		let (translucent, hit) = scene.trace_px(4, 6);
+		assert_eq!(hit, Some(RayHit::Ring));
+		let depth = scene.render_px_aovs(4, 6).1.depth.unwrap();
+		assert!(depth > 15.0 && depth < 20.0, "{}", depth);
+
+		scene.multi_ring.as_mut().unwrap().bands[0].opacity = 1.0;
+		let (opaque, _) = scene.trace_px(4, 6);
+		// Blended in linear light over the black background
+		let [t, _, _, a]: [f64; 4] = translucent.into();
+		let [o, _, _, _]: [f64; 4] = (opaque * 0.5).into();
+		assert!(o > 0.0);
+		assert!((t - o).abs() < 1e-9, "{} {}", t, o);
+		assert!((a - 1.0).abs() < 1e-9, "{}", a);
+
+		// Geodesics between the bands go through the disk
+		let mut part = GRParticle::from_ray(
+			&Ray {
+				origin: Point::new(10.5, 0.0, 5.0),
+				direction: -Vector3::z_axis(),
+			},
+			0.1,
+		);
+		let hit = part.intersect_scene_with_disk(&scene.scene, scene.multi_ring.as_ref(), 2000);
+		assert!(hit.is_none_or(|(_, _, _, band)| band.is_none()));
+	}
+
+	#[test]
+	fn escape_fraction_counts_captured_photons() {
+		let camera = Camera::new(32, 32, 60.0);
+		let mut scene = test_scene(camera, 1.0, (2.0, 3.0), plain_texture(BLACK));
+		scene.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		let facing = scene.escape_fraction(200, 7);
+		assert!(facing > 0.5 && facing < 1.0, "{}", facing);
+		assert_eq!(scene.escape_fraction(200, 7), facing);
+
+		// Looking away from the black hole, every photon escapes
+		scene.set_camera(
+			None,
+			Some(UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI)),
+			None,
+		);
+		assert_eq!(scene.escape_fraction(200, 7), 1.0);
+
+		// Unless another black hole sits there
+		let mut companion = Sphere::new(1.0, scene.scene.sphere.texture.clone());
+		companion.pos = Point::new(0.0, 0.0, 16.0);
+		scene.bodies = vec![(Point::origin(), 1.0), (companion.pos, 1.0)];
+		scene.scene.objects.push(companion);
+		let behind = scene.escape_fraction(200, 7);
+		assert!(behind > 0.0 && behind < 1.0, "{}", behind);
+	}
+
+	#[test]
+	fn binary_black_holes_lens_separately() {
+		let tex = plain_texture(WHITE);
+		let bodies = vec![
+			(Point::new(-6.0, 0.0, 0.0), 1.0),
+			(Point::new(6.0, 0.0, 0.0), 1.0),
+		];
+
+		// Rays going down between both black holes are pulled apart, towards the closest one,
+		// where a single black hole would pull them together
+		let target = Sphere::new(0.1, tex.clone());
+		let deflection = |x: f64, bodies: &[(Point, f64)]| {
+			let ray = Ray {
+				origin: Point::new(x, 0.0, 20.0),
+				direction: -Vector3::z_axis(),
+			};
+			let mut part = GRParticle::from_ray(&ray, 0.1)
+				.with_tolerance(0.1)
+				.with_bodies(bodies);
+			part.intersect(&target, 2000);
+			part.particle.vel().x
+		};
+		assert!(deflection(1.5, &bodies) > 0.0);
+		assert!(deflection(-1.5, &bodies) < 0.0);
+		assert!(deflection(4.5, &[]) < 0.0);
+
+		let mut companion = Sphere::new(1.0, tex.clone());
+		companion.pos = bodies[1].0;
+		let mut scene = GRScene {
+			bodies: bodies.clone(),
+			..test_scene(Camera::new(32, 16, 60.0), 1.0, (20.0, 21.0), tex)
+		};
+		scene.scene.sphere.pos = bodies[0].0;
+		scene.scene.objects.push(companion);
+		scene.set_camera(Some(Translation3::new(0.0, 0.0, 30.0)), None, None);
+		assert_eq!(scene.bodies(), bodies);
+		for (pos, _) in &bodies {
+			let (x, y) = scene.scene.camera.world_to_pixel(pos).unwrap();
+			assert_eq!(scene.trace_px(x as u32, y as u32).1, Some(RayHit::Sphere));
+		}
+		let (x, y) = scene.scene.camera.world_to_pixel(&Point::origin()).unwrap();
+		assert_eq!(
+			scene.trace_px(x as u32, y as u32).1,
+			Some(RayHit::Background)
+		);
+	}
+
+	#[test]
+	fn particle_from_ray() {
+		let ray = Ray {
+			origin: Point::new(1.0, 2.0, 3.0),
+			direction: Vector3::x_axis(),
+		};
+		let part = GRParticle::from_ray(&ray, 0.5).with_dt(0.25);
+		assert_eq!(part.particle.pos(), ray.origin);
+		assert_eq!(part.particle.vel(), Vector3::x());
+		assert_eq!(part.dt, 0.25);
+	}
+
+	#[test]
+	fn circular_orbit_radii() {
+		// A circular orbit needs the acceleration to provide exactly the centripetal v² / r
+		let is_circular = |geodesic_type: GeodesicType, r: f64, v: f64| {
+			let pos = Vector3::new(r, 0.0, 0.0);
+			let h2 = (r * v).powi(2);
+			let acc = geodesic_type.acceleration(pos, h2);
+			(acc.norm() - v * v / r).abs() < 1e-9 && acc.x < 0.0
+		};
+		let massive = GeodesicType::Massive {
+			specific_energy: 1.0,
+		};
+
+		assert!(is_circular(GeodesicType::Photon, 1.5, 1.0));
+		assert!(!is_circular(GeodesicType::Photon, 3.0, 1.0));
+		assert!(is_circular(massive, 3.0, (1.0f64 / 3.0).sqrt()));
+		assert!(!is_circular(massive, 1.5, 1.0));
+	}
+
+	#[test]
+	fn leapfrog_keeps_circular_orbits() {
+		// Stable circular orbit of a massive particle at r = 5, where v² = 1 / (2r - 3)
+		let massive = GeodesicType::Massive {
+			specific_energy: 1.0,
+		};
+		let speed = (1.0f64 / 7.0).sqrt();
+		let mut part = GRParticle::new(Point::new(5.0, 0.0, 0.0), 0.01).with_geodesic_type(massive);
+		part.particle.set_vel(Vector3::new(0.0, speed, 0.0));
+		for _ in 0..1000 {
+			part.step_leapfrog(0.01, &|pos, h2| massive.acceleration(pos.coords, h2));
+			assert!((part.particle.pos().coords.norm() - 5.0).abs() < 1e-4);
+		}
+
+		// Through the scene integrator, explicit Euler spirals outwards
+		let radius_after = |integrator: Integrator| {
+			let tex = plain_texture(BLACK);
+			let mut part = GRParticle::new(Point::new(5.0, 0.0, 0.0), 0.01)
+				.with_geodesic_type(massive)
+				.with_integrator(integrator);
+			part.particle.set_vel(Vector3::new(0.0, speed, 0.0));
+			let evals = 1000 * integrator.evals_per_step();
+			assert_eq!(part.intersect(&Sphere::new(1.0, tex), evals), None);
+			part.particle.pos().coords.norm()
+		};
+		assert!((radius_after(Integrator::Leapfrog) - 5.0).abs() < 1e-4);
+		assert!((radius_after(Integrator::RungeKutta4) - 5.0).abs() < 1e-4);
+		assert!(radius_after(Integrator::Euler) > 5.0 + 1e-3);
+	}
+
+	#[test]
+	fn massive_particle_speed_from_energy() {
+		let ray = Ray {
+			origin: Point::new(0.0, 0.0, 10.0),
+			direction: -Vector3::z_axis(),
+		};
+		let part = GRParticle::from_ray(&ray, 0.1).with_geodesic_type(GeodesicType::Massive {
+			specific_energy: 1.25,
+		});
+		assert!((part.particle.vel() - Vector3::new(0.0, 0.0, -0.6)).norm() < 1e-9);
+	}
+
+	#[test]
+	fn traces_path_until_max_iter() {
+		let tex = plain_texture(BLACK);
+		let sphere = Sphere::new(1.0, tex);
+		let ray = Ray {
+			origin: Point::new(-10.0, 5.0, 0.0),
+			direction: Vector3::x_axis(),
+		};
+		let path = GRParticle::from_ray(&ray, 0.1).trace_path(&sphere, 20 * EVALS_PER_STEP);
+		assert_eq!(path.len(), 21);
+		assert_eq!(path[0], ray.origin);
+	}
+
+	#[test]
+	fn conserves_angular_momentum() {
+		let tex = plain_texture(BLACK);
+		let sphere = Sphere::new(1.0, tex);
+		let ray = Ray {
+			origin: Point::new(-10.0, 5.0, 0.0),
+			direction: Vector3::x_axis(),
+		};
+		let mut part = GRParticle::from_ray(&ray, 0.1);
+		let initial = part.particle().angular_momentum().norm();
+		assert_eq!(initial, 5.0);
+		// Past the black hole, with the geodesic bending the most
+		for _ in 0..500 {
+			assert_eq!(part.intersect(&sphere, EVALS_PER_STEP), None);
+			let h = part.particle().angular_momentum().norm();
+			assert!((h - initial).abs() < 1e-3 * initial, "{}", h);
+		}
+		assert!(part.particle().pos().x > 10.0);
+	}
+
+	#[test]
+	fn tracks_closest_approach() {
+		let tex = plain_texture(BLACK);
+		// A tiny sphere, so photons reach the horizon before hitting it
+		let sphere = Sphere::new(0.1, tex);
+		let fire = |y: f64| {
+			let ray = Ray {
+				origin: Point::new(-10.0, y, 0.0),
+				direction: Vector3::x_axis(),
+			};
+			GRParticle::from_ray(&ray, 0.1).with_tolerance(0.1)
+		};
+
+		let mut part = fire(5.0);
+		assert_eq!(part.min_radius(), None);
+		part.intersect(&sphere, 400 * EVALS_PER_STEP);
+		let closest = part.min_radius().unwrap();
+		assert!(closest > 3.0 && closest < 5.0, "{}", closest);
+		assert!(!part.is_captured());
+
+		// Under the critical impact parameter of 3√3/2
+		let mut part = fire(2.0);
+		part.intersect(&sphere, 400 * EVALS_PER_STEP);
+		assert!(part.is_captured());
+
+		// Each body captures within its own radius: the same impact parameter is well above the
+		// critical one of a black hole half as large
+		let origin = Point::origin();
+		for bodies in &[
+			vec![(origin, 0.5)],
+			vec![(origin, 0.5), (Point::new(0.0, -50.0, 0.0), 1.0)],
+		] {
+			let mut part = fire(2.0).with_bodies(bodies);
+			part.intersect(&sphere, 400 * EVALS_PER_STEP);
+			assert!(!part.is_captured());
+		}
+		let mut part = fire(2.0).with_schwarzschild_radius(0.5);
+		part.intersect(&sphere, 400 * EVALS_PER_STEP);
+		assert!(!part.is_captured());
+	}
+
+	#[test]
+	fn records_trajectory() {
+		let tex = plain_texture(BLACK);
+		let sphere = Sphere::new(1.0, tex);
+		let ray = Ray {
+			origin: Point::new(-10.0, 5.0, 0.0),
+			direction: Vector3::x_axis(),
+		};
+		let mut part = GRParticle::from_ray(&ray, 0.1);
+		part.intersect(&sphere, 10 * EVALS_PER_STEP);
+		assert_eq!(part.trajectory(), None);
+
+		let mut part = GRParticle::from_ray(&ray, 0.1).with_trajectory_recording();
+		let expected = GRParticle::from_ray(&ray, 0.1).trace_path(&sphere, 10 * EVALS_PER_STEP);
+		part.intersect(&sphere, 10 * EVALS_PER_STEP);
+		assert_eq!(part.trajectory(), Some(expected.as_slice()));
+	}
+
+	#[test]
+	fn counts_force_evaluations() {
+		let tex = plain_texture(BLACK);
+		let sphere = Sphere::new(1.0, tex);
+		let ray = Ray {
+			origin: Point::new(-10.0, 5.0, 0.0),
+			direction: Vector3::x_axis(),
+		};
+		let mut part = GRParticle::from_ray(&ray, 0.1);
+		assert_eq!(part.evals(), 0);
+		part.intersect(&sphere, 10 * EVALS_PER_STEP);
+		assert_eq!(part.evals(), 10 * EVALS_PER_STEP);
+		// Integrations carry on from where the previous one stopped
+		part.intersect(&sphere, 5 * EVALS_PER_STEP);
+		assert_eq!(part.evals(), 15 * EVALS_PER_STEP);
+	}
+
+	#[test]
+	fn adapts_timestep_to_potential() {
+		let tex = plain_texture(BLACK);
+		let sphere = Sphere::new(1.0, tex);
+		let ray = Ray {
+			origin: Point::new(-20.0, 3.0, 0.0),
+			direction: Vector3::x_axis(),
+		};
+		let path = GRParticle::from_ray(&ray, 0.5)
+			.with_tolerance(0.1)
+			.trace_path(&sphere, 400 * EVALS_PER_STEP);
+		assert!(path.len() <= 401);
+
+		let steps: Vec<_> = path.windows(2).map(|s| (s[1] - s[0]).norm()).collect();
+		let closest = path
+			.iter()
+			.enumerate()
+			.min_by(|a, b| a.1.coords.norm().partial_cmp(&b.1.coords.norm()).unwrap())
+			.unwrap()
+			.0;
+		// Steps shrink when approaching the black hole, and grow again afterwards
+		assert!(steps[closest - 1] < steps[0]);
+		assert!(steps[steps.len() - 1] > steps[closest - 1]);
+	}
+
+	#[test]
+	fn default_ring_starts_at_isco() {
+		let camera = Camera::new(1, 1, 30.0);
+		let mut scene = test_scene(camera, 2.0, (1.0, 2.0), plain_texture(BLACK));
+		// Whatever the radius of the sphere
+		assert_eq!(scene.default_ring().radius, (3.0, 6.0));
+
+		scene.bodies = vec![(Point::origin(), 2.0)];
+		let ring = scene.default_ring();
+		assert_eq!(ring.radius, (6.0, 12.0));
+		// The photon sphere is well inside the ISCO
+		assert!(ring.radius.0 > photon_sphere_radius(scene.schwarzschild_radius()));
+	}
+
+	#[test]
+	fn draws_shadow_circle() {
+		let camera = Camera::new(64, 64, 60.0);
+		let mut scene = test_scene(camera, 1.0, (2.0, 3.0), plain_texture(BLACK));
+		scene.set_camera(Some(Translation3::new(0.0, 0.0, 10.0)), None, None);
+		let mut img = DynamicImage::new_rgb8(64, 64);
+		scene.draw_shadow_circle(&mut img);
+		let buf = img.as_rgba8().unwrap();
+
+		// The shadow radius is 2.6 at a distance of 10, ie. 0.45 of the half-height at 60° fov
+		let radius = 32.0 * 2.6 / 10.0 / 30f64.to_radians().tan();
+		let edge = (32.0 + radius).round() as u32;
+		assert!((edge - 1..=edge + 1).any(|x| buf.get_pixel(x, 32)[1] == 255));
+		assert_eq!(*buf.get_pixel(32, 32), Rgba([0, 0, 0, 255]));
+	}
 
 	#[test]
 	fn can_render_schwardzchild() {
@@ -142,19 +1520,15 @@ mod tests {
 				Rgb::from_channels(255, 255, 255, 0)
 			};
 		}
-		let scene = GRScene(
-			Scene {
-				camera: Camera::new(30, 30, 10.0),
-				sphere: Sphere {
-					pos: Point::new(0.0, 0.0, -4.0),
-					radius: 1.0,
-					texture: Texture(img, TextureFiltering::Nearest, TextureMode::Clamp),
-				},
-				bgtex: None,
-			},
-			1.0,
-			10,
-		);
+		let tex = Texture::new(img, TextureFiltering::Nearest, TextureMode::Clamp);
+		let mut scene = GRScene {
+			dt: 1.0,
+			max_evals: 10,
+			tolerance: GRScene::DEFAULT_TOLERANCE,
+			..test_scene(Camera::new(30, 30, 10.0), 1.0, (2.0, 3.0), tex)
+		};
+		scene.scene.sphere.pos = Point::new(0.0, 0.0, -4.0);
+		scene.scene.ring.pos = Point::new(0.0, 0.0, -4.0);
 		render(
 			scene,
 			Some(&|p, msg| print!("[{}%] {}           \r", (1000.0 * p).round() / 10.0, msg)),
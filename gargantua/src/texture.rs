@@ -1,52 +1,424 @@
 use color::consts::SYSTEM_SRGB;
-use color::Color;
-use image::{open, DynamicImage, GenericImageView, Pixel};
+use color::{alpha_composite, Color};
+use image::hdr::HDRDecoder;
+use image::{
+	open, DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageError, Luma, Pixel, Rgb,
+	Rgba, RgbaImage,
+};
 use nalgebra::Vector2;
 
-use crate::utils::rgba_to_color;
+use crate::raytrace::TexCoords;
+use crate::utils::{rgba_to_color, SplitMix64};
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextureMode {
 	Clamp,
 	Repeat,
 	Transparent,
+	/// Repeats the texture, flipping every other tile so edges always meet their own texels
+	Mirror,
 }
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextureFiltering {
 	Nearest,
 	Bilinear,
+	/// Catmull-Rom spline over the 4x4 nearest texels, sharper than bilinear
+	Bicubic,
 }
 
+/// Pixel rectangle of the underlying image a texture samples from, as (x, y, width, height)
+type Bounds = (u32, u32, u32, u32);
+
+/// Texture sampled from an image. High dynamic range textures keep their linear texels next to
+/// a tone mapped copy of the image, which is used wherever 8-bit texels are needed.
 #[derive(Clone)]
-pub struct Texture(pub DynamicImage, pub TextureFiltering, pub TextureMode);
+pub struct Texture(
+	pub Arc<DynamicImage>,
+	pub TextureFiltering,
+	pub TextureMode,
+	Bounds,
+	Option<Arc<HdrImage>>,
+);
+
+/// Texels of a high dynamic range image, in linear light
+struct HdrImage {
+	width: u32,
+	pixels: Vec<Rgb<f32>>,
+}
+
+/// Texture along with its mip chain: versions of itself of half the resolution of the previous
+/// one, down to 1x1, to sample it without aliasing when many texels fall into a single pixel
+#[derive(Clone)]
+pub struct MipmappedTexture {
+	levels: Vec<Texture>,
+}
+
+/// Location of a packed image inside a `TextureAtlas`, in atlas UV space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+	pub uv_min: TexCoords,
+	pub uv_max: TexCoords,
+}
+
+/// Single image holding many small textures, packed with a guillotine packer
+pub struct TextureAtlas {
+	image: Arc<DynamicImage>,
+	free: Vec<Bounds>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureError {
+	DimensionMismatch {
+		expected: (u32, u32),
+		found: (u32, u32),
+	},
+}
+
+#[derive(Debug)]
+pub enum TextureLoadError {
+	IoError(io::Error),
+	DecodeError(String),
+	UnsupportedFormat(String),
+}
 
 impl Texture {
+	pub fn new(img: DynamicImage, filtering: TextureFiltering, mode: TextureMode) -> Self {
+		let (w, h) = img.dimensions();
+		Texture(Arc::new(img), filtering, mode, (0, 0, w, h), None)
+	}
+
+	/// Procedural texture, rasterizing `f` at the center of every texel. `f` is given the UV
+	/// coordinates, in 0..1, of the texel.
+	pub fn from_fn(
+		width: u32,
+		height: u32,
+		f: impl Fn(f64, f64) -> Color,
+		filtering: TextureFiltering,
+		mode: TextureMode,
+	) -> Self {
+		let mut img = DynamicImage::new_rgba8(width, height);
+		for (x, y, p) in img.as_mut_rgba8().unwrap().enumerate_pixels_mut() {
+			let u = (x as f64 + 0.5) / width as f64;
+			let v = (y as f64 + 0.5) / height as f64;
+			*p = f(u, v).into();
+		}
+
+		Texture::new(img, filtering, mode)
+	}
+
+	/// Background of isolated stars on black, repeating horizontally. The number of stars follows
+	/// a Poisson distribution of mean `density * width * height`, and their brightness a power
+	/// law, so that most are faint and a few bright. Each star is spread over the pixels around
+	/// it by a Gaussian of 1 pixel. The same seed always gives the same texture. The brightest
+	/// stars exceed white, so it is an HDR texture, clipped in its tone mapped copy.
+	pub fn generate_star_field(width: u32, height: u32, density: f64, seed: u64) -> Texture {
+		// Faintest star, exponent of the power law, and brightest star
+		const MIN_FLUX: f64 = 0.05;
+		const POWER_LAW: f64 = 1.5;
+		const MAX_FLUX: f64 = 4.0;
+		const SIGMA: f64 = 0.5;
+
+		let mut rng = SplitMix64(seed);
+		let mut flux = vec![0.0; (width * height) as usize];
+		let expected = density.max(0.0) * f64::from(width) * f64::from(height);
+		// Counting the arrivals of a Poisson process of unit rate over `expected` gives a Poisson
+		// distributed number of stars
+		let mut arrival = -(1.0 - rng.next_f64()).ln();
+		while arrival < expected {
+			let sx = rng.next_f64() * f64::from(width);
+			let sy = rng.next_f64() * f64::from(height);
+			let star = (MIN_FLUX * (1.0 - rng.next_f64()).powf(-1.0 / POWER_LAW)).min(MAX_FLUX);
+
+			let (cx, cy) = (sx.floor() as i64, sy.floor() as i64);
+			let mut footprint = [[0.0; 3]; 3];
+			let mut total = 0.0;
+			for (j, row) in footprint.iter_mut().enumerate() {
+				for (i, weight) in row.iter_mut().enumerate() {
+					let dx = (cx + i as i64 - 1) as f64 + 0.5 - sx;
+					let dy = (cy + j as i64 - 1) as f64 + 0.5 - sy;
+					*weight = (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp();
+					total += *weight;
+				}
+			}
+			for (j, row) in footprint.iter().enumerate() {
+				let y = cy + j as i64 - 1;
+				if y < 0 || y >= i64::from(height) {
+					continue;
+				}
+				for (i, weight) in row.iter().enumerate() {
+					let x = (cx + i as i64 - 1).rem_euclid(i64::from(width));
+					flux[(y * i64::from(width) + x) as usize] += star * weight / total;
+				}
+			}
+
+			arrival -= (1.0 - rng.next_f64()).ln();
+		}
+
+		let hdr = HdrImage::from_fn(width, height, |x, y| {
+			let v = flux[(y * width + x) as usize];
+			Color::new(v, v, v)
+		});
+		Texture::from_hdr(hdr, TextureFiltering::Bilinear, TextureMode::Repeat)
+	}
+
+	/// Texture sampling only the given region of a shared atlas
+	pub fn from_atlas_region(
+		atlas: Arc<TextureAtlas>,
+		region: AtlasRegion,
+		filtering: TextureFiltering,
+		mode: TextureMode,
+	) -> Self {
+		let (w, h) = atlas.image.dimensions();
+		let x = (region.uv_min[0] * w as f64).round() as u32;
+		let y = (region.uv_min[1] * h as f64).round() as u32;
+		let x_max = (region.uv_max[0] * w as f64).round() as u32;
+		let y_max = (region.uv_max[1] * h as f64).round() as u32;
+		Texture(
+			atlas.image.clone(),
+			filtering,
+			mode,
+			(x, y, x_max - x, y_max - y),
+			None,
+		)
+	}
+
 	pub fn load(
 		filepath: &str,
 		filtering: TextureFiltering,
 		mode: TextureMode,
-	) -> image::ImageResult<Self> {
-		return open(filepath).map(|b| Texture(b, filtering, mode));
+	) -> Result<Self, TextureLoadError> {
+		open(filepath)
+			.map(|b| Texture::new(b, filtering, mode))
+			.map_err(TextureLoadError::from)
+	}
+
+	/// Loads a Radiance RGBE (`.hdr`) image, such as a panorama of the sky for the background.
+	/// Its texels are kept in linear light, and sampled sRGB-encoded like LDR ones but without
+	/// clamping. The texture repeats, so that sampling wraps around the panorama.
+	pub fn load_hdr(filepath: &str, filtering: TextureFiltering) -> Result<Self, ImageError> {
+		let decoder = HDRDecoder::new(BufReader::new(File::open(filepath)?))?;
+		let meta = decoder.metadata();
+		let hdr = HdrImage {
+			width: meta.width,
+			pixels: decoder.read_image_hdr()?,
+		};
+
+		Ok(Texture::from_hdr(hdr, filtering, TextureMode::Repeat))
+	}
+
+	/// HDR texture, along with its tone mapped copy
+	fn from_hdr(hdr: HdrImage, filtering: TextureFiltering, mode: TextureMode) -> Self {
+		let (w, h) = (hdr.width, hdr.pixels.len() as u32 / hdr.width.max(1));
+		let ldr = RgbaImage::from_fn(w, h, |x, y| hdr.texel(x, y).to_unit_range().into());
+
+		Texture(
+			Arc::new(DynamicImage::ImageRgba8(ldr)),
+			filtering,
+			mode,
+			(0, 0, w, h),
+			Some(Arc::new(hdr)),
+		)
+	}
+
+	/// Resamples the texture to the given size, sampling it with `filter` at the center of every
+	/// new texel. HDR textures stay in high dynamic range. The result keeps the filtering and
+	/// mode of this texture.
+	pub fn resize(&self, new_width: u32, new_height: u32, filter: TextureFiltering) -> Texture {
+		let (w, h) = self.dimensions();
+		// Bilinear and bicubic filtering put the centers of texels on integer coordinates
+		let offset = match filter {
+			TextureFiltering::Nearest => 0.0,
+			_ => 0.5,
+		};
+		let sampler = Texture(
+			self.0.clone(),
+			filter,
+			self.2.clone(),
+			self.3,
+			self.4.clone(),
+		);
+		let sample = |x: u32, y: u32| {
+			let sx = ((x as f64 + 0.5) * w as f64 / new_width as f64 - offset).max(0.0);
+			let sy = ((y as f64 + 0.5) * h as f64 / new_height as f64 - offset).max(0.0);
+			sampler.uv(Vector2::new(sx / w as f64, sy / h as f64))
+		};
+
+		match self.4 {
+			Some(_) => {
+				let hdr = HdrImage::from_fn(new_width, new_height, sample);
+				Texture::from_hdr(hdr, self.1.clone(), self.2.clone())
+			}
+			None => Texture::new(
+				DynamicImage::ImageRgba8(RgbaImage::from_fn(new_width, new_height, |x, y| {
+					sample(x, y).into()
+				})),
+				self.1.clone(),
+				self.2.clone(),
+			),
+		}
+	}
+
+	/// Combines four grayscale textures of the same size into a single RGBA texture. Textures
+	/// with colors are converted to grayscale first. The filtering and mode are taken from `r`.
+	pub fn from_channels(
+		r: &Texture,
+		g: &Texture,
+		b: &Texture,
+		a: &Texture,
+	) -> Result<Texture, TextureError> {
+		let (w, h) = r.dimensions();
+		for tex in &[g, b, a] {
+			if tex.dimensions() != (w, h) {
+				return Err(TextureError::DimensionMismatch {
+					expected: (w, h),
+					found: tex.dimensions(),
+				});
+			}
+		}
+
+		let luma = |tex: &Texture, x: u32, y: u32| tex.raw_pixel(x, y).to_luma()[0];
+		let img = RgbaImage::from_fn(w, h, |x, y| {
+			Rgba([luma(r, x, y), luma(g, x, y), luma(b, x, y), luma(a, x, y)])
+		});
+		Ok(Texture::new(
+			DynamicImage::ImageRgba8(img),
+			r.1.clone(),
+			r.2.clone(),
+		))
+	}
+
+	/// Size of the texture, in pixels
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.3 .2, self.3 .3)
+	}
+
+	/// Red channel, as a grayscale texture
+	pub fn channel_r(&self) -> Texture {
+		self.channel(0)
+	}
+
+	/// Green channel, as a grayscale texture
+	pub fn channel_g(&self) -> Texture {
+		self.channel(1)
+	}
+
+	/// Blue channel, as a grayscale texture
+	pub fn channel_b(&self) -> Texture {
+		self.channel(2)
+	}
+
+	/// Alpha channel, as a grayscale texture
+	pub fn channel_a(&self) -> Texture {
+		self.channel(3)
+	}
+
+	fn channel(&self, c: usize) -> Texture {
+		let (w, h) = self.dimensions();
+		let img = ImageBuffer::from_fn(w, h, |x, y| Luma([self.raw_pixel(x, y)[c]]));
+		Texture::new(
+			DynamicImage::ImageLuma8(img),
+			self.1.clone(),
+			self.2.clone(),
+		)
+	}
+
+	/// Precomputes the mip chain of the texture, box filtering in linear light. Every level is
+	/// sampled with bilinear filtering and the texture mode of this texture.
+	pub fn build_mipmaps(&self) -> MipmappedTexture {
+		let mut levels = vec![Texture(
+			self.0.clone(),
+			TextureFiltering::Bilinear,
+			self.2.clone(),
+			self.3,
+			self.4.clone(),
+		)];
+		let (mut w, mut h) = self.dimensions();
+		while w > 1 || h > 1 {
+			let prev = levels.last().unwrap();
+			let (next_w, next_h) = ((w / 2).max(1), (h / 2).max(1));
+			// Odd sizes repeat their last row or column in the last texel
+			let texel = |x: u32, y: u32| rgba_to_color(prev.raw_pixel(x.min(w - 1), y.min(h - 1)));
+			let img = RgbaImage::from_fn(next_w, next_h, |x, y| {
+				let (x, y) = (2 * x, 2 * y);
+				let sum = texel(x, y) + texel(x + 1, y) + texel(x, y + 1) + texel(x + 1, y + 1);
+				(sum / 4.0).into()
+			});
+			levels.push(Texture::new(
+				DynamicImage::ImageRgba8(img),
+				TextureFiltering::Bilinear,
+				self.2.clone(),
+			));
+			w = next_w;
+			h = next_h;
+		}
+
+		MipmappedTexture { levels }
+	}
+
+	/// Pixel of the texture's own region of the underlying image, without any texture mode
+	fn raw_pixel(&self, x: u32, y: u32) -> Rgba<u8> {
+		self.0.get_pixel(self.3 .0 + x, self.3 .1 + y)
+	}
+
+	/// Like `raw_pixel`, as a color, without clamping HDR texels
+	fn region_texel(&self, x: u32, y: u32) -> Color {
+		self.texel(self.3 .0 + x, self.3 .1 + y)
 	}
 
 	pub fn uv(&self, uv: Vector2<f64>) -> Color {
-		let (w, h) = self.0.dimensions();
+		let (_, _, w, h) = self.3;
 		let (x, y) = (uv[0] * w as f64, uv[1] * h as f64);
 
 		return match self.1 {
-			TextureFiltering::Bilinear => self.bilinear(x, y),
-			TextureFiltering::Nearest => self.get_pixel(x.round() as u32, y.round() as u32),
+			TextureFiltering::Bilinear => self.bilinear(x, y, false),
+			TextureFiltering::Bicubic => self.catmull_rom(x, y),
+			// Texel `i` covers [i, i + 1), so UVs of exactly 1.0 land on the texture's edge and are
+			// resolved by the texture mode like any other out-of-range coordinate.
+			TextureFiltering::Nearest => self.get_pixel(x.floor() as u32, y.floor() as u32),
 		};
 	}
 
+	/// Samples like `uv`, but when `seam` is set, bilinear filtering wraps around horizontally
+	/// whatever the texture mode, blending texels from both edges of a periodic parameterisation
+	/// instead of clamping at the seam.
+	pub fn uv_seamless(&self, uv: Vector2<f64>, seam: bool) -> Color {
+		match self.1 {
+			TextureFiltering::Bilinear if seam => {
+				let (w, h) = self.dimensions();
+				let x = (uv[0] * w as f64).rem_euclid(w as f64);
+				self.bilinear(x, uv[1] * h as f64, true)
+			}
+			_ => self.uv(uv),
+		}
+	}
+
 	pub fn get_pixel(&self, x: u32, y: u32) -> Color {
-		let (width, height) = self.0.dimensions();
+		let (left, top, width, height) = self.3;
 		return match self.2 {
-			TextureMode::Clamp => rgba_to_color(
-				self.0
-					.get_pixel(clamp(x, 0, width - 1), clamp(y, 0, height - 1)),
-			),
-			TextureMode::Repeat => rgba_to_color(self.0.get_pixel(x % width, y % height)),
+			TextureMode::Clamp => {
+				self.texel(left + clamp(x, 0, width - 1), top + clamp(y, 0, height - 1))
+			}
+			TextureMode::Repeat => self.texel(left + x % width, top + y % height),
+			TextureMode::Mirror => {
+				// Even tiles are as-is, odd ones flipped
+				let mirror = |x: u32, size: u32| {
+					let x = x % (2 * size);
+					if x < size {
+						x
+					} else {
+						2 * size - 1 - x
+					}
+				};
+				self.texel(left + mirror(x, width), top + mirror(y, height))
+			}
 			TextureMode::Transparent => {
 				let mut col = Color::default().into_with_system(SYSTEM_SRGB);
 				col.alpha = 0.0;
@@ -55,22 +427,420 @@ impl Texture {
 		};
 	}
 
-	fn bilinear(&self, x: f64, y: f64) -> Color {
+	/// Texel of the underlying image, sRGB-encoded. HDR texels are encoded too, without clamping.
+	fn texel(&self, x: u32, y: u32) -> Color {
+		match &self.4 {
+			Some(hdr) => hdr.texel(x, y),
+			None => rgba_to_color(self.0.get_pixel(x, y)),
+		}
+	}
+
+	/// Pixel decoded to linear light
+	fn linear_pixel(&self, x: u32, y: u32) -> Color {
+		self.decode(&self.get_pixel(x, y))
+	}
+
+	/// Decodes a pixel of the texture to linear light
+	fn decode(&self, col: &Color) -> Color {
+		SYSTEM_SRGB.gamma_inv(col)
+	}
+
+	/// Inverse of `decode`, encoding a color sampled in linear light
+	fn encode(&self, col: &Color) -> Color {
+		SYSTEM_SRGB.gamma(col)
+	}
+
+	/// Grayscale version of the texture, with the relative luminance of every texel in all three
+	/// color channels. Alpha is kept.
+	pub fn to_grayscale(&self) -> Texture {
+		let (w, h) = self.dimensions();
+		let gray = |x, y| {
+			let px = self.region_texel(x, y);
+			let y = px.to_grayscale();
+			let mut col = Color::new(y, y, y);
+			col.alpha = px.alpha;
+			col
+		};
+		match self.4 {
+			Some(_) => Texture::from_hdr(
+				HdrImage::from_fn(w, h, gray),
+				self.1.clone(),
+				self.2.clone(),
+			),
+			None => Texture::new(
+				DynamicImage::ImageRgba8(RgbaImage::from_fn(w, h, |x, y| {
+					SYSTEM_SRGB.gamma(&gray(x, y)).into()
+				})),
+				self.1.clone(),
+				self.2.clone(),
+			),
+		}
+	}
+
+	/// Mean color of all the texels, averaged and returned in linear light
+	pub fn average_color(&self) -> Color {
+		let (w, h) = self.dimensions();
+		let mut sum = [0.0; 4];
+		for y in 0..h {
+			for x in 0..w {
+				let px = self.decode(&self.region_texel(x, y));
+				sum[0] += px.red;
+				sum[1] += px.green;
+				sum[2] += px.blue;
+				sum[3] += px.alpha;
+			}
+		}
+		let n = f64::from((w * h).max(1));
+		let mut col = Color::new(sum[0] / n, sum[1] / n, sum[2] / n);
+		col.alpha = sum[3] / n;
+
+		col
+	}
+
+	/// Histogram of the relative luminance of the texels, in linear light, over `buckets` equal
+	/// bins spanning 0..1. The bins are normalized to sum to 1.
+	pub fn histogram(&self, buckets: usize) -> Vec<f64> {
+		let mut bins = vec![0.0; buckets];
+		if buckets == 0 {
+			return bins;
+		}
+		let (w, h) = self.dimensions();
+		let weight = 1.0 / f64::from((w * h).max(1));
+		for y in 0..h {
+			for x in 0..w {
+				let luma = self.region_texel(x, y).to_grayscale();
+				let i = (luma * buckets as f64).max(0.0) as usize;
+				bins[i.min(buckets - 1)] += weight;
+			}
+		}
+
+		bins
+	}
+
+	/// Like `get_pixel`, for coordinates which may lie before the texture's origin
+	fn get_pixel_signed(&self, x: i64, y: i64) -> Color {
+		let (w, h) = self.dimensions();
+		match self.2 {
+			TextureMode::Repeat => self.get_pixel(
+				x.rem_euclid(i64::from(w)) as u32,
+				y.rem_euclid(i64::from(h)) as u32,
+			),
+			// Mirroring has a period of two tiles
+			TextureMode::Mirror => self.get_pixel(
+				x.rem_euclid(2 * i64::from(w)) as u32,
+				y.rem_euclid(2 * i64::from(h)) as u32,
+			),
+			_ => self.get_pixel(x.max(0) as u32, y.max(0) as u32),
+		}
+	}
+
+	fn catmull_rom(&self, x: f64, y: f64) -> Color {
+		let (cx, cy) = (x.floor(), y.floor());
+		let (wx, wy) = (catmull_rom_weights(x - cx), catmull_rom_weights(y - cy));
+		let (x0, y0) = (cx as i64 - 1, cy as i64 - 1);
+
+		// Interpolate in linear light, like `bilinear`
+		let mut col = self.decode(&self.get_pixel_signed(x0, y0));
+		let mut sum = [0.0; 4];
+		for (j, wy) in wy.iter().enumerate() {
+			for (i, wx) in wx.iter().enumerate() {
+				let px = self.decode(&self.get_pixel_signed(x0 + i as i64, y0 + j as i64));
+				let w = wx * wy;
+				sum[0] += w * px.red;
+				sum[1] += w * px.green;
+				sum[2] += w * px.blue;
+				sum[3] += w * px.alpha;
+			}
+		}
+		col.red = sum[0];
+		col.green = sum[1];
+		col.blue = sum[2];
+		col.alpha = sum[3];
+
+		// The spline overshoots around sharp edges, HDR texels have no upper bound
+		match self.4 {
+			Some(_) => SYSTEM_SRGB.gamma(&col.clamp_channels(0.0, f64::INFINITY)),
+			None => SYSTEM_SRGB.gamma(&col.to_unit_range()),
+		}
+	}
+
+	fn bilinear(&self, x: f64, y: f64, wrap_x: bool) -> Color {
 		let cx = x.floor();
 		let cy = y.floor();
 		let fx = x - cx;
 		let fy = y - cy;
+		let (x0, y0) = (cx as u32, cy as u32);
+		let x1 = if wrap_x {
+			(x0 + 1) % self.dimensions().0
+		} else {
+			x0 + 1
+		};
 
-		let pix11 = self.get_pixel(cx as u32, cy as u32);
-		let pix12 = self.get_pixel(cx as u32 + 1, cy as u32);
-		let pix21 = self.get_pixel(cx as u32 + 1, cy as u32);
-		let pix22 = self.get_pixel(cx as u32 + 1, cy as u32 + 1);
+		// Texture pixels are sRGB-encoded: interpolate in linear light and encode only once
+		let pix11 = self.linear_pixel(x0, y0);
+		let pix12 = self.linear_pixel(x1, y0);
+		let pix21 = self.linear_pixel(x0, y0 + 1);
+		let pix22 = self.linear_pixel(x1, y0 + 1);
 		let col_top = Color::mix(fx, pix11, pix12).unwrap();
 		let col_bottom = Color::mix(fx, pix21, pix22).unwrap();
-		return Color::mix(fy, col_top, col_bottom).unwrap();
+		return self.encode(&Color::mix(fy, col_top, col_bottom).unwrap());
+	}
+}
+
+impl MipmappedTexture {
+	/// Number of levels in the chain, including the full resolution one
+	pub fn num_levels(&self) -> usize {
+		self.levels.len()
+	}
+
+	/// Samples the texture at the level of detail `lod`, 0 being the full resolution and each
+	/// unit halving it. Fractional levels blend the two nearest ones.
+	pub fn uv_lod(&self, uv: TexCoords, lod: f64) -> Color {
+		self.uv_lod_seamless(uv, lod, false)
+	}
+
+	/// Samples like `uv_lod`, wrapping around horizontally near a seam, see
+	/// `Texture::uv_seamless`.
+	pub fn uv_lod_seamless(&self, uv: TexCoords, lod: f64, seam: bool) -> Color {
+		let lod = clamp(lod, 0.0, (self.levels.len() - 1) as f64);
+		let level = lod.floor() as usize;
+		let fine = self.levels[level].uv_seamless(uv, seam);
+		if level + 1 == self.levels.len() {
+			return fine;
+		}
+		let coarse = self.levels[level + 1].uv_seamless(uv, seam);
+
+		// Blend in linear light, like bilinear filtering within a level
+		let t = lod - level as f64;
+		let mixed = Color::mix(
+			t,
+			SYSTEM_SRGB.gamma_inv(&fine),
+			SYSTEM_SRGB.gamma_inv(&coarse),
+		)
+		.unwrap();
+		SYSTEM_SRGB.gamma(&mixed)
+	}
+}
+
+impl TextureAtlas {
+	pub fn new(width: u32, height: u32) -> Self {
+		TextureAtlas {
+			image: Arc::new(DynamicImage::new_rgba8(width, height)),
+			free: vec![(0, 0, width, height)],
+		}
+	}
+
+	/// Packs the image into the atlas and returns where it was placed.
+	///
+	/// Panics if there is no free space left large enough for the image.
+	pub fn add(&mut self, img: DynamicImage) -> AtlasRegion {
+		let (w, h) = img.dimensions();
+		// Best short side fit: pick the free rectangle leaving the thinnest leftover strip
+		let (idx, &(x, y, fw, fh)) = self
+			.free
+			.iter()
+			.enumerate()
+			.filter(|(_, &(_, _, fw, fh))| fw >= w && fh >= h)
+			.min_by_key(|(_, &(_, _, fw, fh))| (fw - w).min(fh - h))
+			.expect("Texture atlas is full");
+		self.free.swap_remove(idx);
+
+		// Guillotine cut along the shorter leftover axis, keeping the larger rectangle whole
+		let (right, bottom) = if fw - w < fh - h {
+			((x + w, y, fw - w, h), (x, y + h, fw, fh - h))
+		} else {
+			((x + w, y, fw - w, fh), (x, y + h, w, fh - h))
+		};
+		self.free.extend(
+			[right, bottom]
+				.iter()
+				.filter(|&&(_, _, w, h)| w > 0 && h > 0),
+		);
+
+		// Textures created from the atlas keep their own copy of the pixels added before them
+		Arc::make_mut(&mut self.image).copy_from(&img, x, y);
+		let (aw, ah) = self.image.dimensions();
+		AtlasRegion {
+			uv_min: TexCoords::new(x as f64 / aw as f64, y as f64 / ah as f64),
+			uv_max: TexCoords::new((x + w) as f64 / aw as f64, (y + h) as f64 / ah as f64),
+		}
+	}
+}
+
+impl fmt::Display for TextureError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TextureError::DimensionMismatch { expected, found } => write!(
+				f,
+				"Texture size mismatch: expected {}x{}, found {}x{}",
+				expected.0, expected.1, found.0, found.1
+			),
+		}
+	}
+}
+
+impl Error for TextureError {}
+
+impl fmt::Display for TextureLoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TextureLoadError::IoError(err) => write!(f, "Couldn't read texture: {}", err),
+			TextureLoadError::DecodeError(msg) => write!(f, "Couldn't decode texture: {}", msg),
+			TextureLoadError::UnsupportedFormat(msg) => {
+				write!(f, "Unsupported texture format: {}", msg)
+			}
+		}
 	}
 }
 
+impl Error for TextureLoadError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			TextureLoadError::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for TextureLoadError {
+	fn from(err: io::Error) -> Self {
+		TextureLoadError::IoError(err)
+	}
+}
+
+impl From<ImageError> for TextureLoadError {
+	fn from(err: ImageError) -> Self {
+		match err {
+			ImageError::IoError(err) => TextureLoadError::IoError(err),
+			ImageError::UnsupportedError(msg) => TextureLoadError::UnsupportedFormat(msg),
+			ImageError::UnsupportedColor(color) => {
+				TextureLoadError::UnsupportedFormat(format!("{:?}", color))
+			}
+			err => TextureLoadError::DecodeError(err.to_string()),
+		}
+	}
+}
+
+/// Serialized form of a texture: its image, either loaded from a file or embedded as a
+/// base64-encoded PNG, and its sampling settings
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TextureDesc {
+	image: TextureImage,
+	filtering: TextureFiltering,
+	mode: TextureMode,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum TextureImage {
+	Path { path: String },
+	Png(String),
+}
+
+/// Textures serialize their image, or the region of the atlas they sample, as an embedded PNG
+#[cfg(feature = "serde")]
+impl serde::Serialize for Texture {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let (x, y, w, h) = self.3;
+		let mut png = vec![];
+		(*self.0)
+			.clone()
+			.crop(x, y, w, h)
+			.write_to(&mut png, image::ImageOutputFormat::PNG)
+			.map_err(serde::ser::Error::custom)?;
+
+		TextureDesc {
+			image: TextureImage::Png(base64::encode(&png)),
+			filtering: self.1.clone(),
+			mode: self.2.clone(),
+		}
+		.serialize(serializer)
+	}
+}
+
+/// Textures deserialize from an embedded PNG, or from `{ "path": "..." }` to load an image file
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Texture {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		use serde::de::Error;
+
+		let desc = TextureDesc::deserialize(deserializer)?;
+		match desc.image {
+			TextureImage::Path { path } => Texture::load(&path, desc.filtering, desc.mode)
+				.map_err(|err| D::Error::custom(format!("{}: {}", path, err))),
+			TextureImage::Png(data) => {
+				let bytes = base64::decode(&data).map_err(D::Error::custom)?;
+				let img = image::load_from_memory(&bytes).map_err(D::Error::custom)?;
+				Ok(Texture::new(img, desc.filtering, desc.mode))
+			}
+		}
+	}
+}
+
+/// Composites `over` on top of `under`, texel by texel, with the Porter-Duff "over" operator in
+/// linear light. Both textures must have the same size, and either both or neither be HDR. The
+/// result takes the filtering and mode of `under`.
+pub fn composite_textures(over: &Texture, under: &Texture) -> Result<Texture, &'static str> {
+	let (w, h) = under.dimensions();
+	if over.dimensions() != (w, h) {
+		return Err("Cannot composite textures of different dimensions");
+	} else if over.4.is_some() != under.4.is_some() {
+		return Err("Cannot composite HDR and LDR textures");
+	}
+
+	let texel = |x, y| alpha_composite(&over.region_texel(x, y), &under.region_texel(x, y));
+	Ok(match under.4 {
+		Some(_) => Texture::from_hdr(
+			HdrImage::from_fn(w, h, texel),
+			under.1.clone(),
+			under.2.clone(),
+		),
+		None => Texture::new(
+			DynamicImage::ImageRgba8(RgbaImage::from_fn(w, h, |x, y| texel(x, y).into())),
+			under.1.clone(),
+			under.2.clone(),
+		),
+	})
+}
+
+impl HdrImage {
+	/// Image of the colors computed for every texel, stored in linear light. Colors without a
+	/// color system are taken as linear already.
+	fn from_fn(width: u32, height: u32, f: impl Fn(u32, u32) -> Color) -> Self {
+		let mut pixels = Vec::with_capacity((width * height) as usize);
+		for y in 0..height {
+			for x in 0..width {
+				let [r, g, b, _]: [f64; 4] = f(x, y).into();
+				pixels.push(Rgb([r as f32, g as f32, b as f32]));
+			}
+		}
+
+		HdrImage { width, pixels }
+	}
+
+	/// Texel encoded to sRGB and tagged as such, like the texels of LDR textures, so that it is
+	/// shaded and written out the same way
+	fn texel(&self, x: u32, y: u32) -> Color {
+		let Rgb([r, g, b]) = self.pixels[(y * self.width + x) as usize];
+		SYSTEM_SRGB
+			.gamma(&Color::new(r.into(), g.into(), b.into()))
+			.into_with_system(SYSTEM_SRGB)
+	}
+}
+
+/// Weights of the 4 texels around a sample, `t` being its offset from the second one
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+	let (t2, t3) = (t * t, t * t * t);
+	[
+		0.5 * (-t3 + 2.0 * t2 - t),
+		0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+		0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+		0.5 * (t3 - t2),
+	]
+}
+
 fn clamp<T: PartialOrd>(x: T, min: T, max: T) -> T {
 	if x < min {
 		return min;
@@ -79,3 +849,544 @@ fn clamp<T: PartialOrd>(x: T, min: T, max: T) -> T {
 	}
 	return x;
 }
+
+pub mod noise {
+	use super::{clamp, Texture, TextureFiltering, TextureMode};
+	use crate::utils::SplitMix64;
+
+	use color::consts::SYSTEM_SRGB;
+	use color::Color;
+
+	/// Greyscale texture of Perlin gradient noise, summed over `octaves` octaves of doubling
+	/// frequency and halving amplitude (fractal Brownian motion). `scale` is the number of noise
+	/// cells across the texture at the first octave. The same seed always gives the same texture.
+	pub fn perlin_texture(
+		width: u32,
+		height: u32,
+		scale: f64,
+		octaves: u32,
+		seed: u64,
+		filtering: TextureFiltering,
+		mode: TextureMode,
+	) -> Texture {
+		let perm = permutation(seed);
+		let octaves = octaves.max(1);
+		// Sum of the amplitudes, to keep the result in range whatever the number of octaves
+		let total = 2.0 - 0.5f64.powi(octaves as i32 - 1);
+
+		Texture::from_fn(
+			width,
+			height,
+			|u, v| {
+				let mut value = 0.0;
+				let (mut freq, mut amplitude) = (scale, 1.0);
+				for _ in 0..octaves {
+					value += amplitude * perlin(&perm, u * freq, v * freq);
+					freq *= 2.0;
+					amplitude *= 0.5;
+				}
+				let grey = clamp(0.5 + 0.5 * value / total, 0.0, 1.0);
+				Color::with_system(grey, grey, grey, SYSTEM_SRGB)
+			},
+			filtering,
+			mode,
+		)
+	}
+
+	/// Shuffled 0..256, repeated twice to avoid wrapping indices
+	fn permutation(seed: u64) -> Vec<usize> {
+		let mut rng = SplitMix64(seed);
+		let mut perm: Vec<usize> = (0..256).collect();
+		for i in (1..256).rev() {
+			perm.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
+		}
+		perm.extend_from_within(..);
+		perm
+	}
+
+	/// Noise value in about [-1, 1] at the point, 0 on the integer lattice
+	fn perlin(perm: &[usize], x: f64, y: f64) -> f64 {
+		let (x0, y0) = (x.floor(), y.floor());
+		let (xi, yi) = ((x0 as i64 & 255) as usize, (y0 as i64 & 255) as usize);
+		let (xf, yf) = (x - x0, y - y0);
+		let hash = |dx: usize, dy: usize| perm[perm[xi + dx] + yi + dy];
+
+		let (u, v) = (fade(xf), fade(yf));
+		let top = lerp(
+			u,
+			gradient(hash(0, 0), xf, yf),
+			gradient(hash(1, 0), xf - 1.0, yf),
+		);
+		let bottom = lerp(
+			u,
+			gradient(hash(0, 1), xf, yf - 1.0),
+			gradient(hash(1, 1), xf - 1.0, yf - 1.0),
+		);
+		lerp(v, top, bottom)
+	}
+
+	/// Dot product with one of 8 gradient directions picked by the hash
+	fn gradient(hash: usize, x: f64, y: f64) -> f64 {
+		match hash & 7 {
+			0 => x + y,
+			1 => -x + y,
+			2 => x - y,
+			3 => -x - y,
+			4 => x,
+			5 => -x,
+			6 => y,
+			_ => -y,
+		}
+	}
+
+	fn fade(t: f64) -> f64 {
+		t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+	}
+
+	fn lerp(t: f64, a: f64, b: f64) -> f64 {
+		a + t * (b - a)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::noise::perlin_texture;
+	use super::{
+		composite_textures, Texture, TextureAtlas, TextureError, TextureFiltering,
+		TextureLoadError, TextureMode,
+	};
+	use approx::assert_relative_eq;
+	use color::consts::SYSTEM_SRGB;
+	use color::Color;
+	use image::hdr::HDREncoder;
+	use image::{DynamicImage, Rgb, Rgba};
+	use nalgebra::Vector2;
+	use std::fs::File;
+	use std::sync::Arc;
+
+	fn quad_texture(mode: TextureMode) -> Texture {
+		let mut img = DynamicImage::new_rgba8(2, 2);
+		let buf = img.as_mut_rgba8().unwrap();
+		buf.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+		buf.put_pixel(1, 0, Rgba([0, 255, 0, 255]));
+		buf.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+		buf.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+		Texture::new(img, TextureFiltering::Nearest, mode)
+	}
+
+	#[test]
+	fn bilinear_mixes_in_linear_light() {
+		let mut img = DynamicImage::new_rgba8(2, 2);
+		let buf = img.as_mut_rgba8().unwrap();
+		buf.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+		buf.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+		buf.put_pixel(0, 1, Rgba([255, 255, 255, 255]));
+		buf.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+		let tex = Texture::new(img, TextureFiltering::Bilinear, TextureMode::Clamp);
+		let half_grey = SYSTEM_SRGB.gamma(&Color::new(0.5, 0.5, 0.5)).red;
+
+		let horizontal = tex.uv(Vector2::new(0.25, 0.0));
+		assert_relative_eq!(horizontal.red, half_grey, epsilon = 1e-9);
+		let vertical = tex.uv(Vector2::new(0.0, 0.25));
+		assert_relative_eq!(vertical.red, half_grey, epsilon = 1e-9);
+	}
+
+	#[test]
+	fn seamless_bilinear_wraps_around() {
+		let mut img = DynamicImage::new_rgba8(2, 1);
+		let buf = img.as_mut_rgba8().unwrap();
+		buf.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+		buf.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+		let tex = Texture::new(img, TextureFiltering::Bilinear, TextureMode::Clamp);
+
+		let uv = Vector2::new(0.99, 0.0);
+		assert_eq!(tex.uv_seamless(uv, false), tex.uv(uv));
+		assert_relative_eq!(tex.uv(uv).red, 1.0, epsilon = 1e-9);
+		let wrapped = tex.uv_seamless(uv, true);
+		let expected = SYSTEM_SRGB.gamma(&Color::new(0.02, 0.02, 0.02)).red;
+		assert_relative_eq!(wrapped.red, expected, epsilon = 1e-9);
+	}
+
+	#[test]
+	fn bicubic_interpolates_texels() {
+		let ramp = |mode| {
+			let mut img = DynamicImage::new_rgba8(4, 1);
+			let buf = img.as_mut_rgba8().unwrap();
+			for (x, v) in [0, 60, 120, 255].iter().enumerate() {
+				buf.put_pixel(x as u32, 0, Rgba([*v, *v, *v, 255]));
+			}
+			Texture::new(img, TextureFiltering::Bicubic, mode)
+		};
+		let clamped = ramp(TextureMode::Clamp);
+		let repeated = ramp(TextureMode::Repeat);
+
+		// The spline goes through the texels themselves
+		let texel = clamped.get_pixel(1, 0);
+		assert_relative_eq!(
+			clamped.uv(Vector2::new(0.25, 0.0)).red,
+			texel.red,
+			epsilon = 1e-9
+		);
+		let between = clamped.uv(Vector2::new(0.375, 0.0)).red;
+		assert!(between > texel.red && between < clamped.get_pixel(2, 0).red);
+
+		// The texture mode decides which texels lie past the edges: repeating brings in the bright
+		// last texel, weighted by the negative lobe of the spline
+		let uv = Vector2::new(0.1, 0.0);
+		assert!(repeated.uv(uv).red < clamped.uv(uv).red);
+		assert_eq!(ramp(TextureMode::Transparent).uv(uv).alpha, 0.0);
+	}
+
+	#[test]
+	fn nearest_handles_uv_at_one() {
+		let white = Color::from_u32(0xFFFFFFFF);
+		let red = Color::from_u32(0xFFFF0000);
+
+		let clamped = quad_texture(TextureMode::Clamp);
+		assert_eq!(clamped.uv(Vector2::new(1.0, 1.0)), white);
+		assert_eq!(clamped.uv(Vector2::new(0.99, 0.99)), white);
+
+		let repeated = quad_texture(TextureMode::Repeat);
+		assert_eq!(repeated.uv(Vector2::new(1.0, 1.0)), red);
+		assert_eq!(repeated.uv(Vector2::new(0.99, 0.99)), white);
+
+		let transparent = quad_texture(TextureMode::Transparent);
+		assert_eq!(transparent.uv(Vector2::new(1.0, 1.0)).alpha, 0.0);
+	}
+
+	#[test]
+	fn mirror_flips_alternate_tiles() {
+		let mirrored = quad_texture(TextureMode::Mirror);
+		for y in 0..4 {
+			assert_eq!(mirrored.get_pixel(1, y), mirrored.get_pixel(2, y));
+			assert_eq!(mirrored.get_pixel(3, y), mirrored.get_pixel(4, y));
+		}
+		assert_eq!(mirrored.get_pixel(2, 0), Color::from_u32(0xFF00FF00));
+		assert_eq!(mirrored.get_pixel(3, 0), Color::from_u32(0xFFFF0000));
+		assert_eq!(mirrored.get_pixel(0, 2), Color::from_u32(0xFF0000FF));
+		assert_eq!(mirrored.get_pixel(4, 4), mirrored.get_pixel(0, 0));
+	}
+
+	#[test]
+	fn rasterizes_procedural_textures() {
+		let gradient = Texture::from_fn(
+			4,
+			2,
+			|u, v| Color::new(u, v, 0.0).into_with_system(SYSTEM_SRGB),
+			TextureFiltering::Nearest,
+			TextureMode::Clamp,
+		);
+		assert_eq!(gradient.dimensions(), (4, 2));
+		let texel = gradient.get_pixel(1, 1);
+		assert_relative_eq!(texel.red, 0.375, epsilon = 1.0 / 255.0);
+		assert_relative_eq!(texel.green, 0.75, epsilon = 1.0 / 255.0);
+		assert_eq!(texel.alpha, 1.0);
+	}
+
+	#[test]
+	fn perlin_noise_is_seeded() {
+		let noise = |seed| {
+			perlin_texture(
+				32,
+				32,
+				4.0,
+				3,
+				seed,
+				TextureFiltering::Nearest,
+				TextureMode::Repeat,
+			)
+		};
+		let pixels = |tex: Texture| tex.0.raw_pixels();
+		let a = pixels(noise(1));
+		assert_eq!(a, pixels(noise(1)));
+		assert_ne!(a, pixels(noise(2)));
+
+		// Greyscale, and not flat
+		let texel = noise(1).get_pixel(5, 7);
+		assert_eq!(texel.red, texel.green);
+		assert_eq!(texel.red, texel.blue);
+		assert!(a.iter().any(|&c| c < 100) && a.iter().any(|&c| c > 155));
+	}
+
+	#[test]
+	fn builds_and_samples_mipmaps() {
+		let mut img = DynamicImage::new_rgba8(4, 2);
+		let buf = img.as_mut_rgba8().unwrap();
+		for (x, y, p) in buf.enumerate_pixels_mut() {
+			*p = if (x + y) % 2 == 0 {
+				Rgba([0, 0, 0, 255])
+			} else {
+				Rgba([255, 255, 255, 255])
+			};
+		}
+		let tex = Texture::new(img, TextureFiltering::Nearest, TextureMode::Repeat);
+		let mips = tex.build_mipmaps();
+		assert_eq!(mips.num_levels(), 3);
+		assert_eq!(mips.levels[1].dimensions(), (2, 1));
+		assert_eq!(mips.levels[2].dimensions(), (1, 1));
+
+		// The checkerboard averages out to half the light
+		let half_grey = SYSTEM_SRGB.gamma(&Color::new(0.5, 0.5, 0.5)).red;
+		let uv = Vector2::new(0.3, 0.6);
+		assert_relative_eq!(mips.uv_lod(uv, 1.0).red, half_grey, epsilon = 1e-2);
+		assert_relative_eq!(mips.uv_lod(uv, 10.0).red, half_grey, epsilon = 1e-2);
+		assert_eq!(mips.uv_lod(uv, -1.0), mips.levels[0].uv(uv));
+		let blended = mips.uv_lod(Vector2::new(0.0, 0.0), 0.5).red;
+		assert!(blended > 0.0 && blended < half_grey);
+	}
+
+	#[test]
+	fn atlas_regions_sample_their_own_image() {
+		let solid = |w, h, px| {
+			let mut img = DynamicImage::new_rgba8(w, h);
+			for p in img.as_mut_rgba8().unwrap().pixels_mut() {
+				*p = Rgba(px);
+			}
+			img
+		};
+		let mut atlas = TextureAtlas::new(8, 8);
+		let red = atlas.add(solid(4, 8, [255, 0, 0, 255]));
+		let green = atlas.add(solid(4, 4, [0, 255, 0, 255]));
+		let blue = atlas.add(solid(4, 4, [0, 0, 255, 255]));
+		assert_eq!(red.uv_min, Vector2::new(0.0, 0.0));
+		assert_eq!(red.uv_max, Vector2::new(0.5, 1.0));
+		assert_ne!(green.uv_min, blue.uv_min);
+
+		let atlas = Arc::new(atlas);
+		let texture = |region| {
+			Texture::from_atlas_region(
+				atlas.clone(),
+				region,
+				TextureFiltering::Nearest,
+				TextureMode::Repeat,
+			)
+		};
+		for uv in &[
+			Vector2::new(0.0, 0.0),
+			Vector2::new(0.99, 0.99),
+			Vector2::new(1.5, 0.5),
+		] {
+			assert_eq!(texture(red).uv(*uv), Color::from_u32(0xFFFF0000));
+			assert_eq!(texture(green).uv(*uv), Color::from_u32(0xFF00FF00));
+			assert_eq!(texture(blue).uv(*uv), Color::from_u32(0xFF0000FF));
+		}
+	}
+
+	#[test]
+	fn splits_and_recombines_channels() {
+		let mut img = DynamicImage::new_rgba8(2, 2);
+		let buf = img.as_mut_rgba8().unwrap();
+		buf.put_pixel(0, 0, Rgba([10, 20, 30, 40]));
+		buf.put_pixel(1, 0, Rgba([50, 60, 70, 80]));
+		buf.put_pixel(0, 1, Rgba([90, 100, 110, 120]));
+		buf.put_pixel(1, 1, Rgba([130, 140, 150, 160]));
+		let tex = Texture::new(img.clone(), TextureFiltering::Nearest, TextureMode::Clamp);
+
+		let green = tex.channel_g();
+		assert_eq!(green.0.as_luma8().unwrap().get_pixel(1, 1)[0], 140);
+		let combined =
+			Texture::from_channels(&tex.channel_r(), &green, &tex.channel_b(), &tex.channel_a())
+				.unwrap();
+		assert_eq!(combined.0.raw_pixels(), img.raw_pixels());
+
+		let small = Texture::new(
+			DynamicImage::new_luma8(1, 2),
+			TextureFiltering::Nearest,
+			TextureMode::Clamp,
+		);
+		assert_eq!(
+			Texture::from_channels(&green, &green, &small, &green).err(),
+			Some(TextureError::DimensionMismatch {
+				expected: (2, 2),
+				found: (1, 2),
+			})
+		);
+	}
+
+	#[test]
+	fn computes_statistics() {
+		let checkerboard = |u: f64, v: f64| {
+			if ((u * 4.0) as u32 ^ (v * 4.0) as u32) & 1 == 0 {
+				Color::from_u32(0xFFFF0000)
+			} else {
+				Color::from_u32(0xFF000000)
+			}
+		};
+		let tex = Texture::from_fn(
+			4,
+			4,
+			checkerboard,
+			TextureFiltering::Nearest,
+			TextureMode::Clamp,
+		);
+		let avg = tex.average_color();
+		assert_relative_eq!(avg.red, 0.5, epsilon = 1e-9);
+		assert_relative_eq!(avg.green, 0.0, epsilon = 1e-9);
+		assert_relative_eq!(avg.alpha, 1.0, epsilon = 1e-9);
+
+		// Red has a luminance of 0.2126, black of 0
+		let hist = tex.histogram(5);
+		assert_eq!(hist.len(), 5);
+		assert_relative_eq!(hist[0], 0.5, epsilon = 1e-9);
+		assert_relative_eq!(hist[1], 0.5, epsilon = 1e-9);
+		assert_relative_eq!(hist.iter().sum::<f64>(), 1.0, epsilon = 1e-9);
+		assert!(tex.histogram(0).is_empty());
+
+		// Whatever the texture does outside of its bounds
+		let transparent = Texture::from_fn(
+			4,
+			4,
+			checkerboard,
+			TextureFiltering::Nearest,
+			TextureMode::Transparent,
+		);
+		assert_eq!(transparent.average_color(), avg);
+		assert_eq!(transparent.histogram(5), hist);
+	}
+
+	#[test]
+	fn loads_linear_hdr_textures() {
+		let path = std::env::temp_dir().join(format!("gargantua-{}.hdr", std::process::id()));
+		let pixels = vec![
+			Rgb([0.0, 0.0, 0.0]),
+			Rgb([0.5, 0.25, 0.125]),
+			Rgb([4.0, 2.0, 1.0]),
+			Rgb([1.0, 1.0, 1.0]),
+		];
+		HDREncoder::new(File::create(&path).unwrap())
+			.encode(&pixels, 2, 2)
+			.unwrap();
+		let tex = Texture::load_hdr(path.to_str().unwrap(), TextureFiltering::Nearest).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		// Texels brighter than white survive, decoding back to the linear values of the file
+		let linear = |col: Color| -> [f64; 4] { col.into() };
+		let [r, g, _, _] = linear(tex.uv(Vector2::new(0.25, 0.75)));
+		assert_relative_eq!(r, 4.0, epsilon = 1e-9);
+		assert_relative_eq!(g, 2.0, epsilon = 1e-9);
+		let col = tex.uv(Vector2::new(0.5, 0.5));
+		assert_relative_eq!(col.red, 1.0, epsilon = 1e-9);
+		let half = tex.uv(Vector2::new(0.75, 0.25));
+		assert_relative_eq!(linear(half.clone())[0], 0.5, epsilon = 1e-9);
+		// And are written out sRGB-encoded like LDR texels, 0.5 being 0.735 rather than 127/255
+		let Rgba([r, ..]) = half.into();
+		assert_eq!(r, 187);
+		assert_eq!(tex.0.to_rgba().get_pixel(1, 0)[0], 187);
+
+		let tex = Texture(tex.0, TextureFiltering::Bilinear, tex.2, tex.3, tex.4);
+		assert_relative_eq!(
+			linear(tex.uv(Vector2::new(0.25, 0.5)))[0],
+			2.5,
+			epsilon = 1e-9
+		);
+		let big = tex.resize(4, 4, TextureFiltering::Nearest);
+		assert_relative_eq!(linear(big.get_pixel(0, 2))[0], 4.0, epsilon = 1e-6);
+
+		assert!(Texture::load_hdr("does/not/exist.hdr", TextureFiltering::Nearest).is_err());
+	}
+
+	#[test]
+	fn resizes_back_and_forth() {
+		let smooth = |u: f64, v: f64| {
+			let wave = |t: f64| 0.5 + 0.4 * (t * 2.0 * std::f64::consts::PI).sin();
+			Color::with_system(wave(u), wave(v), wave(u + v), SYSTEM_SRGB)
+		};
+		let tex = Texture::from_fn(
+			64,
+			64,
+			smooth,
+			TextureFiltering::Nearest,
+			TextureMode::Clamp,
+		);
+		let up = tex.resize(128, 128, TextureFiltering::Bilinear);
+		assert_eq!(up.dimensions(), (128, 128));
+		let back = up.resize(64, 64, TextureFiltering::Bilinear);
+		assert_eq!(back.dimensions(), (64, 64));
+		for (a, b) in tex.0.raw_pixels().iter().zip(back.0.raw_pixels().iter()) {
+			assert!((i16::from(*a) - i16::from(*b)).abs() <= 3, "{} {}", a, b);
+		}
+
+		// Nearest filtering duplicates texels exactly, bicubic keeps the texture smooth
+		let quad = quad_texture(TextureMode::Clamp);
+		let big = quad.resize(4, 4, TextureFiltering::Nearest);
+		assert_eq!(big.get_pixel(1, 1), quad.get_pixel(0, 0));
+		assert_eq!(big.get_pixel(2, 3), quad.get_pixel(1, 1));
+		let cubic = tex.resize(32, 32, TextureFiltering::Bicubic);
+		assert_eq!(cubic.dimensions(), (32, 32));
+		assert!(cubic.average_color().red > 0.0);
+	}
+
+	#[test]
+	fn composites_textures() {
+		let solid = |col: u32, w: u32| {
+			Texture::from_fn(
+				w,
+				2,
+				|_, _| Color::from_u32(col),
+				TextureFiltering::Nearest,
+				TextureMode::Clamp,
+			)
+		};
+		let (red, blue) = (solid(0xFFFF0000, 2), solid(0xFF0000FF, 2));
+		let tex = composite_textures(&red, &blue).unwrap();
+		assert_eq!(tex.get_pixel(1, 1), Color::from_u32(0xFFFF0000));
+
+		// Half transparent red over blue mixes both in linear light
+		let tex = composite_textures(&solid(0x80FF0000, 2), &blue).unwrap();
+		let col = SYSTEM_SRGB.gamma_inv(&tex.get_pixel(0, 0));
+		assert_relative_eq!(col.red, 0.5, epsilon = 0.01);
+		assert_relative_eq!(col.blue, 0.5, epsilon = 0.01);
+		assert_relative_eq!(col.alpha, 1.0);
+
+		assert!(composite_textures(&red, &solid(0xFF0000FF, 3)).is_err());
+	}
+
+	#[test]
+	fn converts_to_grayscale() {
+		let gray = quad_texture(TextureMode::Clamp).to_grayscale();
+		let red = SYSTEM_SRGB.gamma_inv(&gray.get_pixel(0, 0));
+		assert_relative_eq!(red.red, 0.2126, epsilon = 0.005);
+		assert_eq!(red.red, red.green);
+		assert_eq!(red.red, red.blue);
+		let white = SYSTEM_SRGB.gamma_inv(&gray.get_pixel(1, 1));
+		assert_relative_eq!(white.red, 1.0, epsilon = 0.01);
+	}
+
+	#[test]
+	fn generates_star_fields() {
+		let stars = Texture::generate_star_field(64, 32, 0.01, 42);
+		assert_eq!(stars.dimensions(), (64, 32));
+		let same = Texture::generate_star_field(64, 32, 0.01, 42);
+		assert_eq!(stars.0.raw_pixels(), same.0.raw_pixels());
+		let other = Texture::generate_star_field(64, 32, 0.01, 43);
+		assert_ne!(stars.0.raw_pixels(), other.0.raw_pixels());
+
+		// About 20 stars, each lighting up a few pixels: most of the sky stays black
+		let lit = stars.0.to_rgba().pixels().filter(|px| px[0] > 0).count();
+		assert!(lit > 0 && lit < 64 * 32 / 4, "{}", lit);
+		let empty = Texture::generate_star_field(64, 32, 0.0, 42);
+		assert_eq!(empty.average_color().red, 0.0);
+
+		// The brightest stars of a dense field are brighter than white
+		let dense = Texture::generate_star_field(64, 32, 0.5, 42);
+		let brightest = (0..32)
+			.flat_map(|y| (0..64).map(move |x| (x, y)))
+			.map(|(x, y)| dense.get_pixel(x, y).red)
+			.fold(0.0, f64::max);
+		assert!(brightest > 1.0, "{}", brightest);
+	}
+
+	#[test]
+	fn load_reports_missing_file() {
+		let res = Texture::load(
+			"does/not/exist.png",
+			TextureFiltering::Nearest,
+			TextureMode::Clamp,
+		);
+		match res {
+			Err(TextureLoadError::IoError(_)) => (),
+			Err(err) => panic!("Unexpected error: {}", err),
+			Ok(_) => panic!("Loaded a texture from a missing file"),
+		}
+	}
+}
@@ -1,9 +1,15 @@
+use crate::raytrace::{Point, TexCoords, Vector};
+
 use color::Color;
 use image::{Pixel, Rgba};
-use nalgebra::Vector3;
+use nalgebra::{Unit, Vector3};
 
+use std::f64;
 
 use std::fmt::{Debug, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 pub struct DimIterator<T> {
 	width: T,
@@ -47,6 +53,13 @@ impl Iterator for DimIterator<u32> {
 	}
 }
 
+impl DimIterator<u32> {
+	/// Total number of pixels covered by the iterator, including the ones already yielded.
+	pub fn total_pixels(&self) -> u64 {
+		self.width as u64 * self.height as u64
+	}
+}
+
 impl<T: Display> Display for DimIterator<T> {
 	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
 		write!(
@@ -104,26 +117,94 @@ pub fn rgba_to_color(col: Rgba<u8>) -> Color {
 	);
 }
 
-pub fn color_to_rgba(col: &Color) -> Rgba<u8> {
-	Rgba::from_channels(
-		(col.red * 255f64) as u8,
-		(col.green * 255f64) as u8,
-		(col.blue * 255f64) as u8,
-		(col.alpha * 255f64) as u8,
-	)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SphericalCoord {
+	pub r: f64,
+	pub theta: f64,
+	pub phi: f64,
+}
+
+impl From<Vector3<f64>> for SphericalCoord {
+	fn from(val: Vector3<f64>) -> Self {
+		cartesian_to_spherical(&val)
+	}
+}
+
+impl From<Unit<Vector3<f64>>> for SphericalCoord {
+	fn from(val: Unit<Vector3<f64>>) -> Self {
+		cartesian_to_spherical(&val)
+	}
 }
 
-pub fn cartesian_to_spherical(vec: &Vector3<f64>) -> (f64, f64, f64) {
+impl From<SphericalCoord> for Unit<Vector3<f64>> {
+	fn from(val: SphericalCoord) -> Self {
+		let (sin_theta, cos_theta) = val.theta.sin_cos();
+		let (sin_phi, cos_phi) = val.phi.sin_cos();
+		Unit::new_normalize(Vector3::new(
+			sin_theta * cos_phi,
+			sin_theta * sin_phi,
+			cos_theta,
+		))
+	}
+}
+
+pub fn cartesian_to_spherical(vec: &Vector3<f64>) -> SphericalCoord {
 	let r = vec.dot(vec).sqrt();
 	let phi = vec.y.atan2(vec.x);
 	let theta = (vec.z / r).acos();
 
-	(r, theta, phi)
+	SphericalCoord { r, theta, phi }
+}
+
+/// Equirectangular mapping of a direction onto the background texture, shared by all renderers.
+pub fn background_uv_from_direction(dir: &Unit<Vector>) -> TexCoords {
+	let coord = cartesian_to_spherical(dir);
+	TexCoords::new(
+		coord.theta / f64::consts::PI,
+		0.5 * coord.phi / f64::consts::PI + 0.5,
+	)
+}
+
+/// Writes a trajectory as CSV, with `x,y,z,step` columns, to plot it or inspect it by hand.
+pub fn export_trajectory_csv(path: &Path, traj: &[Point]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	writeln!(file, "x,y,z,step")?;
+	for (step, pt) in traj.iter().enumerate() {
+		writeln!(file, "{},{},{},{}", pt.x, pt.y, pt.z, step)?;
+	}
+
+	file.flush()
+}
+
+/// Small seeded pseudo-random generator (splitmix64), for reproducible sampling without pulling
+/// in a dependency
+pub(crate) struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+
+	/// Uniformly distributed in [0, 1)
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::utils::DimIterator;
+	use crate::raytrace::{Point, TexCoords};
+	use crate::utils::{
+		background_uv_from_direction, cartesian_to_spherical, export_trajectory_csv, DimIterator,
+		SphericalCoord,
+	};
+	use approx::assert_relative_eq;
+	use nalgebra::{Unit, Vector3};
+	use std::f64;
 
 	#[test]
 	fn dimiterator_works() {
@@ -144,9 +225,55 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn dimiterator_total_pixels() {
+		let mut it = DimIterator::create(3, 2, 5, 5);
+		assert_eq!(it.total_pixels(), 6);
+		it.next();
+		it.next();
+		assert_eq!(it.total_pixels(), 6);
+	}
+
 	#[test]
 	fn dimiterator_empty() {
 		let mut it = DimIterator::new(0, 0);
 		assert_eq!(it.next(), None);
 	}
+
+	#[test]
+	fn spherical_coords_are_named() {
+		let coord = cartesian_to_spherical(&Vector3::new(0.0, 2.0, 0.0));
+		assert_relative_eq!(coord.r, 2.0);
+		assert_relative_eq!(coord.theta, f64::consts::FRAC_PI_2);
+		assert_relative_eq!(coord.phi, f64::consts::FRAC_PI_2);
+	}
+
+	#[test]
+	fn spherical_coords_round_trip() {
+		let dir = Unit::new_normalize(Vector3::new(1.0, -2.0, 0.5));
+		let back: Unit<Vector3<f64>> = SphericalCoord::from(dir).into();
+		assert_relative_eq!(back.into_inner(), dir.into_inner(), epsilon = 1e-12);
+	}
+
+	#[test]
+	fn background_uv_covers_unit_square() {
+		let up = background_uv_from_direction(&Vector3::z_axis());
+		let back_dir = Unit::new_normalize(Vector3::new(-1.0, 0.0, 0.0));
+		let back = background_uv_from_direction(&back_dir);
+		let side = background_uv_from_direction(&Vector3::y_axis());
+		assert_relative_eq!(up.x, 0.0);
+		assert_relative_eq!(back, TexCoords::new(0.5, 1.0));
+		assert_relative_eq!(side, TexCoords::new(0.5, 0.75));
+	}
+
+	#[test]
+	fn exports_trajectory_csv() {
+		let path = std::env::temp_dir().join("gargantua_trajectory_test.csv");
+		let traj = [Point::new(0.0, 1.0, 2.0), Point::new(0.5, -1.0, 3.25)];
+		export_trajectory_csv(&path, &traj).unwrap();
+		let csv = std::fs::read_to_string(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(csv, "x,y,z,step\n0,1,2,0\n0.5,-1,3.25,1\n");
+	}
 }